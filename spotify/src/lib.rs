@@ -1,13 +1,18 @@
 use async_trait::async_trait;
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use zeroflops::Error;
+use std::{
+    collections::HashMap,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use zeroflops::{upstream, Error};
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct Token {
     pub access_token: String,
     pub refresh_token: Option<String>,
+    /// Seconds until the access token expires
+    pub expires_in: Option<i64>,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -22,6 +27,11 @@ pub struct SpotifyCredentials {
     pub url: String,
     pub access_token: String,
     pub refresh_token: String,
+    /// Unix timestamp in seconds of when the access token expires
+    ///
+    /// Missing for credentials saved before access tokens were reused.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<i64>,
 }
 
 #[async_trait]
@@ -37,15 +47,16 @@ impl AuthClient for SpotifyClient {
     type Credentials = SpotifyCredentials;
 
     async fn get_credentials(&self, code: &str, origin: &str) -> Result<Self::Credentials, Error> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("system time should be after the epoch")
+            .as_secs() as i64;
         let client = Client::new();
         let token: Token = client
-            .post("https://accounts.spotify.com/api/token")
+            .post(format!("{}/api/token", upstream::spotify_accounts()))
             .header(
                 "Authorization",
-                &format!(
-                    "Basic {}",
-                    std::env::var("SPOTIFY_TOKEN").expect("SPOTIFY_TOKEN is missing")
-                ),
+                &format!("Basic {}", upstream::key("SPOTIFY_TOKEN")),
             )
             .form(&[
                 ("grant_type", "authorization_code"),
@@ -58,7 +69,7 @@ impl AuthClient for SpotifyClient {
             .await?;
 
         let spotify_user: User = client
-            .get("https://api.spotify.com/v1/me")
+            .get(format!("{}/v1/me", upstream::spotify_api()))
             .header("Authorization", format!("Bearer {}", token.access_token))
             .send()
             .await?
@@ -68,6 +79,7 @@ impl AuthClient for SpotifyClient {
             user_id: spotify_user.id.clone(),
             url: spotify_user.external_urls["spotify"].clone(),
             access_token: token.access_token,
+            expires_at: token.expires_in.map(|e| now + e),
             refresh_token: token.refresh_token.ok_or(Error::internal_error(
                 "Spotify did not return refresh_token",
             ))?,
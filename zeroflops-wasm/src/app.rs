@@ -1,14 +1,18 @@
 use crate::{
     base::Input,
     bootstrap::Modal,
+    dashboard::{self, DashboardView},
     dataframe::DataFrame,
     docs,
     edit::Edit,
     home::Home,
     integrations::spotify::SpotifyIntegration,
     list,
-    list::item::{ItemMode, ListItems},
-    plot::DataView,
+    list::{
+        history::ScoreHistory,
+        item::{ItemMode, ListItems},
+    },
+    plot::{ChartOptions, DataView},
     random::{RandomMatches, RandomRounds},
     search::Search,
     settings::Settings,
@@ -34,6 +38,7 @@ pub enum ListPage {
     RandomRounds,
     Tournament,
     RandomTournament,
+    History,
 }
 
 fn switch(
@@ -45,6 +50,8 @@ fn switch(
     let logged_in = user.is_some();
     match routes {
         Route::Home => html! { <Home {logged_in}/> },
+        Route::Dashboards => html! { <dashboard::Dashboards {logged_in}/> },
+        Route::Dashboard { id } => html! { <DashboardView {id} {user}/> },
         Route::Docs => docs::docs(),
         Route::ListsRoot => html! { <list::Lists {logged_in}/> },
         Route::Lists => {
@@ -226,6 +233,9 @@ impl Component for App {
                       <li class="nav-item">
                         <Link<Route> classes={search} to={Route::Search}>{"Query"}</Link<Route>>
                       </li>
+                      <li class="nav-item">
+                        <Link<Route> classes={search} to={Route::Dashboards}>{"Dashboards"}</Link<Route>>
+                      </li>
                       <li class="nav-item dropdown">
                         <a class={int_toggle_class} href="#" onclick={int_dropdown}>{"Integrations"}</a>
                         <ul class={int_menu_class}>
@@ -295,6 +305,7 @@ enum ListViewMsg {
     Success(Option<DataFrame>),
     Failed(String),
     Select,
+    Options(ChartOptions),
     Query,
 }
 
@@ -307,6 +318,7 @@ struct ListView {
     data: Option<DataFrame>,
     select_ref: NodeRef,
     view: DataView,
+    options: ChartOptions,
     query_ref: NodeRef,
     error: Option<String>,
 }
@@ -327,6 +339,7 @@ impl Component for ListView {
             data: None,
             select_ref: NodeRef::default(),
             view: DataView::Table,
+            options: ChartOptions::default(),
             query_ref: NodeRef::default(),
             error: None,
         }
@@ -350,16 +363,9 @@ impl Component for ListView {
             }
             ListViewMsg::Select => {
                 let view = self.select_ref.cast::<HtmlSelectElement>().unwrap().value();
-                self.view = match &*view {
-                    "Table" => DataView::Table,
-                    "Column Graph" => DataView::ColumnGraph,
-                    "Line Graph" => DataView::LineGraph,
-                    "Scatter Plot" => DataView::ScatterPlot,
-                    "Cumulative Line Graph" => DataView::CumLineGraph,
-                    "CSV" => DataView::Csv,
-                    _ => unreachable!(),
-                };
+                self.view = DataView::from_name(&view).unwrap();
             }
+            ListViewMsg::Options(options) => self.options = options,
             ListViewMsg::Query => {
                 let query = self.query_ref.cast::<HtmlSelectElement>().unwrap().value();
                 let list = ctx.props().list.clone();
@@ -372,7 +378,7 @@ impl Component for ListView {
             }
         }
         if let Some(data) = &self.data {
-            if let Err(e) = self.view.draw(data) {
+            if let Err(e) = self.view.draw("canvas", data, &self.options) {
                 self.error = Some(e.to_string());
             }
         }
@@ -391,12 +397,15 @@ impl Component for ListView {
                         <option>{"Line Graph"}</option>
                         <option>{"Scatter Plot"}</option>
                         <option>{"Cumulative Line Graph"}</option>
+                        <option>{"Histogram"}</option>
+                        <option>{"Box Plot"}</option>
+                        <option>{"Heatmap"}</option>
                         <option>{"CSV"}</option>
                     </select>
                 </div>
                 <Input input_ref={self.query_ref.clone()} onclick={query.clone()} error={self.error.clone()} disabled={matches!(ctx.props().list.mode, ListMode::View(_))}/>
                 if let Some(data) = &self.data {
-                    {self.view.render(data)}
+                    {self.view.render("canvas", data, &self.options, ctx.link().callback(ListViewMsg::Options))}
                 }
             </div>
         }
@@ -446,7 +455,8 @@ impl Component for ListComponent {
             | ListsRoute::View { id }
             | ListsRoute::Edit { id }
             | ListsRoute::Match { id }
-            | ListsRoute::Tournament { id } => id.clone(),
+            | ListsRoute::Tournament { id }
+            | ListsRoute::History { id } => id.clone(),
         };
         ctx.link().send_future(async move {
             if let Some(list) = crate::fetch_list(&id).await.unwrap() {
@@ -501,7 +511,8 @@ impl Component for ListComponent {
                 | ListsRoute::View { id }
                 | ListsRoute::Edit { id }
                 | ListsRoute::Match { id }
-                | ListsRoute::Tournament { id } => id.clone(),
+                | ListsRoute::Tournament { id }
+                | ListsRoute::History { id } => id.clone(),
             };
             ctx.link().send_future(async move {
                 ListMsg::Load(crate::fetch_list(&id).await.unwrap().unwrap())
@@ -527,6 +538,7 @@ impl Component for ListComponent {
             ListsRoute::View { .. } => ListPage::View,
             ListsRoute::List { .. } => ListPage::List,
             ListsRoute::Edit { .. } => ListPage::Edit,
+            ListsRoute::History { .. } => ListPage::History,
             ListsRoute::Tournament { .. } => {
                 if query.get("mode").map(String::as_str) == Some("random") {
                     ListPage::RandomTournament
@@ -542,12 +554,13 @@ impl Component for ListComponent {
                 }
             }
         };
-        let mut tabs = ["nav-link"; 3];
+        let mut tabs = ["nav-link"; 4];
         let active = "nav-link active";
         match view {
             ListPage::View => tabs[0] = active,
             ListPage::List => tabs[1] = active,
             ListPage::Edit => tabs[2] = active,
+            ListPage::History => tabs[3] = active,
             _ => {}
         }
         let component = if crate::user_list(list, &ctx.props().user) {
@@ -565,6 +578,7 @@ impl Component for ListComponent {
                     html! { <RandomTournamentLoader list={list.clone()}/> }
                 }
                 ListPage::Tournament => html! { <TournamentLoader list={list.clone()}/> },
+                ListPage::History => html! { <ScoreHistory list={list.clone()}/> },
             }
         } else {
             match view {
@@ -572,6 +586,7 @@ impl Component for ListComponent {
                 ListPage::List => {
                     html! { <ListItems user={Rc::clone(&ctx.props().user)} list={list.clone()} mode={self.mode.clone()}/> }
                 }
+                ListPage::History => html! { <ScoreHistory list={list.clone()}/> },
                 // TODO: move this up?
                 _ => crate::not_found(),
             }
@@ -623,6 +638,11 @@ impl Component for ListComponent {
                   <li class="nav-item">
                     <Link<ListsRoute> classes={tabs[1]} to={ListsRoute::List{id: list.id.clone()}}>{"Items"}</Link<ListsRoute>>
                   </li>
+                  if !matches!(list.mode, ListMode::View(_)) {
+                    <li class="nav-item">
+                      <Link<ListsRoute> classes={tabs[3]} to={ListsRoute::History{id: list.id.clone()}}>{"History"}</Link<ListsRoute>>
+                    </li>
+                  }
                   if user {
                     {dropdown_html}
                     <li class="nav-item">
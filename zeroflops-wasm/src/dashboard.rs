@@ -0,0 +1,467 @@
+use crate::{
+    dataframe::DataFrame,
+    plot::{ChartOptions, DataView},
+    Route, UserProps,
+};
+use std::rc::Rc;
+use web_sys::{HtmlInputElement, HtmlSelectElement};
+use yew::{html, Component, Context, Html, NodeRef, Properties};
+use yew_router::{prelude::Link, scope_ext::RouterScopeExt};
+use zeroflops::{Dashboard, Panel, User};
+
+pub enum DashboardsMsg {
+    Load(Vec<Dashboard>),
+    Create,
+}
+
+pub struct Dashboards {
+    dashboards: Vec<Dashboard>,
+}
+
+impl Component for Dashboards {
+    type Message = DashboardsMsg;
+    type Properties = UserProps;
+
+    fn create(ctx: &Context<Self>) -> Self {
+        ctx.link().send_future(async move {
+            let dashboards = crate::fetch_dashboards().await.unwrap();
+            DashboardsMsg::Load(dashboards)
+        });
+        Dashboards {
+            dashboards: Vec::new(),
+        }
+    }
+
+    fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
+        match msg {
+            DashboardsMsg::Load(dashboards) => {
+                self.dashboards = dashboards;
+                true
+            }
+            DashboardsMsg::Create => {
+                let navigator = ctx.link().navigator().unwrap();
+                ctx.link().send_future_batch(async move {
+                    let dashboard = crate::create_dashboard().await.unwrap();
+                    navigator.push(&Route::Dashboard { id: dashboard.id });
+                    None
+                });
+                false
+            }
+        }
+    }
+
+    fn view(&self, ctx: &Context<Self>) -> Html {
+        let dashboard_html = self.dashboards.iter().map(|d| {
+            html! {
+                <div class="col-12 col-md-6 mb-4">
+                    <div class="card">
+                        <div class="card-body">
+                            <Link<Route> to={Route::Dashboard{id: d.id.clone()}}>{&d.name}</Link<Route>>
+                        </div>
+                    </div>
+                </div>
+            }
+        });
+        let disabled = !ctx.props().logged_in;
+        let create = ctx.link().callback(|_| DashboardsMsg::Create);
+        crate::nav_content(
+            html! {
+              <ul class="navbar-nav me-auto">
+                <li class="navbar-brand">{"All Dashboards"}</li>
+              </ul>
+            },
+            html! {
+              <div>
+                <div class="row mt-3">
+                  {for dashboard_html}
+                </div>
+                <button type="button" class="btn btn-primary" onclick={create} {disabled}>{"Create Dashboard"}</button>
+              </div>
+            },
+        )
+    }
+}
+
+enum DashboardState {
+    Fetching,
+    Success(Dashboard),
+    NotFound,
+}
+
+pub enum DashboardMsg {
+    Load(Option<Dashboard>),
+    Refresh,
+    Edit,
+    AddPanel,
+    DeletePanel(usize),
+    Save,
+    Delete,
+}
+
+#[derive(PartialEq, Properties)]
+pub struct DashboardProps {
+    pub id: String,
+    pub user: Rc<Option<User>>,
+}
+
+pub struct DashboardView {
+    state: DashboardState,
+    // Incremented to refresh every panel
+    refresh: u32,
+    editing: bool,
+    counter: i32,
+    panels: Vec<(i32, Panel, NodeRef, NodeRef, NodeRef)>,
+    name_ref: NodeRef,
+    public_ref: NodeRef,
+}
+
+impl Component for DashboardView {
+    type Message = DashboardMsg;
+    type Properties = DashboardProps;
+
+    fn create(ctx: &Context<Self>) -> Self {
+        let id = ctx.props().id.clone();
+        ctx.link().send_future(async move {
+            DashboardMsg::Load(crate::fetch_dashboard(&id).await.unwrap())
+        });
+        DashboardView {
+            state: DashboardState::Fetching,
+            refresh: 0,
+            editing: false,
+            counter: 0,
+            panels: Vec::new(),
+            name_ref: NodeRef::default(),
+            public_ref: NodeRef::default(),
+        }
+    }
+
+    fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
+        match msg {
+            DashboardMsg::Load(Some(dashboard)) => {
+                self.state = DashboardState::Success(dashboard);
+            }
+            DashboardMsg::Load(None) => {
+                self.state = DashboardState::NotFound;
+            }
+            DashboardMsg::Refresh => {
+                self.refresh += 1;
+            }
+            DashboardMsg::Edit => {
+                let DashboardState::Success(dashboard) = &self.state else {
+                    return false;
+                };
+                self.editing = !self.editing;
+                self.panels = dashboard
+                    .panels
+                    .iter()
+                    .enumerate()
+                    .map(|(i, p)| {
+                        (
+                            i as i32,
+                            p.clone(),
+                            NodeRef::default(),
+                            NodeRef::default(),
+                            NodeRef::default(),
+                        )
+                    })
+                    .collect();
+                self.counter = self.panels.len() as i32;
+            }
+            DashboardMsg::AddPanel => {
+                self.panels.push((
+                    self.counter,
+                    Panel {
+                        title: String::from("New Panel"),
+                        query: String::from("SELECT name, user_score FROM item"),
+                        view: String::from("Table"),
+                    },
+                    NodeRef::default(),
+                    NodeRef::default(),
+                    NodeRef::default(),
+                ));
+                self.counter += 1;
+            }
+            DashboardMsg::DeletePanel(i) => {
+                self.panels.remove(i);
+            }
+            DashboardMsg::Save => {
+                let DashboardState::Success(dashboard) = &self.state else {
+                    return false;
+                };
+                let mut dashboard = dashboard.clone();
+                dashboard.name = self.name_ref.cast::<HtmlInputElement>().unwrap().value();
+                dashboard.public = self
+                    .public_ref
+                    .cast::<HtmlInputElement>()
+                    .unwrap()
+                    .checked();
+                dashboard.panels = self
+                    .panels
+                    .iter()
+                    .map(|(_, _, title, query, view)| Panel {
+                        title: title.cast::<HtmlInputElement>().unwrap().value(),
+                        query: query.cast::<HtmlInputElement>().unwrap().value(),
+                        view: view.cast::<HtmlSelectElement>().unwrap().value(),
+                    })
+                    .collect();
+                self.editing = false;
+                // Panels are only updated after saving since they query the stored dashboard
+                ctx.link().send_future(async move {
+                    crate::update_dashboard(&dashboard).await.unwrap();
+                    DashboardMsg::Load(Some(dashboard))
+                });
+            }
+            DashboardMsg::Delete => {
+                let id = ctx.props().id.clone();
+                if crate::window()
+                    .confirm_with_message(&format!("Delete {id}?"))
+                    .unwrap()
+                {
+                    let navigator = ctx.link().navigator().unwrap();
+                    ctx.link().send_future_batch(async move {
+                        crate::delete_dashboard(&id).await.unwrap();
+                        navigator.push(&Route::Dashboards);
+                        None
+                    });
+                }
+                return false;
+            }
+        }
+        true
+    }
+
+    fn view(&self, ctx: &Context<Self>) -> Html {
+        let dashboard = match &self.state {
+            DashboardState::Fetching => return html! {},
+            DashboardState::NotFound => return crate::not_found(),
+            DashboardState::Success(dashboard) => dashboard,
+        };
+        let owner = crate::user_dashboard(dashboard, &ctx.props().user);
+        let refresh = ctx.link().callback(|_| DashboardMsg::Refresh);
+        let edit = ctx.link().callback(|_| DashboardMsg::Edit);
+        let nav = html! {
+            <>
+                <ul class="navbar-nav me-auto">
+                    <li class="navbar-brand">{&dashboard.name}</li>
+                    if dashboard.public {
+                        <li class="nav-item align-self-center">
+                            <span class="badge text-bg-secondary">{"Public"}</span>
+                        </li>
+                    }
+                </ul>
+                <div class="d-flex gap-2">
+                    <button type="button" class="btn btn-outline-secondary" onclick={refresh} disabled={self.editing}>{"Refresh"}</button>
+                    if owner {
+                        <button type="button" class="btn btn-outline-secondary" onclick={edit}>
+                            {if self.editing { "Cancel" } else { "Edit" }}
+                        </button>
+                    }
+                </div>
+            </>
+        };
+        let content = if self.editing {
+            self.edit_view(ctx, dashboard)
+        } else if dashboard.panels.is_empty() {
+            html! {
+                <p>{"This dashboard has no panels yet."}</p>
+            }
+        } else {
+            let panels = dashboard.panels.iter().enumerate().map(|(index, panel)| {
+                html! {
+                    <div class="col-12 col-xl-6 mb-4">
+                        <PanelView dashboard_id={dashboard.id.clone()} {index} panel={panel.clone()} refresh={self.refresh}/>
+                    </div>
+                }
+            });
+            html! {
+                <div class="row">
+                    {for panels}
+                </div>
+            }
+        };
+        crate::nav_content(nav, content)
+    }
+}
+
+impl DashboardView {
+    fn edit_view(&self, ctx: &Context<Self>, dashboard: &Dashboard) -> Html {
+        let panel_html = self
+            .panels
+            .iter()
+            .enumerate()
+            .map(|(i, (key, panel, title, query, view))| {
+                let onclick = ctx.link().callback(move |_| DashboardMsg::DeletePanel(i));
+                html! {
+                    <div class="row mb-1" key={*key}>
+                        <input class="col-3" ref={title} value={panel.title.clone()} placeholder="Title"/>
+                        <input class="col-5 col-md-6" ref={query} value={panel.query.clone()} placeholder="Query"/>
+                        <div class="col-2">
+                            <select ref={view} class="form-select">
+                                {for DataView::NAMES.iter().map(|name| html! {
+                                    <option selected={panel.view == *name}>{*name}</option>
+                                })}
+                            </select>
+                        </div>
+                        <div class="col-auto">
+                            <button type="button" class="btn btn-danger" {onclick}>{"Delete"}</button>
+                        </div>
+                    </div>
+                }
+            });
+        let add_panel = ctx.link().callback(|_| DashboardMsg::AddPanel);
+        let save = ctx.link().callback(|_| DashboardMsg::Save);
+        let delete = ctx.link().callback(|_| DashboardMsg::Delete);
+        html! {
+            <div>
+                <h4>{"Dashboard Settings"}</h4>
+                <form class="mb-4" style="max-width: 800px">
+                    <div class="form-floating mb-2">
+                        <input type="text" class="form-control" id="name" ref={&self.name_ref} value={dashboard.name.clone()} placeholder=""/>
+                        <label for="name">{"Dashboard name"}</label>
+                    </div>
+                    <div class="form-check">
+                        <label class="form-check-label" for="public">{"Public"}</label>
+                        <input ref={&self.public_ref} class="form-check-input" type="checkbox" id="public" checked={dashboard.public}/>
+                    </div>
+                </form>
+                <h4>{"Panels"}</h4>
+                <div class="mb-3">
+                    {for panel_html}
+                </div>
+                <div class="d-flex gap-3">
+                    <button type="button" class="btn btn-primary" onclick={add_panel}>{"Add panel"}</button>
+                </div>
+                <hr/>
+                <button type="button" class="btn btn-success mb-3" onclick={save}>{"Save all settings"}</button>
+                <div class="d-flex gap-3">
+                    <button type="button" class="btn btn-danger" onclick={delete}>{"Delete"}</button>
+                </div>
+            </div>
+        }
+    }
+}
+
+pub enum PanelMsg {
+    Load(Option<DataFrame>),
+    Failed(String),
+    Refresh,
+    Options(ChartOptions),
+}
+
+#[derive(PartialEq, Properties)]
+pub struct PanelProps {
+    pub dashboard_id: String,
+    pub index: usize,
+    pub panel: Panel,
+    pub refresh: u32,
+}
+
+pub struct PanelView {
+    data: Option<DataFrame>,
+    error: Option<String>,
+    options: ChartOptions,
+    // The canvas only exists after rendering so drawing is deferred to rendered
+    draw: bool,
+}
+
+impl PanelView {
+    fn fetch(ctx: &Context<Self>) {
+        let id = ctx.props().dashboard_id.clone();
+        let index = ctx.props().index;
+        ctx.link().send_future(async move {
+            match crate::query_panel(&id, index).await {
+                Ok(data) => PanelMsg::Load(data),
+                Err(e) => PanelMsg::Failed(e.as_string().unwrap_or_default()),
+            }
+        });
+    }
+
+    fn canvas(ctx: &Context<Self>) -> String {
+        format!("panel-{}", ctx.props().index)
+    }
+
+    fn view_type(ctx: &Context<Self>) -> DataView {
+        DataView::from_name(&ctx.props().panel.view).unwrap_or(DataView::Table)
+    }
+}
+
+impl Component for PanelView {
+    type Message = PanelMsg;
+    type Properties = PanelProps;
+
+    fn create(ctx: &Context<Self>) -> Self {
+        Self::fetch(ctx);
+        PanelView {
+            data: None,
+            error: None,
+            options: ChartOptions::default(),
+            draw: false,
+        }
+    }
+
+    fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
+        match msg {
+            PanelMsg::Load(data) => {
+                self.data = data.map(|mut data| {
+                    data.drop_in_place("id");
+                    data
+                });
+                self.error = None;
+                self.draw = true;
+            }
+            PanelMsg::Failed(e) => {
+                self.error = Some(e);
+            }
+            PanelMsg::Refresh => {
+                Self::fetch(ctx);
+                return false;
+            }
+            PanelMsg::Options(options) => {
+                self.options = options;
+                self.draw = true;
+            }
+        }
+        true
+    }
+
+    fn changed(&mut self, ctx: &Context<Self>, old_props: &Self::Properties) -> bool {
+        if ctx.props() != old_props {
+            Self::fetch(ctx);
+        }
+        true
+    }
+
+    fn view(&self, ctx: &Context<Self>) -> Html {
+        let refresh = ctx.link().callback(|_| PanelMsg::Refresh);
+        html! {
+            <div class="card">
+                <div class="card-header d-flex justify-content-between align-items-center">
+                    <span>{&ctx.props().panel.title}</span>
+                    <button type="button" class="btn btn-sm btn-outline-secondary" onclick={refresh}>{"Refresh"}</button>
+                </div>
+                <div class="card-body overflow-auto">
+                    if let Some(error) = &self.error {
+                        <div class="alert alert-danger" role="alert">{error}</div>
+                    }
+                    if let Some(data) = &self.data {
+                        {Self::view_type(ctx).render(&Self::canvas(ctx), data, &self.options, ctx.link().callback(PanelMsg::Options))}
+                    } else if self.error.is_none() {
+                        <p class="text-muted mb-0">{"No results"}</p>
+                    }
+                </div>
+            </div>
+        }
+    }
+
+    fn rendered(&mut self, ctx: &Context<Self>, _first_render: bool) {
+        if !self.draw {
+            return;
+        }
+        self.draw = false;
+        if let Some(data) = &self.data {
+            if let Err(e) = Self::view_type(ctx).draw(&Self::canvas(ctx), data, &self.options) {
+                ctx.link().send_message(PanelMsg::Failed(e.to_string()));
+            }
+        }
+    }
+}
@@ -1,21 +1,46 @@
 use std::{
+    collections::HashMap,
     io::{Read, Seek},
     sync::Arc,
 };
 
 use arrow::{
-    array::{ArrayRef, BooleanArray},
-    compute,
-    datatypes::{Fields, Schema},
+    array::{Array, ArrayRef, AsArray, BooleanArray, Float64Array, Int64Array, UInt32Array},
+    compute::{self, SortOptions},
+    datatypes::{DataType, Field, Fields, Float64Type, Schema},
+    error::ArrowError,
     ipc::reader::FileReader,
+    row::{RowConverter, SortField},
 };
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct DataFrame {
     pub schema: Arc<Schema>,
     pub arrays: Vec<ArrayRef>,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Aggregate {
+    Count,
+    Sum,
+    Mean,
+    Min,
+    Max,
+}
+
+impl Aggregate {
+    fn column_name(&self, column: &str) -> String {
+        let name = match self {
+            Aggregate::Count => "COUNT",
+            Aggregate::Sum => "SUM",
+            Aggregate::Mean => "AVG",
+            Aggregate::Min => "MIN",
+            Aggregate::Max => "MAX",
+        };
+        format!("{name}({column})")
+    }
+}
+
 impl DataFrame {
     pub fn from<T: Read + Seek>(buf: &mut T) -> DataFrame {
         let mut reader = FileReader::try_new(buf, None).unwrap();
@@ -25,22 +50,35 @@ impl DataFrame {
         }
     }
 
+    pub fn num_rows(&self) -> usize {
+        self.arrays.first().map_or(0, |a| a.len())
+    }
+
     pub fn column(&self, s: &str) -> Option<&ArrayRef> {
-        if let Some(i) = self.schema.fields.iter().position(|f| f.name() == s) {
+        if let Some(i) = self.position(s) {
             self.arrays.get(i)
         } else {
             None
         }
     }
 
+    fn position(&self, s: &str) -> Option<usize> {
+        self.schema.fields.iter().position(|f| f.name() == s)
+    }
+
+    fn try_column(&self, s: &str) -> Result<&ArrayRef, ArrowError> {
+        self.column(s)
+            .ok_or_else(|| ArrowError::InvalidArgumentError(format!("column {s} does not exist")))
+    }
+
     pub fn drop_in_place(&mut self, s: &str) -> Option<ArrayRef> {
-        if let Some(i) = self.schema.fields.iter().position(|f| f.name() == s) {
+        if let Some(i) = self.position(s) {
             self.schema = Arc::new(Schema::new(Fields::from_iter(
                 self.schema
                     .fields
                     .into_iter()
                     .enumerate()
-                    .filter_map(|(i1, f)| if i == i1 { Some(Arc::clone(f)) } else { None }),
+                    .filter_map(|(i1, f)| if i != i1 { Some(Arc::clone(f)) } else { None }),
             )));
             Some(self.arrays.remove(i))
         } else {
@@ -48,12 +86,186 @@ impl DataFrame {
         }
     }
 
-    pub fn remove(&mut self, mut index: Vec<bool>) {
-        index.sort();
-        index.reverse();
-        let predicate = BooleanArray::from(index);
-        for a in &mut self.arrays {
-            *a = compute::filter(a, &predicate).unwrap();
+    /// Keep the rows where index is true
+    pub fn remove(&mut self, index: Vec<bool>) {
+        self.filter(&BooleanArray::from(index)).unwrap();
+    }
+
+    /// Keep the rows where the predicate is true
+    pub fn filter(&mut self, predicate: &BooleanArray) -> Result<(), ArrowError> {
+        self.arrays = self
+            .arrays
+            .iter()
+            .map(|a| compute::filter(a, predicate))
+            .collect::<Result<_, _>>()?;
+        Ok(())
+    }
+
+    /// Keep the first n rows
+    pub fn head(&mut self, n: usize) {
+        self.arrays = self
+            .arrays
+            .iter()
+            .map(|a| a.slice(0, n.min(a.len())))
+            .collect();
+    }
+
+    /// Stable sort by a single column with nulls last
+    ///
+    /// The row format is used instead of sort_to_indices so that list columns like artists can
+    /// also be sorted.
+    pub fn sort(&mut self, s: &str, descending: bool) -> Result<(), ArrowError> {
+        let column = self.try_column(s)?;
+        let converter = RowConverter::new(vec![SortField::new_with_options(
+            column.data_type().clone(),
+            SortOptions {
+                descending,
+                nulls_first: false,
+            },
+        )])?;
+        let rows = converter.convert_columns(&[Arc::clone(column)])?;
+        let mut indices: Vec<u32> = (0..rows.num_rows() as u32).collect();
+        indices.sort_by(|&a, &b| rows.row(a as usize).cmp(&rows.row(b as usize)));
+        self.take(&UInt32Array::from(indices))
+    }
+
+    fn take(&mut self, indices: &UInt32Array) -> Result<(), ArrowError> {
+        self.arrays = self
+            .arrays
+            .iter()
+            .map(|a| compute::take(a, indices, None))
+            .collect::<Result<_, _>>()?;
+        Ok(())
+    }
+
+    /// Group rows by the key columns and aggregate the remaining columns
+    ///
+    /// Groups are returned in order of first appearance and aggregate columns are named after
+    /// the equivalent SQL expression, e.g. AVG(user_score).
+    pub fn group_by(
+        &self,
+        keys: &[&str],
+        aggregates: &[(&str, Aggregate)],
+    ) -> Result<DataFrame, ArrowError> {
+        let key_columns = keys
+            .iter()
+            .map(|k| self.try_column(k).cloned())
+            .collect::<Result<Vec<_>, _>>()?;
+        let converter = RowConverter::new(
+            key_columns
+                .iter()
+                .map(|c| SortField::new(c.data_type().clone()))
+                .collect(),
+        )?;
+        let rows = converter.convert_columns(&key_columns)?;
+        let mut groups = HashMap::new();
+        let mut group_indices: Vec<Vec<u32>> = Vec::new();
+        for (i, row) in rows.iter().enumerate() {
+            let group = *groups.entry(row.owned()).or_insert_with(|| {
+                group_indices.push(Vec::new());
+                group_indices.len() - 1
+            });
+            group_indices[group].push(i as u32);
+        }
+
+        let first = UInt32Array::from_iter_values(group_indices.iter().map(|g| g[0]));
+        let mut fields: Vec<Field> = keys
+            .iter()
+            .map(|k| self.schema.field_with_name(k).cloned())
+            .collect::<Result<_, _>>()?;
+        let mut arrays = key_columns
+            .iter()
+            .map(|c| compute::take(c, &first, None))
+            .collect::<Result<Vec<_>, _>>()?;
+        for (column, aggregate) in aggregates {
+            let name = aggregate.column_name(column);
+            let values = self.try_column(column)?;
+            let array: ArrayRef = if let Aggregate::Count = aggregate {
+                fields.push(Field::new(name, DataType::Int64, false));
+                Arc::new(Int64Array::from_iter_values(group_indices.iter().map(
+                    |g| g.iter().filter(|&&i| values.is_valid(i as usize)).count() as i64,
+                )))
+            } else {
+                fields.push(Field::new(name, DataType::Float64, true));
+                let values = compute::cast(values, &DataType::Float64)?;
+                let values = values.as_primitive::<Float64Type>();
+                Arc::new(
+                    group_indices
+                        .iter()
+                        .map(|g| {
+                            let group = compute::take(values, &UInt32Array::from(g.clone()), None)?;
+                            let group = group.as_primitive::<Float64Type>();
+                            Ok(match aggregate {
+                                Aggregate::Count => unreachable!(),
+                                Aggregate::Sum => compute::sum(group),
+                                Aggregate::Mean => compute::sum(group)
+                                    .map(|sum| sum / (group.len() - group.null_count()) as f64),
+                                Aggregate::Min => compute::min(group),
+                                Aggregate::Max => compute::max(group),
+                            })
+                        })
+                        .collect::<Result<Float64Array, ArrowError>>()?,
+                )
+            };
+            arrays.push(array);
         }
+        Ok(DataFrame {
+            schema: Arc::new(Schema::new(fields)),
+            arrays,
+        })
+    }
+
+    /// Add a column or replace an existing column with the same name
+    pub fn with_column(&mut self, s: &str, array: ArrayRef) -> Result<(), ArrowError> {
+        if !self.arrays.is_empty() && array.len() != self.num_rows() {
+            return Err(ArrowError::InvalidArgumentError(format!(
+                "column {s} has {} rows but expected {}",
+                array.len(),
+                self.num_rows()
+            )));
+        }
+        let field = Arc::new(Field::new(s, array.data_type().clone(), true));
+        let mut fields: Vec<_> = self.schema.fields.iter().cloned().collect();
+        if let Some(i) = self.position(s) {
+            fields[i] = field;
+            self.arrays[i] = array;
+        } else {
+            fields.push(field);
+            self.arrays.push(array);
+        }
+        self.schema = Arc::new(Schema::new(fields));
+        Ok(())
+    }
+
+    /// Add a numeric column that is computed row by row from other columns
+    ///
+    /// The inputs are cast to f64 and are null if the input value is null or can't be cast.
+    pub fn with_computed_column<F>(
+        &mut self,
+        s: &str,
+        inputs: &[&str],
+        f: F,
+    ) -> Result<(), ArrowError>
+    where
+        F: Fn(&[Option<f64>]) -> Option<f64>,
+    {
+        let inputs = inputs
+            .iter()
+            .map(|i| compute::cast(self.try_column(i)?, &DataType::Float64))
+            .collect::<Result<Vec<_>, _>>()?;
+        let inputs: Vec<_> = inputs
+            .iter()
+            .map(|i| i.as_primitive::<Float64Type>())
+            .collect();
+        let mut row = vec![None; inputs.len()];
+        let array: Float64Array = (0..self.num_rows())
+            .map(|i| {
+                for (value, input) in row.iter_mut().zip(&inputs) {
+                    *value = input.is_valid(i).then(|| input.value(i));
+                }
+                f(&row)
+            })
+            .collect();
+        self.with_column(s, Arc::new(array))
     }
 }
@@ -18,13 +18,37 @@ pub fn docs() -> Html {
             <p>{"Query using SQL to gain insights about your data by calculating statistics and filtering items.
                 You can also view your data using different types of charts.
                 The first column returned by the SQL query is used as the x-axis and the second column is used as the y."}</p>
+            <h5>{"Query plays"}</h5>
+            <p>{"After importing your Spotify streaming history, the play table has a row for every play with the item_id, timestamp, ms_played and skipped columns.
+                For example, SELECT item.name, COUNT(*), SUM(play.ms_played) / 60000 FROM play JOIN item ON play.item_id = item.id GROUP BY item.id shows the play counts and minutes listened of your songs."}</p>
             <h5>{"Manage items"}</h5>
             <p>{"You can mark items as hidden for queries to filter on.
-                You can also delete items to remove it from all lists and queries."}</p>
+                You can also delete items to remove it from all lists and queries.
+                Spotify tracks with the same ISRC are imported as a single item, and other duplicate items can be merged to combine their scores and plays."}</p>
             <h3>{"List features"}</h3>
             <h5>{"Create lists of items using data sources"}</h5>
             <p>{"Add items to a list by adding a data source that resolves to items.
-                Deleting a data source will remove the items from the list but the data for items will still be preserved."}</p>
+                Deleting a data source will remove the items from the list but the data for items will still be preserved.
+                Use Refresh on the list page to fetch the data sources again. Spotify playlists are only fetched if they changed and items keep their scores. Lists can also be refreshed daily or weekly in the background from the edit page.
+                Spotify artist URLs add every track from the artist's albums and singles, and adding ?include_groups=album,single,compilation to the URL picks which releases are used.
+                After logging in with Spotify, the Spotify sources \"Liked Songs\" and \"Top Tracks\" with short_term, medium_term or long_term use your own library.
+                Setlist sources also take a search like {\"artist_mbid\": \"a74b1b7f-71a5-4011-9441-d0b5e4122711\", \"tour\": \"A Moon Shaped Pool\", \"start_year\": 2016, \"end_year\": 2018} to add every song played with its play_count, first_played, last_played and cover metadata.
+                Setlist songs are matched to Spotify tracks by searching, and the edit page shows which songs were ambiguous or couldn't be found.
+                Pin a Spotify track to a song to use it whenever that song of the artist is in a setlist.
+                List Items sources add the items of another list, and refreshing a list also refreshes the lists it includes.
+                They also take a set operation like {\"operation\": \"difference\", \"ids\": [\"list ID\", \"other list ID\"]} with union, intersection or difference to combine lists.
+                Lists can't include themselves and can only be nested 5 levels deep."}</p>
+            <h5>{"Upload files"}</h5>
+            <p>{"Upload a CSV, JSON or NDJSON file to add custom items to a list.
+                Choose the columns to use for item IDs and names and the remaining columns can be used in queries.
+                Upload the file again to update the items without losing their scores.
+                Letterboxd and Goodreads CSV exports can be uploaded too and their star ratings are converted to item ratings from 1 to 10."}</p>
+            <h5>{"Import Last.fm scrobbles"}</h5>
+            <p>{"Import a Last.fm scrobble export to add the scrobbled tracks to a list with their play counts.
+                Tracks that can't be found on Spotify are listed after the import so you can add them some other way."}</p>
+            <h5>{"Import college football teams"}</h5>
+            <p>{"The cfb command adds teams from CollegeFootballData team and game CSV or JSON files to a list with their conference and the record of the latest season.
+                With --seed-matches the game results are also played as matches so your rankings can be compared with the results on the field."}</p>
             <h5>{"Query items in a list"}</h5>
             <p>{"Queries under a list page are similar to queries in the top-level page except they will also be filtered against items in the list."}</p>
             <h5>{"Integrate with external systems"}</h5>
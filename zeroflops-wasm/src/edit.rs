@@ -1,7 +1,10 @@
 use web_sys::{HtmlInputElement, HtmlSelectElement};
 use yew::{html, Component, Context, Html, NodeRef, Properties};
 use yew_router::scope_ext::RouterScopeExt;
-use zeroflops::{Id, List, ListMode, Source, SourceType, Spotify};
+use zeroflops::{
+    Id, Imdb, List, ListMode, RefreshInterval, ScrobbleImport, SetlistOverride, SetlistReport,
+    Source, SourceType, Spotify,
+};
 
 use crate::Route;
 
@@ -10,6 +13,15 @@ pub enum Msg {
     AddSource,
     DeleteSource(usize),
     Save,
+    Upload,
+    Uploaded(Result<(), String>),
+    ImportScrobbles,
+    ImportedScrobbles(Result<ScrobbleImport, String>),
+    CheckSetlists,
+    CheckedSetlists(Result<Vec<SetlistReport>, String>),
+    // Report and song index
+    PinSong(usize, usize),
+    PinnedSong(Result<(), String>),
     Delete,
     DeleteAll,
 }
@@ -30,6 +42,20 @@ pub struct Edit {
     query_ref: NodeRef,
     favorite_ref: NodeRef,
     public_ref: NodeRef,
+    refresh_ref: NodeRef,
+    file_ref: NodeRef,
+    file_type_ref: NodeRef,
+    id_column_ref: NodeRef,
+    name_column_ref: NodeRef,
+    iframe_column_ref: NodeRef,
+    upload_error: Option<String>,
+    scrobbles_ref: NodeRef,
+    importing: bool,
+    scrobble_import: Option<Result<ScrobbleImport, String>>,
+    checking_setlists: bool,
+    setlist_reports: Option<Result<Vec<SetlistReport>, String>>,
+    // Track inputs of the ambiguous and unmatched songs of each report
+    pin_refs: Vec<Vec<(String, NodeRef)>>,
 }
 
 impl Component for Edit {
@@ -60,6 +86,19 @@ impl Component for Edit {
             query_ref: NodeRef::default(),
             favorite_ref: NodeRef::default(),
             public_ref: NodeRef::default(),
+            refresh_ref: NodeRef::default(),
+            file_ref: NodeRef::default(),
+            file_type_ref: NodeRef::default(),
+            id_column_ref: NodeRef::default(),
+            name_column_ref: NodeRef::default(),
+            iframe_column_ref: NodeRef::default(),
+            upload_error: None,
+            scrobbles_ref: NodeRef::default(),
+            importing: false,
+            scrobble_import: None,
+            checking_setlists: false,
+            setlist_reports: None,
+            pin_refs: Vec::new(),
         }
     }
 
@@ -105,46 +144,118 @@ impl Component for Edit {
                     .cast::<HtmlInputElement>()
                     .unwrap()
                     .checked();
+                self.list.refresh = match &*self
+                    .refresh_ref
+                    .cast::<HtmlSelectElement>()
+                    .unwrap()
+                    .value()
+                {
+                    "daily" => Some(RefreshInterval::Daily),
+                    "weekly" => Some(RefreshInterval::Weekly),
+                    _ => None,
+                };
                 self.list.sources.clear();
+                let ids = |id: &str| {
+                    id.split(',')
+                        .map(|i| i.trim().to_owned())
+                        .filter(|i| !i.is_empty())
+                        .collect()
+                };
                 for (_, source, id, _) in &self.sources {
                     let source = source.cast::<HtmlSelectElement>().unwrap().value();
                     let id = id.cast::<HtmlInputElement>().unwrap().value();
                     match &*source {
                         "Spotify" => {
                             if let Some(source) = crate::parse_spotify_source(id) {
-                                self.list.sources.push(Source {
-                                    source_type: SourceType::Spotify(source),
-                                    name: String::new(),
-                                });
+                                self.list
+                                    .sources
+                                    .push(Source::new(SourceType::Spotify(source), String::new()));
                             } else {
                                 return false;
                             }
                         }
                         "Custom" => {
                             if let Ok(json) = serde_json::from_str(&id) {
-                                self.list.sources.push(Source {
-                                    source_type: SourceType::Custom(json),
-                                    name: String::new(),
-                                });
+                                self.list
+                                    .sources
+                                    .push(Source::new(SourceType::Custom(json), String::new()));
                             } else {
                                 return false;
                             }
                         }
                         "Setlist" => {
-                            if let Some(id) = crate::parse_setlist_source(id) {
-                                self.list.sources.push(Source {
-                                    source_type: SourceType::Setlist(id),
-                                    name: String::new(),
-                                });
+                            // Either a setlist URL or a JSON search for an artist
+                            let source_type = if id.trim_start().starts_with('{') {
+                                match serde_json::from_str(&id) {
+                                    Ok(query) => SourceType::SetlistSearch(query),
+                                    Err(_) => return false,
+                                }
+                            } else if let Some(id) = crate::parse_setlist_source(id) {
+                                SourceType::Setlist(id)
                             } else {
                                 return false;
-                            }
+                            };
+                            self.list
+                                .sources
+                                .push(Source::new(source_type, String::new()));
+                        }
+                        "IMDb" => {
+                            // Either a JSON query or comma separated title IDs
+                            let imdb = if id.trim_start().starts_with('{') {
+                                match serde_json::from_str(&id) {
+                                    Ok(query) => Imdb::Query(query),
+                                    Err(_) => return false,
+                                }
+                            } else {
+                                Imdb::Titles(
+                                    id.split(',')
+                                        .map(|t| t.trim().to_owned())
+                                        .filter(|t| !t.is_empty())
+                                        .collect(),
+                                )
+                            };
+                            self.list
+                                .sources
+                                .push(Source::new(SourceType::Imdb(imdb), String::new()));
+                        }
+                        "Last.fm" => {
+                            self.list
+                                .sources
+                                .push(Source::new(SourceType::Lastfm(ids(&id)), String::new()));
+                        }
+                        "Letterboxd" => {
+                            self.list
+                                .sources
+                                .push(Source::new(SourceType::Letterboxd(ids(&id)), String::new()));
+                        }
+                        "Goodreads" => {
+                            self.list
+                                .sources
+                                .push(Source::new(SourceType::Goodreads(ids(&id)), String::new()));
+                        }
+                        "CFB" => {
+                            self.list
+                                .sources
+                                .push(Source::new(SourceType::Cfb(ids(&id)), String::new()));
+                        }
+                        "Upload" => {
+                            self.list
+                                .sources
+                                .push(Source::new(SourceType::Upload(ids(&id)), String::new()));
                         }
                         "List Items" => {
-                            self.list.sources.push(Source {
-                                source_type: SourceType::ListItems(id),
-                                name: String::new(),
-                            });
+                            // Either a list ID or a JSON set operation on lists
+                            let source_type = if id.trim_start().starts_with('{') {
+                                match serde_json::from_str(&id) {
+                                    Ok(set) => SourceType::ListSet(set),
+                                    Err(_) => return false,
+                                }
+                            } else {
+                                SourceType::ListItems(id)
+                            };
+                            self.list
+                                .sources
+                                .push(Source::new(source_type, String::new()));
                         }
                         _ => {
                             return false;
@@ -158,6 +269,139 @@ impl Component for Edit {
                 });
                 false
             }
+            Msg::Upload => {
+                let Some(file) = self
+                    .file_ref
+                    .cast::<HtmlInputElement>()
+                    .unwrap()
+                    .files()
+                    .and_then(|files| files.get(0))
+                else {
+                    return false;
+                };
+                let column = |node: &NodeRef| node.cast::<HtmlInputElement>().unwrap().value();
+                let columns = [
+                    ("id_column", column(&self.id_column_ref)),
+                    ("name_column", column(&self.name_column_ref)),
+                    ("iframe_column", column(&self.iframe_column_ref)),
+                ];
+                let file_type = self
+                    .file_type_ref
+                    .cast::<HtmlSelectElement>()
+                    .unwrap()
+                    .value();
+                let id = self.list.id.clone();
+                ctx.link().send_future(async move {
+                    Msg::Uploaded(
+                        crate::upload_list_items(&id, &file_type, &file, &columns)
+                            .await
+                            .map_err(|e| e.as_string().unwrap_or_default()),
+                    )
+                });
+                false
+            }
+            Msg::Uploaded(Ok(())) => {
+                // The list sources and items have changed
+                crate::window().location().reload().unwrap();
+                false
+            }
+            Msg::Uploaded(Err(e)) => {
+                self.upload_error = Some(e);
+                true
+            }
+            Msg::ImportScrobbles => {
+                let Some(file) = self
+                    .scrobbles_ref
+                    .cast::<HtmlInputElement>()
+                    .unwrap()
+                    .files()
+                    .and_then(|files| files.get(0))
+                else {
+                    return false;
+                };
+                self.importing = true;
+                let id = self.list.id.clone();
+                ctx.link().send_future(async move {
+                    Msg::ImportedScrobbles(
+                        crate::import_scrobbles(&id, &file)
+                            .await
+                            .map_err(|e| e.as_string().unwrap_or_default()),
+                    )
+                });
+                true
+            }
+            Msg::ImportedScrobbles(result) => {
+                self.importing = false;
+                self.scrobble_import = Some(result);
+                true
+            }
+            Msg::CheckSetlists => {
+                self.checking_setlists = true;
+                let id = self.list.id.clone();
+                ctx.link().send_future(async move {
+                    Msg::CheckedSetlists(
+                        crate::fetch_setlist_reports(&id)
+                            .await
+                            .map_err(|e| e.as_string().unwrap_or_default()),
+                    )
+                });
+                true
+            }
+            Msg::CheckedSetlists(result) => {
+                self.checking_setlists = false;
+                self.pin_refs = match &result {
+                    Ok(reports) => reports
+                        .iter()
+                        .map(|report| {
+                            report
+                                .ambiguous
+                                .iter()
+                                .map(|m| &m.song)
+                                .chain(&report.unmatched)
+                                .map(|song| (song.clone(), NodeRef::default()))
+                                .collect()
+                        })
+                        .collect(),
+                    Err(_) => Vec::new(),
+                };
+                self.setlist_reports = Some(result);
+                true
+            }
+            Msg::PinSong(report, song) => {
+                let Some(Ok(reports)) = &self.setlist_reports else {
+                    return false;
+                };
+                let (song, track_ref) = &self.pin_refs[report][song];
+                let track = track_ref.cast::<HtmlInputElement>().unwrap().value();
+                if track.trim().is_empty() {
+                    return false;
+                }
+                let pin = SetlistOverride {
+                    artist_mbid: reports[report].artist_mbid.clone(),
+                    song: song.clone(),
+                    track,
+                };
+                self.checking_setlists = true;
+                let id = self.list.id.clone();
+                ctx.link().send_future(async move {
+                    Msg::PinnedSong(
+                        crate::pin_setlist_song(&id, &pin)
+                            .await
+                            .map_err(|e| e.as_string().unwrap_or_default()),
+                    )
+                });
+                true
+            }
+            Msg::PinnedSong(Ok(())) => {
+                // Show the new matches
+                ctx.link().send_message(Msg::CheckSetlists);
+                false
+            }
+            Msg::PinnedSong(Err(e)) => {
+                self.checking_setlists = false;
+                self.setlist_reports = Some(Err(e));
+                true
+            }
             Msg::Delete => {
                 let id = self.list.id.clone();
                 if crate::window()
@@ -199,13 +443,21 @@ impl Component for Edit {
             .iter()
             .enumerate()
             .map(|(i, (key, source_ref, id, source))| {
-                let mut selected = [false; 4];
+                let mut selected = [false; 10];
                 match source {
                     None => selected[1] = true,
                     Some(SourceType::Custom(_)) => selected[0] = true,
                     Some(SourceType::Spotify(_)) => selected[1] = true,
-                    Some(SourceType::Setlist(_)) => selected[2] = true,
-                    Some(SourceType::ListItems(_)) => selected[3] = true,
+                    Some(SourceType::Setlist(_) | SourceType::SetlistSearch(_)) => {
+                        selected[2] = true
+                    }
+                    Some(SourceType::ListItems(_) | SourceType::ListSet(_)) => selected[3] = true,
+                    Some(SourceType::Imdb(_)) => selected[4] = true,
+                    Some(SourceType::Lastfm(_)) => selected[5] = true,
+                    Some(SourceType::Letterboxd(_)) => selected[6] = true,
+                    Some(SourceType::Goodreads(_)) => selected[7] = true,
+                    Some(SourceType::Cfb(_)) => selected[8] = true,
+                    Some(SourceType::Upload(_)) => selected[9] = true,
                 };
                 let onclick = ctx.link().callback(move |_| Msg::DeleteSource(i));
                 html! {
@@ -216,6 +468,12 @@ impl Component for Edit {
                                 <option selected={selected[1]}>{"Spotify"}</option>
                                 <option selected={selected[2]}>{"Setlist"}</option>
                                 <option selected={selected[3]}>{"List Items"}</option>
+                                <option selected={selected[4]}>{"IMDb"}</option>
+                                <option selected={selected[5]}>{"Last.fm"}</option>
+                                <option selected={selected[6]}>{"Letterboxd"}</option>
+                                <option selected={selected[7]}>{"Goodreads"}</option>
+                                <option selected={selected[8]}>{"CFB"}</option>
+                                <option selected={selected[9]}>{"Upload"}</option>
                             </select>
                         </div>
                         <input class="col-9 col-sm-7 col-md-8" ref={id}/>
@@ -232,6 +490,91 @@ impl Component for Edit {
         };
         let add_source = ctx.link().callback(|_| Msg::AddSource);
         let save = ctx.link().callback(|_| Msg::Save);
+        let upload = ctx.link().callback(|_| Msg::Upload);
+        let import_scrobbles = ctx.link().callback(|_| Msg::ImportScrobbles);
+        let scrobble_import = match &self.scrobble_import {
+            Some(Ok(import)) => html! {
+                <div class="alert alert-success" role="alert">
+                    {format!("Imported {} scrobbles as {} items.", import.scrobbles, import.items)}
+                    if !import.unmatched.is_empty() {
+                        {format!(" {} tracks couldn't be found on Spotify:", import.unmatched.len())}
+                        <ul class="mb-0 overflow-y-auto" style="max-height: 240px">
+                            {for import.unmatched.iter().map(|t| html! {
+                                <li>{format!("{} - {} ({} plays)", t.artist, t.track, t.play_count)}</li>
+                            })}
+                        </ul>
+                    }
+                </div>
+            },
+            Some(Err(error)) => html! {
+                <div class="alert alert-danger" role="alert" style="white-space: pre-line">{error}</div>
+            },
+            None => html! {},
+        };
+        let has_setlists = ctx.props().list.sources.iter().any(|s| {
+            matches!(
+                s.source_type,
+                SourceType::Setlist(_) | SourceType::SetlistSearch(_)
+            )
+        });
+        let check_setlists = ctx.link().callback(|_| Msg::CheckSetlists);
+        let setlist_reports = match &self.setlist_reports {
+            Some(Ok(reports)) => reports
+                .iter()
+                .zip(&self.pin_refs)
+                .enumerate()
+                .map(|(i, (report, pin_refs))| {
+                    let tracks = report
+                        .ambiguous
+                        .iter()
+                        .map(|m| {
+                            let url = format!(
+                                "https://open.spotify.com/track/{}",
+                                m.item_id.trim_start_matches("spotify:track:")
+                            );
+                            html! {<a href={url} target="_blank">{&m.item_name}</a>}
+                        })
+                        .chain(report.unmatched.iter().map(|_| html! {<span class="text-danger">{"Not found"}</span>}));
+                    let rows = pin_refs.iter().zip(tracks).enumerate().map(|(j, ((song, track_ref), track))| {
+                        let pin = ctx.link().callback(move |_| Msg::PinSong(i, j));
+                        html! {
+                            <tr>
+                                <td>{song}</td>
+                                <td>{track}</td>
+                                <td class="d-flex gap-2">
+                                    <input class="form-control form-control-sm" ref={track_ref} placeholder="Spotify track URL"/>
+                                    <button type="button" class="btn btn-sm btn-primary" onclick={pin} disabled={disabled || self.checking_setlists}>{"Pin"}</button>
+                                </td>
+                            </tr>
+                        }
+                    });
+                    let pinned = report.matched.iter().filter(|m| m.pinned).count();
+                    html! {
+                        <div class="mb-3">
+                            <h5>{&report.source}</h5>
+                            <p class="mb-1">{format!(
+                                "{} matched ({pinned} pinned), {} ambiguous, {} not found",
+                                report.matched.len(),
+                                report.ambiguous.len(),
+                                report.unmatched.len()
+                            )}</p>
+                            if !pin_refs.is_empty() {
+                                <table class="table table-sm">
+                                    <thead>
+                                        <tr><th>{"Song"}</th><th>{"Spotify track"}</th><th>{"Pin track"}</th></tr>
+                                    </thead>
+                                    <tbody>{for rows}</tbody>
+                                </table>
+                            }
+                        </div>
+                    }
+                })
+                .collect::<Html>(),
+            Some(Err(error)) => html! {
+                <div class="alert alert-danger" role="alert" style="white-space: pre-line">{error}</div>
+            },
+            None => html! {},
+        };
         let delete = ctx.link().callback(|_| Msg::Delete);
         let delete_all = ctx.link().callback(|_| Msg::DeleteAll);
         html! {
@@ -276,6 +619,70 @@ impl Component for Edit {
                 <div class="d-flex gap-3">
                     <button type="button" class="btn btn-primary" onclick={add_source}>{"Add source"}</button>
                 </div>
+                <form class="mt-3" style="max-width: 800px">
+                    <div class="form-floating mb-2">
+                        <select class="form-select" id="refresh" ref={&self.refresh_ref}>
+                            <option value="">{"Never"}</option>
+                            <option value="daily">{"Daily"}</option>
+                            <option value="weekly">{"Weekly"}</option>
+                        </select>
+                        <label for="refresh">{"Refresh sources"}</label>
+                    </div>
+                    if let Some(timestamp) = self.list.last_refreshed {
+                        <p class="mb-2">{format!("Last refreshed {}", String::from(js_sys::Date::new(&(timestamp as f64 * 1000.).into()).to_string()))}</p>
+                    }
+                    if let Some(error) = &self.list.refresh_error {
+                        <div class="alert alert-danger" role="alert">{format!("Refresh failed: {error}")}</div>
+                    }
+                </form>
+                <h4 class="mt-4">{"Upload File"}</h4>
+                <p>{"Add items from a CSV file with a header row, a JSON array or NDJSON. Columns that aren't mapped are kept as item metadata.
+                    Letterboxd and Goodreads CSV exports can also be uploaded with their star ratings and dates.
+                    Uploading a file with the same name again updates the items without resetting their scores."}</p>
+                <form class="mb-3" style="max-width: 800px">
+                    <div class="d-flex gap-2 mb-2">
+                        <select class="form-select w-auto" ref={&self.file_type_ref}>
+                            <option value="upload">{"Items"}</option>
+                            <option value="letterboxd">{"Letterboxd"}</option>
+                            <option value="goodreads">{"Goodreads"}</option>
+                        </select>
+                        <input class="form-control" type="file" accept=".csv,.json,.ndjson,.jsonl" ref={&self.file_ref}/>
+                    </div>
+                    <div class="row g-2 mb-2">
+                        <div class="col form-floating">
+                            <input class="form-control" id="idColumn" ref={&self.id_column_ref} placeholder="id"/>
+                            <label for="idColumn">{"ID column (default: id)"}</label>
+                        </div>
+                        <div class="col form-floating">
+                            <input class="form-control" id="nameColumn" ref={&self.name_column_ref} placeholder="name"/>
+                            <label for="nameColumn">{"Name column (default: name)"}</label>
+                        </div>
+                        <div class="col form-floating">
+                            <input class="form-control" id="iframeColumn" ref={&self.iframe_column_ref} placeholder="iframe"/>
+                            <label for="iframeColumn">{"Iframe column (optional)"}</label>
+                        </div>
+                    </div>
+                    if let Some(error) = &self.upload_error {
+                        <div class="alert alert-danger" role="alert" style="white-space: pre-line">{error}</div>
+                    }
+                    <button type="button" class="btn btn-primary" onclick={upload} {disabled}>{"Upload"}</button>
+                </form>
+                <h4 class="mt-4">{"Import Last.fm Scrobbles"}</h4>
+                <p>{"Add the tracks from a Last.fm scrobble export in CSV or JSON. Tracks are matched to Spotify songs and get play_count, first_played and last_played metadata."}</p>
+                <form class="mb-3" style="max-width: 800px">
+                    <input class="form-control mb-2" type="file" accept=".csv,.json" ref={&self.scrobbles_ref}/>
+                    {scrobble_import}
+                    <button type="button" class="btn btn-primary" onclick={import_scrobbles} disabled={disabled || self.importing}>{"Import"}</button>
+                </form>
+                if has_setlists {
+                    <h4 class="mt-4">{"Setlist Matching"}</h4>
+                    <p>{"Setlist songs are matched to the first Spotify search result. Songs are ambiguous if the track has a different name or artist.
+                        Pinning a Spotify track to a song uses it for that song of the artist in every list."}</p>
+                    <div style="max-width: 800px">
+                        {setlist_reports}
+                        <button type="button" class="btn btn-primary mb-3" onclick={check_setlists} disabled={disabled || self.checking_setlists}>{"Check matches"}</button>
+                    </div>
+                }
                 <hr/>
                 <button type="button" class="btn btn-success mb-3" onclick={save} {disabled}>{"Save all settings"}</button>
                 <div class="d-flex gap-3">
@@ -318,6 +725,15 @@ impl Component for Edit {
                     .unwrap()
                     .set_checked(true);
             }
+            let refresh = match self.list.refresh {
+                Some(RefreshInterval::Daily) => "daily",
+                Some(RefreshInterval::Weekly) => "weekly",
+                None => "",
+            };
+            self.refresh_ref
+                .cast::<HtmlSelectElement>()
+                .unwrap()
+                .set_value(refresh);
             for (_, _, id, source) in self.sources.iter() {
                 let value = match source {
                     None => String::new(),
@@ -325,10 +741,32 @@ impl Component for Edit {
                     Some(
                         SourceType::Spotify(Spotify::Playlist(Id { raw_id, .. }))
                         | SourceType::Spotify(Spotify::Album(Id { raw_id, .. }))
-                        | SourceType::Spotify(Spotify::Track(Id { raw_id, .. })),
+                        | SourceType::Spotify(Spotify::Track(Id { raw_id, .. }))
+                        | SourceType::Spotify(Spotify::Artist(Id { raw_id, .. })),
                     ) => raw_id.clone(),
+                    Some(SourceType::Spotify(Spotify::LikedSongs)) => String::from("Liked Songs"),
+                    Some(SourceType::Spotify(Spotify::TopTracks(time_range))) => {
+                        format!("Top Tracks {}", time_range.as_str())
+                    }
                     Some(SourceType::Setlist(Id { raw_id, .. })) => raw_id.clone(),
+                    Some(SourceType::SetlistSearch(query)) => {
+                        serde_json::to_string(query).unwrap_or_default()
+                    }
                     Some(SourceType::ListItems(id)) => id.clone(),
+                    Some(SourceType::ListSet(set)) => {
+                        serde_json::to_string(set).unwrap_or_default()
+                    }
+                    Some(SourceType::Imdb(Imdb::Titles(ids))) => ids.join(","),
+                    Some(
+                        SourceType::Lastfm(ids)
+                        | SourceType::Letterboxd(ids)
+                        | SourceType::Goodreads(ids)
+                        | SourceType::Cfb(ids)
+                        | SourceType::Upload(ids),
+                    ) => ids.join(","),
+                    Some(SourceType::Imdb(Imdb::Query(query))) => {
+                        serde_json::to_string(query).unwrap_or_default()
+                    }
                 };
                 id.cast::<HtmlInputElement>().unwrap().set_value(&value);
             }
@@ -3,6 +3,7 @@ use std::{collections::HashMap, rc::Rc};
 use crate::{
     bootstrap::{Accordion, Collapse},
     dataframe::DataFrame,
+    plot::DataTable,
     ListMode, ListsRoute, Route, UserProps,
 };
 use wasm_bindgen::JsValue;
@@ -246,7 +247,7 @@ impl Component for Widget {
             <>
                 <Accordion header={list.name.clone()} collapsed={self.collapsed} {on_toggle}>
                     if let Some(query) = &self.query {
-                        {crate::plot::df_table_view(query, false)}
+                        <DataTable df={query.clone()} min_width=false/>
                     } else {
                         <div></div>
                     }
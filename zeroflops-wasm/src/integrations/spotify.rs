@@ -52,7 +52,9 @@ impl Component for SpotifyIntegration {
                     Some(Spotify::Playlist(id)) => ("spotify:playlist", id),
                     Some(Spotify::Album(id)) => ("spotify:album", id),
                     Some(Spotify::Track(id)) => ("spotify:track", id),
-                    None => {
+                    Some(Spotify::Artist(id)) => ("spotify:artist", id),
+                    // Library sources can only be added to lists
+                    Some(Spotify::LikedSongs | Spotify::TopTracks(_)) | None => {
                         return false;
                     }
                 };
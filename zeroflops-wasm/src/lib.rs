@@ -1,20 +1,28 @@
 #![feature(iter_intersperse)]
 use crate::{app::App, dataframe::DataFrame};
-use arrow::array::AsArray;
+use arrow::{
+    array::AsArray,
+    compute,
+    datatypes::{DataType, TimeUnit},
+};
 use js_sys::Uint8Array;
 use regex::Regex;
 use std::{collections::HashSet, io::Cursor};
 use wasm_bindgen::{prelude::*, JsCast};
 use wasm_bindgen_futures::JsFuture;
-use web_sys::{Request, RequestInit, RequestMode, Response, Window};
+use web_sys::{File, Request, RequestInit, RequestMode, Response, Window};
 use yew::{html, Component, Context, Html, Properties};
 use yew_router::Routable;
-use zeroflops::{Id, Items, List, ListMode, Lists, Spotify, User};
+use zeroflops::{
+    spotify::ImportSummary, Dashboard, Dashboards, Id, Items, List, ListMode, Lists,
+    ScrobbleImport, SetlistOverride, SetlistReport, Spotify, TimeRange, User,
+};
 
 mod app;
 mod base;
 mod bootstrap;
-mod dataframe;
+mod dashboard;
+pub mod dataframe;
 mod docs;
 mod edit;
 mod home;
@@ -30,6 +38,10 @@ pub mod tournament;
 enum Route {
     #[at("/")]
     Home,
+    #[at("/dashboards")]
+    Dashboards,
+    #[at("/dashboards/:id")]
+    Dashboard { id: String },
     #[at("/docs")]
     Docs,
     #[at("/lists")]
@@ -56,6 +68,8 @@ pub enum ListsRoute {
     Match { id: String },
     #[at("/lists/:id/tournament")]
     Tournament { id: String },
+    #[at("/lists/:id/history")]
+    History { id: String },
 }
 
 #[derive(Eq, PartialEq, Properties)]
@@ -63,10 +77,27 @@ pub struct UserProps {
     logged_in: bool,
 }
 
+/// Besides URLs, "Liked Songs" and "Top Tracks" with an optional short_term, medium_term or
+/// long_term time range use the library of the user
 pub fn parse_spotify_source(input: String) -> Option<Spotify> {
+    let normalized = input.trim().to_lowercase();
+    if normalized == "liked songs"
+        || normalized.starts_with("https://open.spotify.com/collection/tracks")
+    {
+        return Some(Spotify::LikedSongs);
+    }
+    if let Some(time_range) = normalized.strip_prefix("top tracks") {
+        return match time_range.trim() {
+            "short_term" => Some(Spotify::TopTracks(TimeRange::ShortTerm)),
+            "" | "medium_term" => Some(Spotify::TopTracks(TimeRange::MediumTerm)),
+            "long_term" => Some(Spotify::TopTracks(TimeRange::LongTerm)),
+            _ => None,
+        };
+    }
     let playlist_re = Regex::new(r"https://open.spotify.com/playlist/([[:alnum:]]*)").unwrap();
     let album_re = Regex::new(r"https://open.spotify.com/album/([[:alnum:]]*)").unwrap();
     let track_re = Regex::new(r"https://open.spotify.com/track/([[:alnum:]]*)").unwrap();
+    let artist_re = Regex::new(r"https://open.spotify.com/artist/([[:alnum:]]*)").unwrap();
     return if let Some(caps) = playlist_re.captures_iter(&input).next() {
         Some(Spotify::Playlist(Id {
             id: caps[1].to_owned(),
@@ -82,6 +113,11 @@ pub fn parse_spotify_source(input: String) -> Option<Spotify> {
             id: caps[1].to_owned(),
             raw_id: input,
         }))
+    } else if let Some(caps) = artist_re.captures_iter(&input).next() {
+        Some(Spotify::Artist(Id {
+            id: caps[1].to_owned(),
+            raw_id: input,
+        }))
     } else {
         None
     };
@@ -238,6 +274,117 @@ async fn update_list(list: &List) -> Result<(), JsValue> {
     Ok(())
 }
 
+/// Upload a CSV, JSON or NDJSON file as a data source of the list
+///
+/// The file type is "upload" for custom items or "letterboxd" or "goodreads" for exports. Empty
+/// column mappings use the server defaults.
+async fn upload_list_items(
+    id: &str,
+    file_type: &str,
+    file: &File,
+    columns: &[(&str, String)],
+) -> Result<(), JsValue> {
+    let window = window();
+    let mut url = format!(
+        "/api/lists/{}/{}?file_name={}",
+        id,
+        file_type,
+        js_sys::encode_uri_component(&file.name())
+    );
+    for (key, column) in columns.iter().filter(|(_, c)| !c.is_empty()) {
+        url.push_str(&format!("&{key}={}", js_sys::encode_uri_component(column)));
+    }
+    let request = Request::new_with_str_and_init(
+        &url,
+        RequestInit::new()
+            .method("POST")
+            .mode(RequestMode::Cors)
+            .body(Some(file)),
+    )?;
+    let resp_value = JsFuture::from(window.fetch_with_request(&request)).await?;
+    let resp: Response = resp_value.dyn_into()?;
+    if [400, 404, 500].contains(&resp.status()) {
+        return Err(JsFuture::from(resp.text()?).await?);
+    }
+    Ok(())
+}
+
+/// Import a Last.fm scrobble export into the list
+async fn import_scrobbles(id: &str, file: &File) -> Result<ScrobbleImport, JsValue> {
+    let window = window();
+    let request = Request::new_with_str_and_init(
+        &format!(
+            "/api/lists/{}/lastfm?file_name={}",
+            id,
+            js_sys::encode_uri_component(&file.name())
+        ),
+        RequestInit::new()
+            .method("POST")
+            .mode(RequestMode::Cors)
+            .body(Some(file)),
+    )?;
+    let resp_value = JsFuture::from(window.fetch_with_request(&request)).await?;
+    let resp: Response = resp_value.dyn_into()?;
+    if [400, 404, 500].contains(&resp.status()) {
+        return Err(JsFuture::from(resp.text()?).await?);
+    }
+    let json = JsFuture::from(resp.json()?).await?;
+    Ok(serde_wasm_bindgen::from_value(json).unwrap())
+}
+
+/// How the songs of the setlist sources of the list were matched
+async fn fetch_setlist_reports(id: &str) -> Result<Vec<SetlistReport>, JsValue> {
+    let window = window();
+    let request = query(&format!("/api/lists/{}/setlists", id), "GET")?;
+    let resp_value = JsFuture::from(window.fetch_with_request(&request)).await?;
+    let resp: Response = resp_value.dyn_into()?;
+    if [400, 404, 500].contains(&resp.status()) {
+        return Err(JsFuture::from(resp.text()?).await?);
+    }
+    let json = JsFuture::from(resp.json()?).await?;
+    Ok(serde_wasm_bindgen::from_value(json).unwrap())
+}
+
+/// Use a Spotify track for a setlist song and refresh the list
+async fn pin_setlist_song(id: &str, pin: &SetlistOverride) -> Result<(), JsValue> {
+    let window = window();
+    let request = Request::new_with_str_and_init(
+        &format!("/api/lists/{}/setlists", id),
+        RequestInit::new()
+            .method("PUT")
+            .mode(RequestMode::Cors)
+            .body(Some(&JsValue::from_str(
+                &serde_json::to_string(pin).unwrap(),
+            ))),
+    )?;
+    request.headers().set("Content-Type", "application/json")?;
+    let resp_value = JsFuture::from(window.fetch_with_request(&request)).await?;
+    let resp: Response = resp_value.dyn_into()?;
+    if [400, 404, 500].contains(&resp.status()) {
+        return Err(JsFuture::from(resp.text()?).await?);
+    }
+    Ok(())
+}
+
+/// Import a Streaming_History_Audio_*.json file from the Spotify privacy export
+async fn import_streaming_history(file: &File) -> Result<ImportSummary, JsValue> {
+    let window = window();
+    let request = Request::new_with_str_and_init(
+        "/api/spotify/history",
+        RequestInit::new()
+            .method("POST")
+            .mode(RequestMode::Cors)
+            .body(Some(file)),
+    )?;
+    let resp_value = JsFuture::from(window.fetch_with_request(&request)).await?;
+    let resp: Response = resp_value.dyn_into()?;
+    if [400, 500].contains(&resp.status()) {
+        return Err(JsFuture::from(resp.text()?).await?);
+    }
+    let json = JsFuture::from(resp.json()?).await?;
+    Ok(serde_wasm_bindgen::from_value(json).unwrap())
+}
+
 async fn delete_list(id: &str) -> Result<(), JsValue> {
     let window = window();
     let request = Request::new_with_str_and_init(
@@ -277,6 +424,33 @@ async fn query_list(list: &List, qs: Option<String>) -> Result<Option<DataFrame>
     }))
 }
 
+/// Get the scores of the selected list items after each match
+async fn fetch_score_history(id: &str, ids: &[String]) -> Result<Option<DataFrame>, JsValue> {
+    let window = window();
+    let request = query(
+        &format!(
+            "/api/lists/{}/history?ids={}",
+            id,
+            js_sys::encode_uri_component(&ids.join(","))
+        ),
+        "GET",
+    )?;
+    let resp_value = JsFuture::from(window.fetch_with_request(&request)).await?;
+    let resp: Response = resp_value.dyn_into()?;
+    if [400, 500].contains(&resp.status()) {
+        return Err(JsFuture::from(resp.text()?).await?);
+    }
+    Ok(serialize_into_df(resp).await?.map(|mut df| {
+        // Unix timestamps are plotted as dates
+        if let Some(timestamp) = df.column("timestamp") {
+            let timestamp =
+                compute::cast(timestamp, &DataType::Timestamp(TimeUnit::Second, None)).unwrap();
+            df.with_column("timestamp", timestamp).unwrap();
+        }
+        df
+    }))
+}
+
 async fn get_items(id: &str) -> Result<Items, JsValue> {
     let window = window();
     let request = query(&format!("/api/lists/{}/items", id), "GET").unwrap();
@@ -306,6 +480,18 @@ async fn push_list(id: &str) -> Result<(), JsValue> {
     Ok(())
 }
 
+/// Fetch the sources of the list again, skipping unchanged Spotify playlists
+async fn refresh_list(id: &str) -> Result<(), JsValue> {
+    let window = window();
+    let request = query(&format!("/api/?action=refresh&list={}", id), "POST")?;
+    let resp_value = JsFuture::from(window.fetch_with_request(&request)).await?;
+    let resp: Response = resp_value.dyn_into()?;
+    if [400, 404, 500].contains(&resp.status()) {
+        return Err(JsFuture::from(resp.text()?).await?);
+    }
+    Ok(())
+}
+
 async fn import_list(source: &str, id: &str) -> Result<(), JsValue> {
     let window = window();
     let request = query(
@@ -327,6 +513,74 @@ async fn find_items(search: &str) -> Result<Option<DataFrame>, JsValue> {
     serialize_into_df(resp).await
 }
 
+async fn fetch_dashboards() -> Result<Vec<Dashboard>, JsValue> {
+    let window = window();
+    let request = query("/api/dashboards", "GET")?;
+    let resp_value = JsFuture::from(window.fetch_with_request(&request)).await?;
+    let resp: Response = resp_value.dyn_into()?;
+    let json = JsFuture::from(resp.json()?).await?;
+    let dashboards: Dashboards = serde_wasm_bindgen::from_value(json).unwrap();
+    Ok(dashboards.dashboards)
+}
+
+async fn fetch_dashboard(id: &str) -> Result<Option<Dashboard>, JsValue> {
+    let window = window();
+    let request = query(&format!("/api/dashboards/{}", id), "GET")?;
+    let resp_value = JsFuture::from(window.fetch_with_request(&request)).await?;
+    let resp: Response = resp_value.dyn_into()?;
+    if resp.status() == 404 {
+        return Ok(None);
+    }
+    let json = JsFuture::from(resp.json()?).await?;
+    Ok(Some(serde_wasm_bindgen::from_value(json).unwrap()))
+}
+
+async fn create_dashboard() -> Result<Dashboard, JsValue> {
+    let window = window();
+    let request = query("/api/dashboards", "POST")?;
+    let resp_value = JsFuture::from(window.fetch_with_request(&request)).await?;
+    let resp: Response = resp_value.dyn_into()?;
+    let json = JsFuture::from(resp.json()?).await?;
+    Ok(serde_wasm_bindgen::from_value(json).unwrap())
+}
+
+async fn update_dashboard(dashboard: &Dashboard) -> Result<(), JsValue> {
+    let window = window();
+    let request = Request::new_with_str_and_init(
+        &format!("/api/dashboards/{}", dashboard.id),
+        RequestInit::new()
+            .method("PUT")
+            .mode(RequestMode::Cors)
+            .body(Some(&JsValue::from_str(
+                &serde_json::to_string(&dashboard).unwrap(),
+            ))),
+    )?;
+    request.headers().set("Content-Type", "application/json")?;
+    JsFuture::from(window.fetch_with_request(&request)).await?;
+    Ok(())
+}
+
+async fn delete_dashboard(id: &str) -> Result<(), JsValue> {
+    let window = window();
+    let request = query(&format!("/api/dashboards/{}", id), "DELETE")?;
+    JsFuture::from(window.fetch_with_request(&request)).await?;
+    Ok(())
+}
+
+async fn query_panel(id: &str, index: usize) -> Result<Option<DataFrame>, JsValue> {
+    let window = window();
+    let request = query(
+        &format!("/api/dashboards/{}/panels/{}/query", id, index),
+        "GET",
+    )?;
+    let resp_value = JsFuture::from(window.fetch_with_request(&request)).await?;
+    let resp: Response = resp_value.dyn_into()?;
+    if [400, 404, 500].contains(&resp.status()) {
+        return Err(JsFuture::from(resp.text()?).await?);
+    }
+    serialize_into_df(resp).await
+}
+
 async fn serialize_into_df(resp: Response) -> Result<Option<DataFrame>, JsValue> {
     let buf = Uint8Array::new(&JsFuture::from(resp.array_buffer()?).await?).to_vec();
     if buf.is_empty() {
@@ -364,6 +618,11 @@ fn user_list(list: &List, user: &Option<User>) -> bool {
         || (user.is_none() && list.user_id == "demo")
 }
 
+/// Dashboards can only be edited by their owner since the demo user can't save changes
+fn user_dashboard(dashboard: &Dashboard, user: &Option<User>) -> bool {
+    Some(&dashboard.user_id) == user.as_ref().map(|u| &u.user_id)
+}
+
 fn not_found() -> Html {
     html! {
         <h1>{"Not found"}</h1>
@@ -3,6 +3,7 @@ use yew::{html, Component, Context, Html};
 use yew_router::{prelude::Link, scope_ext::RouterScopeExt};
 use zeroflops::List;
 
+pub mod history;
 pub mod item;
 
 pub enum ListsMsg {
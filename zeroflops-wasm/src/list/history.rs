@@ -0,0 +1,155 @@
+use crate::{
+    dataframe::DataFrame,
+    plot::{ChartOptions, DataView},
+};
+use yew::{html, Component, Context, Html, Properties};
+use zeroflops::List;
+
+// Only the top items are plotted by default to keep the chart readable
+const DEFAULT_SELECTED: usize = 5;
+
+pub enum Msg {
+    Toggle(usize),
+    Load(Option<DataFrame>),
+    Failed(String),
+    Options(ChartOptions),
+}
+
+#[derive(PartialEq, Properties)]
+pub struct ScoreHistoryProps {
+    pub list: List,
+}
+
+/// Plot the score of the selected items after each match
+pub struct ScoreHistory {
+    // Indices of list items ordered by score
+    order: Vec<usize>,
+    selected: Vec<bool>,
+    data: Option<DataFrame>,
+    error: Option<String>,
+    options: ChartOptions,
+    draw: bool,
+}
+
+impl ScoreHistory {
+    fn fetch(&self, ctx: &Context<Self>) {
+        let list = &ctx.props().list;
+        let id = list.id.clone();
+        let ids: Vec<_> = list
+            .items
+            .iter()
+            .zip(&self.selected)
+            .filter_map(|(i, selected)| selected.then(|| i.id.clone()))
+            .collect();
+        if ids.is_empty() {
+            ctx.link().send_message(Msg::Load(None));
+            return;
+        }
+        ctx.link().send_future(async move {
+            match crate::fetch_score_history(&id, &ids).await {
+                Ok(data) => Msg::Load(data),
+                Err(e) => Msg::Failed(e.as_string().unwrap_or_default()),
+            }
+        });
+    }
+}
+
+impl Component for ScoreHistory {
+    type Message = Msg;
+    type Properties = ScoreHistoryProps;
+
+    fn create(ctx: &Context<Self>) -> Self {
+        let items = &ctx.props().list.items;
+        let mut order: Vec<_> = (0..items.len()).collect();
+        order.sort_by_key(|&i| -items[i].score);
+        let mut selected = vec![false; items.len()];
+        for &i in order.iter().take(DEFAULT_SELECTED) {
+            selected[i] = true;
+        }
+        let history = ScoreHistory {
+            order,
+            selected,
+            data: None,
+            error: None,
+            options: ChartOptions {
+                x: Some(String::from("timestamp")),
+                y: Some(String::from("score")),
+                // Items can have the same name
+                series: Some(String::from("id")),
+                series_label: Some(String::from("name")),
+                title: String::from("Score over time"),
+                x_label: String::from("Date"),
+                y_label: String::from("Score"),
+                ..ChartOptions::default()
+            },
+            draw: false,
+        };
+        history.fetch(ctx);
+        history
+    }
+
+    fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
+        match msg {
+            Msg::Toggle(i) => {
+                self.selected[i] = !self.selected[i];
+                self.fetch(ctx);
+            }
+            Msg::Load(data) => {
+                self.data = data;
+                self.error = None;
+                self.draw = true;
+            }
+            Msg::Failed(e) => {
+                self.error = Some(e);
+            }
+            Msg::Options(options) => {
+                self.options = options;
+                self.draw = true;
+            }
+        }
+        true
+    }
+
+    fn view(&self, ctx: &Context<Self>) -> Html {
+        let items = &ctx.props().list.items;
+        let item_html = self.order.iter().map(|&i| {
+            let id = format!("history-{i}");
+            let onchange = ctx.link().callback(move |_| Msg::Toggle(i));
+            html! {
+                <div class="form-check">
+                    <input class="form-check-input" type="checkbox" id={id.clone()} checked={self.selected[i]} {onchange}/>
+                    <label class="form-check-label" for={id}>{&items[i].name}</label>
+                </div>
+            }
+        });
+        html! {
+            <div class="row">
+                <div class="col-12 col-lg-3 mb-3 overflow-y-auto" style="max-height: 480px">
+                    {for item_html}
+                </div>
+                <div class="col-12 col-lg-9">
+                    if let Some(error) = &self.error {
+                        <div class="alert alert-danger" role="alert">{error}</div>
+                    }
+                    if let Some(data) = &self.data {
+                        {DataView::LineGraph.render("canvas", data, &self.options, ctx.link().callback(Msg::Options))}
+                    } else {
+                        <p>{"The selected items haven't been in any matches yet."}</p>
+                    }
+                </div>
+            </div>
+        }
+    }
+
+    fn rendered(&mut self, ctx: &Context<Self>, _first_render: bool) {
+        if !self.draw {
+            return;
+        }
+        self.draw = false;
+        if let Some(data) = &self.data {
+            if let Err(e) = DataView::LineGraph.draw("canvas", data, &self.options) {
+                ctx.link().send_message(Msg::Failed(e.to_string()));
+            }
+        }
+    }
+}
@@ -25,6 +25,7 @@ pub enum Msg {
     HideAlert,
     SaveSuccess(Vec<(usize, HashMap<String, Value>)>),
     Push,
+    Refresh,
     Open(usize),
     ModalBack,
     ModalForward,
@@ -242,6 +243,20 @@ impl Component for ListItems {
                 });
                 false
             }
+            Msg::Refresh => {
+                let id = ctx.props().list.id.clone();
+                ctx.link().send_future(async move {
+                    match crate::refresh_list(&id).await {
+                        Ok(()) => {
+                            // The list items have changed
+                            crate::window().location().reload().unwrap();
+                            Msg::None
+                        }
+                        Err(e) => Msg::SaveError(e.as_string().unwrap_or_default()),
+                    }
+                });
+                false
+            }
             Msg::Open(item) => {
                 self.modal = Some(item);
                 true
@@ -317,6 +332,7 @@ impl Component for ListItems {
             let raw_id = match &source.source_type {
                 SourceType::Spotify(Spotify::Playlist(Id { raw_id, .. }))
                 | SourceType::Spotify(Spotify::Album(Id { raw_id, .. }))
+                | SourceType::Spotify(Spotify::Artist(Id { raw_id, .. }))
                 | SourceType::Setlist(Id { raw_id, .. })
                     if Url::new(raw_id).is_ok() =>
                 {
@@ -407,6 +423,7 @@ impl Component for ListItems {
         };
         let save = ctx.link().callback(|_| Msg::Save);
         let push = ctx.link().callback(|_| Msg::Push);
+        let refresh = ctx.link().callback(|_| Msg::Refresh);
         let push_available = if let Some(user) = &*ctx.props().user {
             if let Ok((Some(source), _)) = list.get_unique_source() {
                 source == "spotify" && user.spotify_user.is_some()
@@ -446,6 +463,7 @@ impl Component for ListItems {
                 <hr/>
                 <h4>{"Data Sources"}</h4>
                 {for source_html}
+                <button type="button" class="btn btn-primary me-3" onclick={refresh} {disabled}>{"Refresh"}</button>
                 if !matches!(list.mode, ListMode::External) {
                     <button type="button" class="btn btn-success" onclick={push} disabled={!push_available}>{"Push"}</button>
                 }
@@ -1,36 +1,181 @@
 use arrow::{
-    array::{AsArray, RecordBatch},
+    array::{Array, ArrayRef, AsArray, BooleanArray, Float64Array, RecordBatch},
     compute,
     csv::Writer,
-    datatypes::{DataType, Float64Type, UInt32Type},
+    datatypes::{DataType, Float64Type, TimeUnit, UInt32Type},
     util::display,
 };
-use plotters::prelude::{
-    ChartBuilder, Circle, Color, Histogram, IntoDrawingArea, IntoSegmentedCoord, LineSeries, BLACK,
-    RED, WHITE,
+use plotters::{
+    coord::{
+        ranged1d::{AsRangedCoord, ValueFormatter},
+        CoordTranslate, Shift,
+    },
+    prelude::{
+        Boxplot, ChartBuilder, ChartContext, Circle, Color, DrawingArea, DrawingBackend, Histogram,
+        IntoDrawingArea, IntoLogRange, IntoSegmentedCoord, LineSeries, Palette, Palette99,
+        PathElement, Quartiles, RGBAColor, Rectangle, SVGBackend, SegmentValue, BLACK, RED, WHITE,
+    },
 };
 use plotters_canvas::CanvasBackend;
-use std::{collections::HashMap, sync::Arc};
-use yew::{html, Html};
+use std::{collections::HashMap, ops::Range, sync::Arc};
+use wasm_bindgen::{JsCast, JsValue};
+use web_sys::{HtmlElement, HtmlInputElement, HtmlSelectElement};
+use yew::{html, Callback, Component, Context, Event, Html, InputEvent, Properties, TargetCast};
 
 use crate::dataframe::DataFrame;
 
+const WIDTH: u32 = 640;
+const HEIGHT: u32 = 426;
+
+type Column<'a> = (&'a str, &'a ArrayRef);
+
+/// Key, label and coordinates of one line in a line graph
+type Line<'a> = (&'a str, &'a str, Vec<(f64, f64)>);
+
+#[derive(Clone, Copy)]
 pub enum DataView {
     Table,
     ColumnGraph,
     LineGraph,
     ScatterPlot,
     CumLineGraph,
+    Histogram,
+    BoxPlot,
+    Heatmap,
     Csv,
 }
 
+/// Options shared by all chart types
+///
+/// The x and y columns default to the first two columns and the axis labels default to the
+/// column names.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ChartOptions {
+    pub x: Option<String>,
+    pub y: Option<String>,
+    pub title: String,
+    pub x_label: String,
+    pub y_label: String,
+    pub log_scale: bool,
+    /// Sort rows by the value column before plotting, true for descending
+    pub sort: Option<bool>,
+    pub limit: Option<usize>,
+    /// Draw one line per distinct value of this column in line graphs
+    pub series: Option<String>,
+    /// Label the lines with this column instead of the series values, like names of ids
+    pub series_label: Option<String>,
+}
+
+impl ChartOptions {
+    fn x<'a>(&self, df: &'a DataFrame) -> Result<Column<'a>, Box<dyn std::error::Error>> {
+        axis(df, self.x.as_deref(), 0)
+    }
+
+    fn y<'a>(&self, df: &'a DataFrame) -> Result<Column<'a>, Box<dyn std::error::Error>> {
+        axis(df, self.y.as_deref(), 1)
+    }
+
+    fn x_desc<'a>(&'a self, name: &'a str) -> &'a str {
+        if self.x_label.is_empty() {
+            name
+        } else {
+            &self.x_label
+        }
+    }
+
+    fn y_desc<'a>(&'a self, name: &'a str) -> &'a str {
+        if self.y_label.is_empty() {
+            name
+        } else {
+            &self.y_label
+        }
+    }
+
+    fn series<'a>(&self, df: &'a DataFrame) -> Option<Column<'a>> {
+        column(df, self.series.as_deref()?)
+    }
+
+    fn series_label<'a>(&self, df: &'a DataFrame) -> Option<Column<'a>> {
+        column(df, self.series_label.as_deref()?)
+    }
+
+    /// Cast to f64, dropping values that are not positive if log scale is enabled
+    fn values(&self, array: &ArrayRef) -> Result<Float64Array, Box<dyn std::error::Error>> {
+        let values = compute::cast(array, &DataType::Float64)?;
+        let values = values.as_primitive::<Float64Type>();
+        Ok(if self.log_scale {
+            values.iter().map(|v| v.filter(|v| *v > 0.)).collect()
+        } else {
+            values.clone()
+        })
+    }
+}
+
+fn column<'a>(df: &'a DataFrame, name: &str) -> Option<Column<'a>> {
+    let i = df.schema.fields.iter().position(|f| f.name() == name)?;
+    Some((df.schema.fields[i].name(), &df.arrays[i]))
+}
+
+/// Fall back to the default column if the selected column isn't in the query anymore
+fn axis<'a>(
+    df: &'a DataFrame,
+    name: Option<&str>,
+    default: usize,
+) -> Result<Column<'a>, Box<dyn std::error::Error>> {
+    let i = name
+        .and_then(|name| df.schema.fields.iter().position(|f| f.name() == name))
+        .unwrap_or(default);
+    match (df.schema.fields.get(i), df.arrays.get(i)) {
+        (Some(field), Some(array)) => Ok((field.name(), array)),
+        _ => Err(format!("query needs at least {} columns to plot", i + 1).into()),
+    }
+}
+
 impl DataView {
-    pub fn render(&self, df: &DataFrame) -> Html {
+    pub const NAMES: [&'static str; 9] = [
+        "Table",
+        "Column Graph",
+        "Line Graph",
+        "Scatter Plot",
+        "Cumulative Line Graph",
+        "Histogram",
+        "Box Plot",
+        "Heatmap",
+        "CSV",
+    ];
+
+    pub fn from_name(name: &str) -> Option<DataView> {
+        Some(match name {
+            "Table" => DataView::Table,
+            "Column Graph" => DataView::ColumnGraph,
+            "Line Graph" => DataView::LineGraph,
+            "Scatter Plot" => DataView::ScatterPlot,
+            "Cumulative Line Graph" => DataView::CumLineGraph,
+            "Histogram" => DataView::Histogram,
+            "Box Plot" => DataView::BoxPlot,
+            "Heatmap" => DataView::Heatmap,
+            "CSV" => DataView::Csv,
+            _ => return None,
+        })
+    }
+
+    /// Charts are drawn on the canvas with the given id so that several views can share a page
+    pub fn render(
+        &self,
+        canvas: &str,
+        df: &DataFrame,
+        options: &ChartOptions,
+        onchange: Callback<ChartOptions>,
+    ) -> Html {
+        let chart = !matches!(self, DataView::Table | DataView::Csv);
         html! {
             <div>
-                <canvas id="canvas" width="640" height="426" class={if let DataView::Table | DataView::Csv = self { "d-none" } else { "" }}></canvas>
+                <div class={if chart { "" } else { "d-none" }}>
+                    {self.options_view(canvas, df, options, onchange)}
+                </div>
+                <canvas id={canvas.to_owned()} width={WIDTH.to_string()} height={HEIGHT.to_string()} class={if chart { "" } else { "d-none" }}></canvas>
                 if let DataView::Table = self {
-                    {df_table_view(df, true)}
+                    <DataTable df={df.clone()} min_width=true/>
                 } else if let DataView::Csv = self {
                     <p>{write_csv(df)
                         .lines()
@@ -42,40 +187,361 @@ impl DataView {
         }
     }
 
-    pub fn draw(&self, df: &DataFrame) -> Result<(), Box<dyn std::error::Error>> {
+    fn options_view(
+        &self,
+        canvas: &str,
+        df: &DataFrame,
+        options: &ChartOptions,
+        onchange: Callback<ChartOptions>,
+    ) -> Html {
+        // Each control replaces one field of the current options
+        let update = |f: fn(&mut ChartOptions, String)| {
+            let options = options.clone();
+            onchange.reform(move |value| {
+                let mut options = options.clone();
+                f(&mut options, value);
+                options
+            })
+        };
+        let x = options.x(df).ok().map(|(name, _)| name);
+        let y = options.y(df).ok().map(|(name, _)| name);
+        let log_scale = {
+            let options = options.clone();
+            onchange.reform(move |e: Event| ChartOptions {
+                log_scale: e.target_unchecked_into::<HtmlInputElement>().checked(),
+                ..options.clone()
+            })
+        };
+        let log_scale_id = format!("{canvas}-log-scale");
+        let export = Callback::from({
+            let view = *self;
+            let df = df.clone();
+            let options = options.clone();
+            move |_| match view.svg(&df, &options) {
+                Ok(svg) => {
+                    let title = if options.title.is_empty() {
+                        "chart"
+                    } else {
+                        &options.title
+                    };
+                    if let Err(e) = download(&format!("{title}.svg"), &svg) {
+                        web_sys::console::log_1(&e);
+                    }
+                }
+                Err(e) => web_sys::console::log_1(&JsValue::from(e.to_string())),
+            }
+        });
+        html! {
+            <div class="d-flex flex-wrap gap-2 mb-2">
+                {axis_select("X", df, x, update(|o, x| o.x = Some(x)))}
+                if !matches!(self, DataView::Histogram) {
+                    {axis_select("Y", df, y, update(|o, y| o.y = Some(y)))}
+                }
+                if let DataView::LineGraph = self {
+                    <div class="input-group input-group-sm w-auto">
+                        <span class="input-group-text">{"Series"}</span>
+                        <select class="form-select" onchange={update(|o, series| o.series = (!series.is_empty()).then_some(series)).reform(|e: Event| e.target_unchecked_into::<HtmlSelectElement>().value())}>
+                            <option value="" selected={options.series.is_none()}>{"None"}</option>
+                            {for df.schema.fields.iter().map(|f| html! {
+                                <option selected={options.series.as_ref() == Some(f.name())}>{f.name()}</option>
+                            })}
+                        </select>
+                    </div>
+                }
+                {text_input("Title", "", &options.title, update(|o, title| o.title = title))}
+                {text_input("X label", x.unwrap_or_default(), &options.x_label, update(|o, label| o.x_label = label))}
+                {text_input("Y label", y.unwrap_or_default(), &options.y_label, update(|o, label| o.y_label = label))}
+                <div class="input-group input-group-sm w-auto">
+                    <span class="input-group-text">{"Sort"}</span>
+                    <select class="form-select" onchange={update(|o, sort| o.sort = match &*sort {
+                        "Ascending" => Some(false),
+                        "Descending" => Some(true),
+                        _ => None,
+                    }).reform(|e: Event| e.target_unchecked_into::<HtmlSelectElement>().value())}>
+                        <option selected={options.sort.is_none()}>{"None"}</option>
+                        <option selected={options.sort == Some(false)}>{"Ascending"}</option>
+                        <option selected={options.sort == Some(true)}>{"Descending"}</option>
+                    </select>
+                </div>
+                <div class="input-group input-group-sm w-auto">
+                    <span class="input-group-text">{"Top"}</span>
+                    <input type="number" min="1" class="form-control" style="max-width: 80px" value={options.limit.map(|l| l.to_string())} onchange={update(|o, limit| o.limit = limit.parse().ok()).reform(|e: Event| e.target_unchecked_into::<HtmlInputElement>().value())}/>
+                </div>
+                <div class="form-check form-check-inline align-self-center m-0">
+                    <input class="form-check-input" type="checkbox" id={log_scale_id.clone()} checked={options.log_scale} onchange={log_scale}/>
+                    <label class="form-check-label" for={log_scale_id}>{"Log scale"}</label>
+                </div>
+                <button type="button" class="btn btn-sm btn-outline-secondary" onclick={export}>{"Export SVG"}</button>
+            </div>
+        }
+    }
+
+    pub fn draw(
+        &self,
+        canvas: &str,
+        df: &DataFrame,
+        options: &ChartOptions,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        if let DataView::Table | DataView::Csv = self {
+            return Ok(());
+        }
+        let backend = CanvasBackend::new(canvas).expect("cannot find canvas");
+        self.draw_on(&backend.into_drawing_area(), df, options)
+    }
+
+    /// Render the chart as a standalone SVG document
+    pub fn svg(
+        &self,
+        df: &DataFrame,
+        options: &ChartOptions,
+    ) -> Result<String, Box<dyn std::error::Error>> {
+        let mut svg = String::new();
+        {
+            let root = SVGBackend::with_string(&mut svg, (WIDTH, HEIGHT)).into_drawing_area();
+            self.draw_on(&root, df, options)?;
+            root.present()?;
+        }
+        Ok(svg)
+    }
+
+    fn draw_on<DB: DrawingBackend>(
+        &self,
+        root: &DrawingArea<DB, Shift>,
+        df: &DataFrame,
+        options: &ChartOptions,
+    ) -> Result<(), Box<dyn std::error::Error>>
+    where
+        DB::ErrorType: 'static,
+    {
+        root.fill(&WHITE)?;
+
+        let mut df = df.clone();
+        if let Some(descending) = options.sort {
+            let (column, _) = if let DataView::Histogram = self {
+                options.x(&df)?
+            } else {
+                options.y(&df)?
+            };
+            let column = column.to_string();
+            df.sort(&column, descending)?;
+        }
+        if let Some(limit) = options.limit {
+            df.head(limit);
+        }
+        if options.log_scale {
+            self.draw_scaled(root, &df, options, |range| range.log_scale())
+        } else {
+            self.draw_scaled(root, &df, options, |range| range)
+        }
+    }
+
+    /// Draw the chart with the value axis built by scale, either linear or log
+    fn draw_scaled<DB: DrawingBackend, Y>(
+        &self,
+        root: &DrawingArea<DB, Shift>,
+        df: &DataFrame,
+        options: &ChartOptions,
+        scale: impl Fn(Range<f64>) -> Y,
+    ) -> Result<(), Box<dyn std::error::Error>>
+    where
+        DB::ErrorType: 'static,
+        Y: AsRangedCoord<Value = f64>,
+        Y::CoordDescType: ValueFormatter<f64>,
+    {
         match self {
             DataView::Table | DataView::Csv => Ok(()),
-            DataView::ColumnGraph => draw_column_graph(df),
-            DataView::LineGraph => draw_line_graph(df),
-            DataView::ScatterPlot => draw_scatter_plot(df),
-            DataView::CumLineGraph => draw_cum_line_graph(df),
+            DataView::ColumnGraph => {
+                draw_column_graph(root, options, scale, options.x(df)?, options.y(df)?)
+            }
+            DataView::LineGraph => draw_line_graph(
+                root,
+                options,
+                scale,
+                options.x(df)?,
+                options.y(df)?,
+                options.series(df),
+                options.series_label(df),
+            ),
+            DataView::ScatterPlot => {
+                draw_scatter_plot(root, options, scale, options.x(df)?, options.y(df)?)
+            }
+            DataView::CumLineGraph => {
+                draw_cum_line_graph(root, options, scale, options.x(df)?, options.y(df)?)
+            }
+            DataView::Histogram => draw_histogram(root, options, scale, options.x(df)?),
+            DataView::BoxPlot => draw_box_plot(root, options, options.x(df)?, options.y(df)?),
+            DataView::Heatmap => draw_heatmap(root, options, options.x(df)?, options.y(df)?),
         }
     }
 }
 
-pub fn df_table_view(df: &DataFrame, min_width: bool) -> Html {
-    let style = if min_width {
-        "min-width: calc(min(568px, 100%))"
-    } else {
-        "min-width: 100%"
-    };
+fn axis_select(
+    label: &str,
+    df: &DataFrame,
+    selected: Option<&str>,
+    onchange: Callback<String>,
+) -> Html {
+    let onchange =
+        onchange.reform(|e: Event| e.target_unchecked_into::<HtmlSelectElement>().value());
     html! {
-        <div class="table-responsive">
-            <table class="table table-striped mb-0 w-auto" {style}>
-                <thead>
-                    <tr>
-                        <th>{"#"}</th>
-                        {for df.schema.fields.iter().map(|f| html! {
-                            <th>{&f.name()}</th>
-                        })}
-                    </tr>
-                </thead>
-                <tbody>{for (0..df.arrays[0].len()).map(|i| df_item_view(df, i))}</tbody>
-            </table>
+        <div class="input-group input-group-sm w-auto">
+            <span class="input-group-text">{label}</span>
+            <select class="form-select" {onchange}>
+                {for df.schema.fields.iter().map(|f| html! {
+                    <option selected={Some(f.name().as_str()) == selected}>{f.name()}</option>
+                })}
+            </select>
         </div>
     }
 }
 
+fn text_input(label: &str, placeholder: &str, value: &str, onchange: Callback<String>) -> Html {
+    let onchange =
+        onchange.reform(|e: Event| e.target_unchecked_into::<HtmlInputElement>().value());
+    html! {
+        <div class="input-group input-group-sm w-auto">
+            <span class="input-group-text">{label}</span>
+            <input type="text" class="form-control" style="max-width: 160px" placeholder={placeholder.to_string()} value={value.to_string()} {onchange}/>
+        </div>
+    }
+}
+
+/// Download a file by clicking a temporary link to a data URL
+fn download(filename: &str, svg: &str) -> Result<(), JsValue> {
+    let document = web_sys::window()
+        .expect("no global `window` exists")
+        .document()
+        .expect("should have a document on window");
+    let link = document.create_element("a")?.dyn_into::<HtmlElement>()?;
+    link.set_attribute(
+        "href",
+        &format!(
+            "data:image/svg+xml;charset=utf-8,{}",
+            String::from(js_sys::encode_uri_component(svg))
+        ),
+    )?;
+    link.set_attribute("download", filename)?;
+    link.click();
+    Ok(())
+}
+
+pub enum DataTableMsg {
+    Sort(String),
+    Filter(String),
+}
+
+#[derive(PartialEq, Properties)]
+pub struct DataTableProps {
+    pub df: DataFrame,
+    pub min_width: bool,
+}
+
+/// Table view that supports sorting by column and quick filters without re-querying
+pub struct DataTable {
+    sort: Option<(String, bool)>,
+    filter: String,
+}
+
+impl Component for DataTable {
+    type Message = DataTableMsg;
+    type Properties = DataTableProps;
+
+    fn create(_: &Context<Self>) -> Self {
+        DataTable {
+            sort: None,
+            filter: String::new(),
+        }
+    }
+
+    fn update(&mut self, _: &Context<Self>, msg: Self::Message) -> bool {
+        match msg {
+            // Toggle between ascending and descending order
+            DataTableMsg::Sort(column) => {
+                self.sort = match self.sort.take() {
+                    Some((c, descending)) if c == column => Some((c, !descending)),
+                    _ => Some((column, false)),
+                };
+            }
+            DataTableMsg::Filter(filter) => self.filter = filter,
+        }
+        true
+    }
+
+    fn changed(&mut self, ctx: &Context<Self>, old_props: &Self::Properties) -> bool {
+        // Reset the sort column if the query changed
+        if ctx.props().df.schema != old_props.df.schema {
+            self.sort = None;
+        }
+        true
+    }
+
+    fn view(&self, ctx: &Context<Self>) -> Html {
+        let mut df = ctx.props().df.clone();
+        if !self.filter.is_empty() {
+            if let Err(e) = df.filter(&quick_filter(&df, &self.filter)) {
+                web_sys::console::log_1(&JsValue::from(e.to_string()));
+            }
+        }
+        if let Some((column, descending)) = &self.sort {
+            if let Err(e) = df.sort(column, *descending) {
+                web_sys::console::log_1(&JsValue::from(e.to_string()));
+            }
+        }
+        let style = if ctx.props().min_width {
+            "min-width: calc(min(568px, 100%))"
+        } else {
+            "min-width: 100%"
+        };
+        let oninput = ctx.link().callback(|e: InputEvent| {
+            DataTableMsg::Filter(e.target_unchecked_into::<HtmlInputElement>().value())
+        });
+        html! {
+            <div>
+                <input type="search" class="form-control form-control-sm mb-2" style="max-width: 300px" placeholder="Filter" value={self.filter.clone()} {oninput}/>
+                <div class="table-responsive">
+                    <table class="table table-striped mb-0 w-auto" {style}>
+                        <thead>
+                            <tr>
+                                <th>{"#"}</th>
+                                {for df.schema.fields.iter().map(|f| {
+                                    let name = f.name().clone();
+                                    let arrow = match &self.sort {
+                                        Some((c, false)) if *c == name => " \u{25b2}",
+                                        Some((c, true)) if *c == name => " \u{25bc}",
+                                        _ => "",
+                                    };
+                                    let onclick = ctx.link().callback({
+                                        let name = name.clone();
+                                        move |_| DataTableMsg::Sort(name.clone())
+                                    });
+                                    html! {
+                                        <th class="text-nowrap" style="cursor: pointer" {onclick}>{name}{arrow}</th>
+                                    }
+                                })}
+                            </tr>
+                        </thead>
+                        <tbody>{for (0..df.num_rows()).map(|i| df_item_view(&df, i))}</tbody>
+                    </table>
+                </div>
+            </div>
+        }
+    }
+}
+
+/// Case-insensitive match against the displayed value of every column
+fn quick_filter(df: &DataFrame, filter: &str) -> BooleanArray {
+    let filter = filter.to_lowercase();
+    (0..df.num_rows())
+        .map(|i| {
+            Some(df.arrays.iter().any(|a| {
+                display::array_value_to_string(a, i)
+                    .map(|s| s.to_lowercase().contains(&filter))
+                    .unwrap_or(false)
+            }))
+        })
+        .collect()
+}
+
 fn df_item_view(df: &DataFrame, i: usize) -> Html {
     html! {
         <tr>
@@ -87,186 +553,560 @@ fn df_item_view(df: &DataFrame, i: usize) -> Html {
     }
 }
 
-fn draw_column_graph(df: &DataFrame) -> Result<(), Box<dyn std::error::Error>> {
-    let backend = CanvasBackend::new("canvas").expect("cannot find canvas");
-    let root = backend.into_drawing_area();
-
-    root.fill(&WHITE)?;
-
-    let mut builder = ChartBuilder::on(&root);
+fn chart_builder<'a, 'b, DB: DrawingBackend>(
+    root: &'a DrawingArea<DB, Shift>,
+    options: &'b ChartOptions,
+) -> ChartBuilder<'a, 'b, DB> {
+    let mut builder = ChartBuilder::on(root);
     builder
         .x_label_area_size(35)
         .y_label_area_size(40)
         .margin(5);
-    let range = compute::cast(&df.arrays[1], &DataType::Float64).unwrap();
-    let range = range.as_primitive::<Float64Type>();
-    match df.arrays[0].data_type() {
+    if !options.title.is_empty() {
+        builder.caption(&options.title, ("sans-serif", 20));
+    }
+    builder
+}
+
+/// Start the value axis at zero unless there are negative values
+///
+/// Log scale axes start at the power of ten below the smallest positive value instead and leave
+/// out the values that are not positive.
+fn value_range(
+    options: &ChartOptions,
+    values: &Float64Array,
+) -> Result<Range<f64>, Box<dyn std::error::Error>> {
+    if options.log_scale {
+        let values: Float64Array = values.iter().map(|v| v.filter(|v| *v > 0.)).collect();
+        let (Some(min), Some(max)) = (compute::min(&values), compute::max(&values)) else {
+            return Err("log scale needs positive values".into());
+        };
+        Ok(10f64.powf(min.log10().ceil() - 1.)..max)
+    } else {
+        let max = compute::max(values).unwrap_or(0.);
+        Ok(f64::min(0., compute::min(values).unwrap_or(0.))..max)
+    }
+}
+
+/// Charts with a single series use the same colour as the first line of a line graph
+fn series_color() -> RGBAColor {
+    Palette99::pick(0).mix(0.5)
+}
+
+fn draw_legend<'a, DB: DrawingBackend + 'a, CT: CoordTranslate>(
+    chart: &mut ChartContext<'a, DB, CT>,
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    DB::ErrorType: 'static,
+{
+    chart
+        .configure_series_labels()
+        .background_style(WHITE.mix(0.8))
+        .border_style(BLACK)
+        .draw()?;
+    Ok(())
+}
+
+fn draw_column_graph<DB: DrawingBackend, Y>(
+    root: &DrawingArea<DB, Shift>,
+    options: &ChartOptions,
+    scale: impl Fn(Range<f64>) -> Y,
+    (x_name, x): Column,
+    (y_name, y): Column,
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    DB::ErrorType: 'static,
+    Y: AsRangedCoord<Value = f64>,
+    Y::CoordDescType: ValueFormatter<f64>,
+{
+    let mut builder = chart_builder(root, options);
+    let color = series_color();
+    let legend = move |(x, y)| Rectangle::new([(x, y - 5), (x + 10, y + 5)], color.filled());
+    match x.data_type() {
         DataType::Int64 | DataType::UInt64 => {
-            let domain = compute::cast(&df.arrays[0], &DataType::UInt32).unwrap();
+            let domain = compute::cast(x, &DataType::UInt32).unwrap();
             let domain = domain.as_primitive::<UInt32Type>();
+            let range = compute::cast(y, &DataType::Float64).unwrap();
             let mut data = HashMap::new();
-            for (i, f) in domain.iter().zip(range) {
+            for (i, f) in domain.iter().zip(range.as_primitive::<Float64Type>()) {
                 *data.entry(i.unwrap()).or_insert(0f64) += f.unwrap();
             }
+            // Drop the sums that can't be shown on a log scale after summing
+            let (keys, sums): (Vec<_>, Vec<_>) = data.into_iter().unzip();
+            let sums = options.values(&(Arc::new(Float64Array::from(sums)) as ArrayRef))?;
             let domain = 0u32..compute::max(domain).unwrap();
-            let mut chart = builder.build_cartesian_2d(
-                domain.into_segmented(),
-                0f64..*data.values().max_by(|a, b| a.total_cmp(b)).unwrap(),
-            )?;
+            let range = value_range(options, &sums)?;
+            let baseline = range.start;
+            let mut chart = builder.build_cartesian_2d(domain.into_segmented(), scale(range))?;
             chart
                 .configure_mesh()
                 .disable_x_mesh()
                 .bold_line_style(WHITE.mix(0.3))
-                .y_desc(df.schema.fields[1].name())
-                .x_desc(df.schema.fields[0].name())
+                .y_desc(options.y_desc(y_name))
+                .x_desc(options.x_desc(x_name))
                 .axis_desc_style(("sans-serif", 15))
                 .draw()?;
-            chart.draw_series(
-                Histogram::vertical(&chart)
-                    .style(RED.mix(0.5).filled())
-                    .data(data.into_iter()),
-            )?;
+            chart
+                .draw_series(
+                    Histogram::vertical(&chart)
+                        .style(color.filled())
+                        .baseline(baseline)
+                        .data(
+                            keys.into_iter()
+                                .zip(&sums)
+                                .filter_map(|(i, f)| Some((i, f?))),
+                        ),
+                )?
+                .label(options.y_desc(y_name))
+                .legend(legend);
+            draw_legend(&mut chart)?;
         }
         DataType::LargeUtf8 => {
-            let domain: Vec<_> = df.arrays[0]
+            let values = options.values(y)?;
+            let domain: Vec<_> = x
                 .as_string::<i64>()
                 .into_iter()
                 .map(Option::unwrap)
                 .collect();
-            let range: Vec<_> = range.into_iter().map(Option::unwrap).collect();
-            let mut chart = builder.build_cartesian_2d(
-                domain.into_segmented(),
-                0f64..*range.iter().max_by(|a, b| a.total_cmp(b)).unwrap(),
-            )?;
+            let range = value_range(options, &values)?;
+            let baseline = range.start;
+            let mut chart = builder.build_cartesian_2d(domain.into_segmented(), scale(range))?;
             chart
                 .configure_mesh()
                 .disable_x_mesh()
                 .bold_line_style(WHITE.mix(0.3))
-                .y_desc(df.schema.fields[1].name())
-                .x_desc(df.schema.fields[0].name())
+                .y_desc(options.y_desc(y_name))
+                .x_desc(options.x_desc(x_name))
                 .axis_desc_style(("sans-serif", 15))
                 .draw()?;
-            chart.draw_series(
-                Histogram::vertical(&chart)
-                    .style(RED.mix(0.5).filled())
-                    .data(domain.iter().zip(range.into_iter())),
-            )?;
+            chart
+                .draw_series(
+                    Histogram::vertical(&chart)
+                        .style(color.filled())
+                        .baseline(baseline)
+                        .data(
+                            domain
+                                .iter()
+                                .zip(&values)
+                                .filter_map(|(x, f)| Some((x, f?))),
+                        ),
+                )?
+                .label(options.y_desc(y_name))
+                .legend(legend);
+            draw_legend(&mut chart)?;
         }
         _ => todo!(),
     }
     Ok(())
 }
 
-fn draw_line_graph(df: &DataFrame) -> Result<(), Box<dyn std::error::Error>> {
-    let backend = CanvasBackend::new("canvas").expect("cannot find canvas");
-    let root = backend.into_drawing_area();
-
-    root.fill(&WHITE)?;
-
-    let mut builder = ChartBuilder::on(&root);
-    builder
-        .x_label_area_size(35)
-        .y_label_area_size(40)
-        .margin(5);
-    let data = df_coords(df)?;
+fn draw_line_graph<DB: DrawingBackend, Y>(
+    root: &DrawingArea<DB, Shift>,
+    options: &ChartOptions,
+    scale: impl Fn(Range<f64>) -> Y,
+    x: Column,
+    y: Column,
+    series: Option<Column>,
+    series_label: Option<Column>,
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    DB::ErrorType: 'static,
+    Y: AsRangedCoord<Value = f64>,
+    Y::CoordDescType: ValueFormatter<f64>,
+{
+    let mut builder = chart_builder(root, options);
+    let data = df_coords(options, x, y)?;
+    let domain = compute::cast(x.1, &DataType::Float64)?;
+    let domain = domain.as_primitive::<Float64Type>();
+    // Timestamps are labelled as dates and start at the earliest value instead of zero
+    let unit = if let DataType::Timestamp(unit, _) = x.1.data_type() {
+        Some(*unit)
+    } else {
+        None
+    };
+    let min = if unit.is_some() {
+        compute::min(domain).unwrap()
+    } else {
+        0f64
+    };
     let mut chart = builder.build_cartesian_2d(
-        0f64..compute::max(
-            compute::cast(&df.arrays[0], &DataType::Float64)
-                .unwrap()
-                .as_primitive::<Float64Type>(),
-        )
-        .unwrap(),
-        0f64..compute::max(
-            compute::cast(&df.arrays[1], &DataType::Float64)
-                .unwrap()
-                .as_primitive::<Float64Type>(),
-        )
-        .unwrap(),
+        min..compute::max(domain).unwrap(),
+        scale(value_range(options, &options.values(y.1)?)?),
     )?;
-    chart.configure_mesh().draw()?;
-    chart.draw_series(LineSeries::new(data, BLACK))?;
+    let date_label = |x: &f64| format_date(*x, unit.unwrap_or(TimeUnit::Second));
+    let mut mesh = chart.configure_mesh();
+    mesh.y_desc(options.y_desc(y.0))
+        .x_desc(options.x_desc(x.0))
+        .axis_desc_style(("sans-serif", 15));
+    if unit.is_some() {
+        mesh.x_label_formatter(&date_label);
+    }
+    mesh.draw()?;
+    if let Some((_, series)) = series {
+        let keys = compute::cast(series, &DataType::Utf8)?;
+        let keys = keys.as_string::<i32>();
+        let labels: ArrayRef = match series_label {
+            Some((_, labels)) => compute::cast(labels, &DataType::Utf8)?,
+            None => Arc::new(keys.clone()),
+        };
+        let labels = labels.as_string::<i32>();
+        // Lines are ordered by first appearance and labelled with their first label
+        let mut lines: Vec<Line> = Vec::new();
+        for ((key, label), coord) in keys.iter().zip(labels).zip(data) {
+            let key = key.unwrap_or("null");
+            if let Some((_, _, line)) = lines.iter_mut().find(|(k, _, _)| *k == key) {
+                line.push(coord);
+            } else {
+                lines.push((key, label.unwrap_or("null"), vec![coord]));
+            }
+        }
+        for (i, (_, name, line)) in lines.into_iter().enumerate() {
+            let color = Palette99::pick(i).to_rgba();
+            chart
+                .draw_series(LineSeries::new(line, color.stroke_width(2)))?
+                .label(name)
+                .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], color));
+        }
+        draw_legend(&mut chart)?;
+    } else {
+        chart.draw_series(LineSeries::new(data, BLACK))?;
+    }
     Ok(())
 }
 
-fn draw_scatter_plot(df: &DataFrame) -> Result<(), Box<dyn std::error::Error>> {
-    let backend = CanvasBackend::new("canvas").expect("cannot find canvas");
-    let root = backend.into_drawing_area();
-
-    root.fill(&WHITE)?;
+/// Format a timestamp as YYYY-MM-DD in UTC
+fn format_date(timestamp: f64, unit: TimeUnit) -> String {
+    let seconds = match unit {
+        TimeUnit::Second => timestamp,
+        TimeUnit::Millisecond => timestamp / 1e3,
+        TimeUnit::Microsecond => timestamp / 1e6,
+        TimeUnit::Nanosecond => timestamp / 1e9,
+    };
+    // https://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = (seconds / 86400.).floor() as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!("{year:04}-{month:02}-{day:02}")
+}
 
-    let mut builder = ChartBuilder::on(&root);
-    builder
-        .x_label_area_size(35)
-        .y_label_area_size(40)
-        .margin(5);
-    let data = df_coords(df)?;
+fn draw_scatter_plot<DB: DrawingBackend, Y>(
+    root: &DrawingArea<DB, Shift>,
+    options: &ChartOptions,
+    scale: impl Fn(Range<f64>) -> Y,
+    x: Column,
+    y: Column,
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    DB::ErrorType: 'static,
+    Y: AsRangedCoord<Value = f64>,
+    Y::CoordDescType: ValueFormatter<f64>,
+{
+    let mut builder = chart_builder(root, options);
+    let data = df_coords(options, x, y)?;
     let mut chart = builder.build_cartesian_2d(
         0f64..compute::max(
-            compute::cast(&df.arrays[0], &DataType::Float64)
-                .unwrap()
-                .as_primitive::<Float64Type>(),
-        )
-        .unwrap(),
-        0f64..compute::max(
-            compute::cast(&df.arrays[1], &DataType::Float64)
+            compute::cast(x.1, &DataType::Float64)
                 .unwrap()
                 .as_primitive::<Float64Type>(),
         )
         .unwrap(),
+        scale(value_range(options, &options.values(y.1)?)?),
     )?;
     chart
         .configure_mesh()
         .disable_x_mesh()
         .disable_y_mesh()
+        .y_desc(options.y_desc(y.0))
+        .x_desc(options.x_desc(x.0))
+        .axis_desc_style(("sans-serif", 15))
         .draw()?;
-    chart.draw_series(data.into_iter().map(|c| Circle::new(c, 2, BLACK.filled())))?;
+    let color = Palette99::pick(0).to_rgba();
+    chart
+        .draw_series(data.into_iter().map(|c| Circle::new(c, 2, color.filled())))?
+        .label(options.y_desc(y.0))
+        .legend(move |c| Circle::new(c, 3, color.filled()));
+    draw_legend(&mut chart)?;
     Ok(())
 }
 
-fn draw_cum_line_graph(df: &DataFrame) -> Result<(), Box<dyn std::error::Error>> {
-    let backend = CanvasBackend::new("canvas").expect("cannot find canvas");
-    let root = backend.into_drawing_area();
-
-    root.fill(&WHITE)?;
-
-    let mut builder = ChartBuilder::on(&root);
-    builder
-        .x_label_area_size(35)
-        .y_label_area_size(40)
-        .margin(5);
-    let df = df_coords(df)?;
+fn draw_cum_line_graph<DB: DrawingBackend, Y>(
+    root: &DrawingArea<DB, Shift>,
+    options: &ChartOptions,
+    scale: impl Fn(Range<f64>) -> Y,
+    x: Column,
+    y: Column,
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    DB::ErrorType: 'static,
+    Y: AsRangedCoord<Value = f64>,
+    Y::CoordDescType: ValueFormatter<f64>,
+{
+    let mut builder = chart_builder(root, options);
+    let df = df_coords(options, x, y)?;
     let mut cum_sum = 0.0;
     let mut data = Vec::with_capacity(2 * df.len());
-    let mut max = 0.0;
-    for (d, f) in df {
+    for &(d, f) in &df {
         data.push((cum_sum, f));
         cum_sum += d;
         data.push((cum_sum, f));
-        max = f64::max(max, f);
     }
-    let mut chart = builder.build_cartesian_2d(0f64..cum_sum, 0f64..max)?;
-    chart.configure_mesh().draw()?;
-    chart.draw_series(LineSeries::new(data.into_iter(), BLACK))?;
+    let values = df.into_iter().map(|(_, f)| Some(f)).collect();
+    let mut chart =
+        builder.build_cartesian_2d(0f64..cum_sum, scale(value_range(options, &values)?))?;
+    chart
+        .configure_mesh()
+        .y_desc(options.y_desc(y.0))
+        .x_desc(options.x_desc(x.0))
+        .axis_desc_style(("sans-serif", 15))
+        .draw()?;
+    chart.draw_series(LineSeries::new(data, BLACK))?;
+    Ok(())
+}
+
+/// Count the values of a numeric column in sqrt(n) equal width bins
+fn draw_histogram<DB: DrawingBackend, Y>(
+    root: &DrawingArea<DB, Shift>,
+    options: &ChartOptions,
+    scale: impl Fn(Range<f64>) -> Y,
+    (x_name, x): Column,
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    DB::ErrorType: 'static,
+    Y: AsRangedCoord<Value = f64>,
+    Y::CoordDescType: ValueFormatter<f64>,
+{
+    let values = compute::cast(x, &DataType::Float64)?;
+    let values: Vec<_> = values
+        .as_primitive::<Float64Type>()
+        .iter()
+        .flatten()
+        .collect();
+    if values.is_empty() {
+        return Err(format!("{x_name} has no numeric values").into());
+    }
+    let (min, max) = values
+        .iter()
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), &v| {
+            (min.min(v), max.max(v))
+        });
+    let bins = (values.len() as f64).sqrt().ceil().clamp(1., 50.) as usize;
+    let width = if max > min {
+        (max - min) / bins as f64
+    } else {
+        1.
+    };
+    let mut counts = vec![0u32; bins];
+    for v in values {
+        counts[(((v - min) / width) as usize).min(bins - 1)] += 1;
+    }
+    // Log scale applies to the counts so that sparse bins are still visible
+    let counts: Float64Array = counts.into_iter().map(|count| Some(count as f64)).collect();
+    let counts = options.values(&(Arc::new(counts) as ArrayRef))?;
+
+    let mut builder = chart_builder(root, options);
+    let range = value_range(options, &counts)?;
+    let baseline = range.start;
+    let mut chart = builder.build_cartesian_2d(min..min + width * bins as f64, scale(range))?;
+    chart
+        .configure_mesh()
+        .disable_x_mesh()
+        .bold_line_style(WHITE.mix(0.3))
+        .y_desc(options.y_desc("count"))
+        .x_desc(options.x_desc(x_name))
+        .axis_desc_style(("sans-serif", 15))
+        .draw()?;
+    let color = series_color();
+    chart
+        .draw_series(counts.iter().enumerate().filter_map(|(i, count)| {
+            let x0 = min + width * i as f64;
+            count.map(|count| Rectangle::new([(x0, baseline), (x0 + width, count)], color.filled()))
+        }))?
+        .label(options.x_desc(x_name))
+        .legend(move |(x, y)| Rectangle::new([(x, y - 5), (x + 10, y + 5)], color.filled()));
+    draw_legend(&mut chart)?;
+    Ok(())
+}
+
+/// Draw the distribution of the y column for each distinct value of the x column
+fn draw_box_plot<DB: DrawingBackend>(
+    root: &DrawingArea<DB, Shift>,
+    options: &ChartOptions,
+    (x_name, x): Column,
+    (y_name, y): Column,
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    DB::ErrorType: 'static,
+{
+    let values = options.values(y)?;
+    let mut keys = Vec::new();
+    let mut groups: HashMap<_, Vec<f64>> = HashMap::new();
+    for i in 0..x.len() {
+        if values.is_null(i) {
+            continue;
+        }
+        let key = display::array_value_to_string(x, i)?;
+        if !groups.contains_key(&key) {
+            keys.push(key.clone());
+        }
+        groups.entry(key).or_default().push(values.value(i));
+    }
+    if keys.is_empty() {
+        return Err(format!("{y_name} has no numeric values").into());
+    }
+    let quartiles: Vec<_> = keys.iter().map(|k| Quartiles::new(&groups[k])).collect();
+    let (min, max) = quartiles
+        .iter()
+        .flat_map(Quartiles::values)
+        .fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), v| {
+            (min.min(v), max.max(v))
+        });
+    let names = (x_name, y_name);
+    if options.log_scale {
+        let range = (min / 1.2..max * 1.2).log_scale();
+        draw_boxes(root, options, names, &keys, &quartiles, range)
+    } else {
+        let margin = f32::max((max - min) * 0.05, 1.);
+        draw_boxes(
+            root,
+            options,
+            names,
+            &keys,
+            &quartiles,
+            min - margin..max + margin,
+        )
+    }
+}
+
+/// Quartiles are f32 so box plots build their own linear or log value axis
+fn draw_boxes<DB: DrawingBackend, Y>(
+    root: &DrawingArea<DB, Shift>,
+    options: &ChartOptions,
+    (x_name, y_name): (&str, &str),
+    keys: &[String],
+    quartiles: &[Quartiles],
+    range: Y,
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    DB::ErrorType: 'static,
+    Y: AsRangedCoord<Value = f32>,
+    Y::CoordDescType: ValueFormatter<f32>,
+{
+    let mut builder = chart_builder(root, options);
+    let mut chart = builder.build_cartesian_2d(keys.into_segmented(), range)?;
+    chart
+        .configure_mesh()
+        .disable_x_mesh()
+        .bold_line_style(WHITE.mix(0.3))
+        .y_desc(options.y_desc(y_name))
+        .x_desc(options.x_desc(x_name))
+        .axis_desc_style(("sans-serif", 15))
+        .draw()?;
+    chart.draw_series(
+        keys.iter()
+            .zip(quartiles)
+            .map(|(k, q)| Boxplot::new_vertical(SegmentValue::CenterOf(k), q).style(BLACK)),
+    )?;
+    Ok(())
+}
+
+/// Count the rows for each pair of x and y values
+fn draw_heatmap<DB: DrawingBackend>(
+    root: &DrawingArea<DB, Shift>,
+    options: &ChartOptions,
+    (x_name, x): Column,
+    (y_name, y): Column,
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    DB::ErrorType: 'static,
+{
+    let mut xs = Vec::new();
+    let mut ys = Vec::new();
+    let mut counts = HashMap::new();
+    for i in 0..x.len() {
+        if x.is_null(i) || y.is_null(i) {
+            continue;
+        }
+        let xi = category_index(&mut xs, display::array_value_to_string(x, i)?);
+        let yi = category_index(&mut ys, display::array_value_to_string(y, i)?);
+        *counts.entry((xi, yi)).or_insert(0u32) += 1;
+    }
+    let Some(&max) = counts.values().max() else {
+        return Err(format!("{x_name} and {y_name} have no values").into());
+    };
+    // Log scale applies to the colour intensity
+    let intensity = |count: u32| {
+        if options.log_scale {
+            (count as f64).ln_1p() / (max as f64).ln_1p()
+        } else {
+            count as f64 / max as f64
+        }
+    };
+
+    let mut builder = chart_builder(root, options);
+    let mut chart = builder.build_cartesian_2d(xs[..].into_segmented(), ys[..].into_segmented())?;
+    chart
+        .configure_mesh()
+        .disable_x_mesh()
+        .disable_y_mesh()
+        .y_desc(options.y_desc(y_name))
+        .x_desc(options.x_desc(x_name))
+        .axis_desc_style(("sans-serif", 15))
+        .draw()?;
+    chart.draw_series(counts.into_iter().map(|((i, j), count)| {
+        Rectangle::new(
+            [
+                (SegmentValue::Exact(&xs[i]), SegmentValue::Exact(&ys[j])),
+                (segment_end(&xs, i), segment_end(&ys, j)),
+            ],
+            RED.mix(intensity(count)).filled(),
+        )
+    }))?;
     Ok(())
 }
 
-fn df_coords(df: &DataFrame) -> Result<Vec<(f64, f64)>, Box<dyn std::error::Error>> {
-    let domain = compute::cast(&df.arrays[0], &DataType::Float64).unwrap();
+fn category_index(categories: &mut Vec<String>, value: String) -> usize {
+    if let Some(i) = categories.iter().position(|c| *c == value) {
+        i
+    } else {
+        categories.push(value);
+        categories.len() - 1
+    }
+}
+
+fn segment_end<T>(categories: &[T], i: usize) -> SegmentValue<&T> {
+    categories
+        .get(i + 1)
+        .map_or(SegmentValue::Last, SegmentValue::Exact)
+}
+
+fn df_coords(
+    options: &ChartOptions,
+    (x_name, x): Column,
+    (y_name, y): Column,
+) -> Result<Vec<(f64, f64)>, Box<dyn std::error::Error>> {
+    let domain = compute::cast(x, &DataType::Float64).unwrap();
     let domain = domain.as_primitive::<Float64Type>();
-    let range = compute::cast(&df.arrays[1], &DataType::Float64).unwrap();
-    let range = range.as_primitive::<Float64Type>();
+    let range = options.values(y)?;
+    // Values that can't be shown on a log scale are skipped
     domain
         .into_iter()
-        .zip(range)
+        .zip(&range)
+        .filter(|(_, o2)| o2.is_some() || !options.log_scale)
         .map(|(o1, o2)| {
             Ok((
                 o1.ok_or(format!(
-                    "unsupported data type for line graph: {:?}",
-                    df.arrays[0].data_type()
+                    "unsupported data type for {x_name}: {:?}",
+                    x.data_type()
                 ))?,
                 o2.ok_or(format!(
-                    "unsupported data type for line graph: {:?}",
-                    df.arrays[1].data_type()
+                    "unsupported data type for {y_name}: {:?}",
+                    y.data_type()
                 ))?,
             ))
         })
@@ -281,3 +1121,29 @@ fn write_csv(df: &DataFrame) -> String {
         .unwrap();
     String::from_utf8(writer.into_inner()).unwrap()
 }
+
+#[cfg(test)]
+mod test {
+    use super::ChartOptions;
+    use arrow::array::Float64Array;
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    #[wasm_bindgen_test]
+    fn test_value_range() {
+        let log_scale = ChartOptions {
+            log_scale: true,
+            ..Default::default()
+        };
+        let values = |values: &[f64]| Float64Array::from(values.to_vec());
+        assert_eq!(
+            super::value_range(&ChartOptions::default(), &values(&[-1., 3.])).unwrap(),
+            -1.0..3.
+        );
+        assert_eq!(
+            super::value_range(&log_scale, &values(&[-1., 0., 3., 250.])).unwrap(),
+            1.0..250.
+        );
+        assert!(super::value_range(&log_scale, &values(&[-1., 0.])).is_err());
+        assert!(super::value_range(&log_scale, &values(&[])).is_err());
+    }
+}
@@ -1,4 +1,8 @@
-use crate::{bootstrap::Collapse, dataframe::DataFrame, plot::DataView};
+use crate::{
+    bootstrap::Collapse,
+    dataframe::DataFrame,
+    plot::{ChartOptions, DataView},
+};
 use web_sys::{HtmlSelectElement, KeyboardEvent};
 use yew::{html, Component, Context, Html, NodeRef, Properties};
 
@@ -94,6 +98,14 @@ impl Component for Search {
                       <li>{"popularity - Spotify popularity of the track"}</li>
                       <li>{"track_number - The number of the track"}</li>
                     </ul>
+                    <p><em>{"College Football Item Fields"}</em></p>
+                    <p>{"Type is set to 'team' for college football items"}</p>
+                    <ul>
+                      <li>{"conference: string - The conference of the team"}</li>
+                      <li>{"season: number - The season of the record"}</li>
+                      <li>{"wins, losses and ties: number - The record of the team in the season"}</li>
+                      <li>{"conference_wins and conference_losses: number - The record of the team in conference games"}</li>
+                    </ul>
                   </Collapse>
                 </div>
                 if self.split_view {
@@ -118,6 +130,7 @@ pub enum Msg {
     Success(Option<DataFrame>),
     Failed(String),
     Select,
+    Options(ChartOptions),
     CreateList,
 }
 
@@ -127,6 +140,7 @@ pub struct SearchPane {
     error: Option<String>,
     select_ref: NodeRef,
     view: DataView,
+    options: ChartOptions,
 }
 
 impl Component for SearchPane {
@@ -140,6 +154,7 @@ impl Component for SearchPane {
             error: None,
             select_ref: NodeRef::default(),
             view: DataView::Table,
+            options: ChartOptions::default(),
         }
     }
 
@@ -165,16 +180,9 @@ impl Component for SearchPane {
             }
             Msg::Select => {
                 let view = self.select_ref.cast::<HtmlSelectElement>().unwrap().value();
-                self.view = match &*view {
-                    "Table" => DataView::Table,
-                    "Column Graph" => DataView::ColumnGraph,
-                    "Line Graph" => DataView::LineGraph,
-                    "Scatter Plot" => DataView::ScatterPlot,
-                    "Cumulative Line Graph" => DataView::CumLineGraph,
-                    "CSV" => DataView::Csv,
-                    _ => unreachable!(),
-                };
+                self.view = DataView::from_name(&view).unwrap();
             }
+            Msg::Options(options) => self.options = options,
             Msg::CreateList => {
                 let input = self.search_ref.cast::<HtmlSelectElement>().unwrap().value();
                 ctx.link().send_future(async move {
@@ -187,7 +195,7 @@ impl Component for SearchPane {
             }
         }
         if let Some(df) = &self.query {
-            if let Err(e) = self.view.draw(df) {
+            if let Err(e) = self.view.draw("canvas", df, &self.options) {
                 self.error = Some(e.to_string());
             }
         }
@@ -224,6 +232,9 @@ impl Component for SearchPane {
                         <option>{"Line Graph"}</option>
                         <option>{"Scatter Plot"}</option>
                         <option>{"Cumulative Line Graph"}</option>
+                        <option>{"Histogram"}</option>
+                        <option>{"Box Plot"}</option>
+                        <option>{"Heatmap"}</option>
                         <option>{"CSV"}</option>
                     </select>
                 </div>
@@ -241,7 +252,7 @@ impl Component for SearchPane {
                     </div>
                 </form>
                 if let Some(query) = &self.query {
-                    {self.view.render(query)}
+                    {self.view.render("canvas", query, &self.options, ctx.link().callback(Msg::Options))}
                 }
             </div>
         }
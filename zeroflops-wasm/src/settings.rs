@@ -1,24 +1,92 @@
-use yew::{html, Component, Context, Html, Properties};
+use web_sys::HtmlInputElement;
+use yew::{html, Component, Context, Html, NodeRef, Properties};
 use zeroflops::User;
 
+pub enum Msg {
+    Import,
+    Imported(Result<String, String>),
+}
+
 #[derive(Eq, PartialEq, Properties)]
 pub struct SettingsProps {
     pub user: User,
 }
 
-pub struct Settings;
+pub struct Settings {
+    history_ref: NodeRef,
+    importing: bool,
+    import_status: Option<Result<String, String>>,
+}
 
 impl Component for Settings {
-    type Message = ();
+    type Message = Msg;
     type Properties = SettingsProps;
 
     fn create(_: &Context<Self>) -> Self {
-        Settings
+        Settings {
+            history_ref: NodeRef::default(),
+            importing: false,
+            import_status: None,
+        }
+    }
+
+    fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
+        match msg {
+            Msg::Import => {
+                let Some(files) = self.history_ref.cast::<HtmlInputElement>().unwrap().files()
+                else {
+                    return false;
+                };
+                let files: Vec<_> = (0..files.length()).filter_map(|i| files.get(i)).collect();
+                if files.is_empty() {
+                    return false;
+                }
+                self.importing = true;
+                ctx.link().send_future(async move {
+                    let mut plays = 0;
+                    let mut new_items = 0;
+                    for file in files {
+                        match crate::import_streaming_history(&file).await {
+                            Ok(summary) => {
+                                plays += summary.plays;
+                                new_items += summary.new_items;
+                            }
+                            Err(e) => {
+                                return Msg::Imported(Err(format!(
+                                    "{}: {}",
+                                    file.name(),
+                                    e.as_string().unwrap_or_default()
+                                )))
+                            }
+                        }
+                    }
+                    Msg::Imported(Ok(format!(
+                        "Imported {plays} plays and {new_items} new items"
+                    )))
+                });
+                true
+            }
+            Msg::Imported(status) => {
+                self.importing = false;
+                self.import_status = Some(status);
+                true
+            }
+        }
     }
 
     fn view(&self, ctx: &Context<Self>) -> Html {
         let window = crate::window();
         let location = window.location();
+        let import = ctx.link().callback(|_| Msg::Import);
+        let import_status = match &self.import_status {
+            Some(Ok(status)) => html! {
+                <div class="alert alert-success" role="alert">{status}</div>
+            },
+            Some(Err(error)) => html! {
+                <div class="alert alert-danger" role="alert">{error}</div>
+            },
+            None => html! {},
+        };
         // TODO: let you remove integrations
         // Should we link to Google profile?
         crate::nav_content(
@@ -34,8 +102,15 @@ impl Component for Settings {
                 if let (Some(url), Some(user)) = (&ctx.props().user.spotify_url, &ctx.props().user.spotify_user) {
                   <a href={url.clone()}>{&user}</a>
                 } else {
-                  <a class="btn btn-success" href={format!("https://accounts.spotify.com/authorize?client_id=ee3d1b4f8d80477ea48743a511ef3018&redirect_uri={}/api/login&response_type=code&scope=playlist-modify-public playlist-modify-private user-read-recently-played playlist-read-private", location.origin().unwrap().as_str())}>{"Log in with Spotify"}</a>
+                  <a class="btn btn-success" href={format!("https://accounts.spotify.com/authorize?client_id=ee3d1b4f8d80477ea48743a511ef3018&redirect_uri={}/api/login&response_type=code&scope=playlist-modify-public playlist-modify-private user-read-recently-played playlist-read-private user-library-read user-top-read", location.origin().unwrap().as_str())}>{"Log in with Spotify"}</a>
                 }
+                <h4 class="mt-3">{"Streaming history"}</h4>
+                <p>{"Import the Streaming_History_Audio files from your extended streaming history to query play counts and listening time with the play table."}</p>
+                <div class="d-flex gap-3 mb-3" style="max-width: 800px">
+                  <input class="form-control" type="file" accept=".json" multiple=true ref={&self.history_ref}/>
+                  <button type="button" class="btn btn-primary" onclick={import} disabled={self.importing}>{"Import"}</button>
+                </div>
+                {import_status}
                 <h2>{"Google"}</h2>
                 if let Some(google_email) = &ctx.props().user.google_email {
                   <p>{google_email}</p>
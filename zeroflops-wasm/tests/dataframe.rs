@@ -0,0 +1,182 @@
+use arrow::{
+    array::{ArrayRef, AsArray, BooleanArray, Float64Array, Int64Array, LargeStringArray},
+    datatypes::{DataType, Field, Float64Type, Int64Type, Schema},
+};
+use std::sync::Arc;
+use wasm_bindgen_test::wasm_bindgen_test;
+use zeroflops_wasm::dataframe::{Aggregate, DataFrame};
+
+fn df() -> DataFrame {
+    DataFrame {
+        schema: Arc::new(Schema::new(vec![
+            Field::new("name", DataType::LargeUtf8, true),
+            Field::new("album", DataType::LargeUtf8, true),
+            Field::new("user_score", DataType::Int64, true),
+        ])),
+        arrays: vec![
+            Arc::new(LargeStringArray::from(vec!["a", "b", "c", "d"])) as ArrayRef,
+            Arc::new(LargeStringArray::from(vec!["x", "y", "x", "y"])),
+            Arc::new(Int64Array::from(vec![
+                Some(1500),
+                Some(1530),
+                None,
+                Some(1470),
+            ])),
+        ],
+    }
+}
+
+fn names(df: &DataFrame) -> Vec<&str> {
+    df.column("name")
+        .unwrap()
+        .as_string::<i64>()
+        .iter()
+        .map(Option::unwrap)
+        .collect()
+}
+
+#[wasm_bindgen_test]
+fn test_drop_in_place() {
+    let mut df = df();
+    assert!(df.drop_in_place("album").is_some());
+    assert_eq!(
+        df.schema
+            .fields
+            .iter()
+            .map(|f| f.name().as_str())
+            .collect::<Vec<_>>(),
+        vec!["name", "user_score"]
+    );
+    assert_eq!(df.arrays.len(), 2);
+    assert!(df.drop_in_place("album").is_none());
+}
+
+#[wasm_bindgen_test]
+fn test_filter() {
+    let mut df = df();
+    df.filter(&BooleanArray::from(vec![true, false, false, true]))
+        .unwrap();
+    assert_eq!(names(&df), vec!["a", "d"]);
+
+    let mut df = self::df();
+    df.remove(vec![false, true, true, false]);
+    assert_eq!(names(&df), vec!["b", "c"]);
+}
+
+#[wasm_bindgen_test]
+fn test_sort() {
+    let mut df = df();
+    df.sort("user_score", false).unwrap();
+    assert_eq!(names(&df), vec!["d", "a", "b", "c"]);
+    df.sort("user_score", true).unwrap();
+    assert_eq!(names(&df), vec!["b", "a", "d", "c"]);
+    // Sorting is stable
+    df.sort("album", false).unwrap();
+    assert_eq!(names(&df), vec!["a", "c", "b", "d"]);
+    assert!(df.sort("missing", false).is_err());
+}
+
+#[wasm_bindgen_test]
+fn test_head() {
+    let mut df = df();
+    df.sort("user_score", true).unwrap();
+    df.head(2);
+    assert_eq!(names(&df), vec!["b", "a"]);
+    df.head(5);
+    assert_eq!(names(&df), vec!["b", "a"]);
+}
+
+#[wasm_bindgen_test]
+fn test_group_by() {
+    let df = df()
+        .group_by(
+            &["album"],
+            &[
+                ("user_score", Aggregate::Count),
+                ("user_score", Aggregate::Mean),
+                ("user_score", Aggregate::Max),
+            ],
+        )
+        .unwrap();
+    assert_eq!(
+        df.schema
+            .fields
+            .iter()
+            .map(|f| f.name().as_str())
+            .collect::<Vec<_>>(),
+        vec![
+            "album",
+            "COUNT(user_score)",
+            "AVG(user_score)",
+            "MAX(user_score)"
+        ]
+    );
+    assert_eq!(
+        df.arrays[0].as_string::<i64>().iter().collect::<Vec<_>>(),
+        vec![Some("x"), Some("y")]
+    );
+    assert_eq!(
+        df.arrays[1]
+            .as_primitive::<Int64Type>()
+            .iter()
+            .collect::<Vec<_>>(),
+        vec![Some(1), Some(2)]
+    );
+    assert_eq!(
+        df.arrays[2]
+            .as_primitive::<Float64Type>()
+            .iter()
+            .collect::<Vec<_>>(),
+        vec![Some(1500.), Some(1500.)]
+    );
+    assert_eq!(
+        df.arrays[3]
+            .as_primitive::<Float64Type>()
+            .iter()
+            .collect::<Vec<_>>(),
+        vec![Some(1500.), Some(1530.)]
+    );
+}
+
+#[wasm_bindgen_test]
+fn test_group_by_count_strings() {
+    let mut df = df();
+    df.with_column(
+        "artist",
+        Arc::new(LargeStringArray::from(vec![
+            Some("p"),
+            None,
+            Some("q"),
+            Some("r"),
+        ])),
+    )
+    .unwrap();
+    let df = df
+        .group_by(&["album"], &[("artist", Aggregate::Count)])
+        .unwrap();
+    assert_eq!(
+        df.arrays[1]
+            .as_primitive::<Int64Type>()
+            .iter()
+            .collect::<Vec<_>>(),
+        vec![Some(2), Some(1)]
+    );
+}
+
+#[wasm_bindgen_test]
+fn test_with_computed_column() {
+    let mut df = df();
+    df.with_computed_column("delta", &["user_score"], |row| row[0].map(|s| s - 1500.))
+        .unwrap();
+    assert_eq!(
+        df.column("delta")
+            .unwrap()
+            .as_primitive::<Float64Type>()
+            .iter()
+            .collect::<Vec<_>>(),
+        vec![Some(0.), Some(30.), None, Some(-30.)]
+    );
+    assert!(df
+        .with_column("delta", Arc::new(Float64Array::from(vec![0.])))
+        .is_err());
+}
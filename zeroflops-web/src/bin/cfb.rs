@@ -0,0 +1,45 @@
+//! Import college football teams and games from CollegeFootballData CSV or JSON files
+//!
+//! Usage: cfb <user ID> <list ID> [--seed-matches] <teams or games file>...
+//!
+//! With --seed-matches the results of the games are also played as matches between the teams.
+use zeroflops::{storage::SqlSessionClient, UserId};
+use zeroflops_web::source::{self, cfb, Registry};
+
+#[tokio::main]
+async fn main() {
+    let mut args = std::env::args().skip(1);
+    let user_id = UserId(args.next().expect("Pass a user ID first!"));
+    let list_id = args.next().expect("Pass a list ID second!");
+    let (flags, paths): (Vec<_>, Vec<_>) = args.partition(|arg| arg.starts_with("--"));
+    let seed_matches = flags.iter().any(|flag| flag == "--seed-matches");
+    let files = paths
+        .into_iter()
+        .map(|path| {
+            let data = std::fs::read(&path).unwrap();
+            let file_name = std::path::Path::new(&path)
+                .file_name()
+                .unwrap()
+                .to_string_lossy()
+                .into_owned();
+            (file_name, data)
+        })
+        .collect();
+    let client = SqlSessionClient { path: "data" };
+    client.create_tables().unwrap();
+    let list = source::get_list(&client, &user_id, &list_id).await.unwrap();
+    let summary = cfb::import_cfb(
+        &client,
+        &Registry::default(),
+        &user_id,
+        list,
+        files,
+        seed_matches,
+    )
+    .await
+    .unwrap();
+    println!(
+        "{} teams, {} games, {} matches",
+        summary.teams, summary.games, summary.matches
+    );
+}
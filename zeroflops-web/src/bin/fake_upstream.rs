@@ -0,0 +1,14 @@
+//! Serve the fake upstream APIs for running the app offline
+//!
+//! Set SPOTIFY_API_URL, SPOTIFY_ACCOUNTS_URL, SETLIST_API_URL, GOOGLE_OAUTH_URL and
+//! GOOGLE_USERINFO_URL to the printed URL.
+use std::net::SocketAddr;
+use zeroflops_web::fake_upstream;
+
+#[tokio::main]
+async fn main() {
+    let addr = SocketAddr::from(([127, 0, 0, 1], 3001));
+    let listener = tokio::net::TcpListener::bind(&addr).await.unwrap();
+    println!("Serving fake upstream APIs on http://{addr}");
+    fake_upstream::serve(listener).await;
+}
@@ -0,0 +1,27 @@
+//! Export a list as a playlist file for local players
+//!
+//! Usage: playlist <user ID> <list ID> <m3u8|xspf|jspf> [output file]
+//!
+//! The playlist is written to stdout without an output file.
+use zeroflops::{storage::SqlSessionClient, UserId};
+use zeroflops_web::{export, source};
+
+#[tokio::main]
+async fn main() {
+    let mut args = std::env::args().skip(1);
+    let user_id = UserId(args.next().expect("Pass a user ID first!"));
+    let list_id = args.next().expect("Pass a list ID second!");
+    let format = export::PlaylistFormat::from_extension(
+        &args.next().expect("Pass a playlist format third!"),
+    )
+    .unwrap();
+    let client = SqlSessionClient { path: "data" };
+    let list = source::get_list(&client, &user_id, &list_id).await.unwrap();
+    let playlist = export::export_list(&client, &user_id, list, format)
+        .await
+        .unwrap();
+    match args.next() {
+        Some(path) => std::fs::write(path, playlist).unwrap(),
+        None => print!("{playlist}"),
+    }
+}
@@ -0,0 +1,23 @@
+//! Import Spotify extended streaming history files for a user
+//!
+//! Usage: streaming_history <user ID> <Streaming_History_Audio_*.json>...
+use zeroflops::{storage::SqlSessionClient, UserId};
+use zeroflops_web::play;
+
+#[tokio::main]
+async fn main() {
+    let mut args = std::env::args().skip(1);
+    let user_id = UserId(args.next().expect("Pass a user ID first!"));
+    let client = SqlSessionClient { path: "data" };
+    client.create_tables().unwrap();
+    for path in args {
+        let data = std::fs::read(&path).unwrap();
+        let summary = play::import_streaming_history(&client, &user_id, &data)
+            .await
+            .unwrap();
+        println!(
+            "{path}: {} plays, {} new items",
+            summary.plays, summary.new_items
+        );
+    }
+}
@@ -0,0 +1,281 @@
+use crate::query::{self, IntoQuery};
+use serde_json::{Map, Value};
+use zeroflops::{
+    storage::{
+        CosmosParam, CosmosQuery, CreateDocumentBuilder, DeleteDocumentBuilder, DocumentWriter,
+        GetDocumentBuilder, QueryDocumentsBuilder, ReplaceDocumentBuilder, SessionClient, View,
+    },
+    Dashboard, Error, InternalError, List, RawDashboard, RawList, UserId,
+};
+
+pub async fn get_dashboard(
+    client: &impl SessionClient,
+    user_id: &UserId,
+    id: &str,
+) -> Result<Dashboard, Error> {
+    if let Some(dashboard) = client
+        .get_document::<RawDashboard>(GetDocumentBuilder::new(
+            "dashboard",
+            id.to_owned(),
+            View::User(user_id.clone()),
+        ))
+        .await?
+    {
+        dashboard.try_into()
+    } else if let Some(dashboard) = client
+        .get_document::<RawDashboard>(GetDocumentBuilder::new(
+            "dashboard",
+            id.to_owned(),
+            View::Public,
+        ))
+        .await?
+    {
+        dashboard.try_into()
+    } else {
+        Err(Error::NotFound)
+    }
+}
+
+pub async fn create_dashboard(
+    client: &impl SessionClient,
+    dashboard: Dashboard,
+) -> Result<(), Error> {
+    client
+        .write_document(DocumentWriter::Create(CreateDocumentBuilder {
+            collection_name: "dashboard",
+            document: RawDashboard::from(dashboard),
+            is_upsert: false,
+        }))
+        .await
+}
+
+pub async fn update_dashboard(
+    client: &impl SessionClient,
+    user_id: &UserId,
+    mut dashboard: Dashboard,
+) -> Result<(), Error> {
+    // The owner can't be changed
+    dashboard.user_id.clone_from(&user_id.0);
+    client
+        .write_document(DocumentWriter::Replace(ReplaceDocumentBuilder {
+            collection_name: "dashboard",
+            document_name: dashboard.id.clone(),
+            partition_key: user_id.clone(),
+            document: RawDashboard::from(dashboard),
+        }))
+        .await
+}
+
+pub async fn delete_dashboard(
+    client: &impl SessionClient,
+    user_id: &UserId,
+    id: &str,
+) -> Result<(), Error> {
+    // Public dashboards can be read by anyone so check the owner before deleting
+    if client
+        .get_document::<RawDashboard>(GetDocumentBuilder::new(
+            "dashboard",
+            id.to_owned(),
+            View::User(user_id.clone()),
+        ))
+        .await?
+        .is_none()
+    {
+        return Err(Error::NotFound);
+    }
+    client
+        .write_document(DocumentWriter::<RawDashboard>::Delete(
+            DeleteDocumentBuilder {
+                collection_name: "dashboard",
+                document_name: id.to_owned(),
+                partition_key: user_id.clone(),
+            },
+        ))
+        .await
+}
+
+/// Run the stored query of a panel
+///
+/// Panels query the items of the dashboard owner. Other users of public dashboards only see the
+/// items in the public lists of the owner.
+pub async fn query_panel(
+    client: &impl SessionClient,
+    user_id: &UserId,
+    id: &str,
+    index: usize,
+) -> Result<Vec<Map<String, Value>>, Error> {
+    let dashboard = get_dashboard(client, user_id, id).await?;
+    let Some(panel) = dashboard.panels.get(index) else {
+        return Err(Error::NotFound);
+    };
+    let (query, _) = query::rewrite_query(&panel.query)?;
+    let view = if dashboard.user_id == user_id.0 {
+        View::User(user_id.clone())
+    } else {
+        View::PublicList(public_item_ids(client, dashboard.user_id).await?)
+    };
+    client
+        .query_documents(QueryDocumentsBuilder::new(
+            "item",
+            view,
+            CosmosQuery::new(query),
+        ))
+        .await
+        .map_err(|e| match e {
+            Error::InternalError(InternalError::SqlError(e)) => Error::client_error(e.to_string()),
+            e => e,
+        })
+}
+
+async fn public_item_ids(
+    client: &impl SessionClient,
+    user_id: String,
+) -> Result<Vec<String>, Error> {
+    let lists = client
+        .query_documents::<RawList>(QueryDocumentsBuilder::new(
+            "list",
+            View::Public,
+            CosmosQuery::with_params(
+                "SELECT * FROM list WHERE user_id = ?".into_query()?,
+                vec![CosmosParam::new(String::from("@user_id"), user_id)],
+            ),
+        ))
+        .await?;
+    let mut ids = Vec::new();
+    for list in lists {
+        ids.extend(List::try_from(list)?.items.into_iter().map(|i| i.id));
+    }
+    Ok(ids)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::query::test::{Mock, TestSessionClient};
+    use zeroflops::{
+        storage::{DocumentWriter, GetDocumentBuilder, ReplaceDocumentBuilder, View},
+        Dashboard, Error, Panel, UserId,
+    };
+
+    const DASHBOARD: &str = r#"{"id":"dashboard","user_id":"owner","name":"Dashboard","panels":"[{\"title\":\"Scores\",\"query\":\"SELECT name, user_score FROM item\",\"view\":\"Table\"}]","public":true}"#;
+
+    #[tokio::test]
+    async fn test_get_public_dashboard() {
+        let client = TestSessionClient {
+            get_mock: Mock::new(vec!["null", DASHBOARD]),
+            query_mock: Mock::empty(),
+            write_mock: Mock::empty(),
+        };
+        let dashboard = super::get_dashboard(&client, &UserId("user".to_owned()), "dashboard")
+            .await
+            .unwrap();
+        assert_eq!(dashboard.user_id, "owner");
+        assert_eq!(
+            dashboard.panels,
+            vec![Panel {
+                title: "Scores".to_owned(),
+                query: "SELECT name, user_score FROM item".to_owned(),
+                view: "Table".to_owned(),
+            }]
+        );
+        assert_eq!(
+            *client.get_mock.call_args.lock().unwrap(),
+            vec![
+                GetDocumentBuilder::new(
+                    "dashboard",
+                    "dashboard".to_owned(),
+                    View::User(UserId("user".to_owned()))
+                ),
+                GetDocumentBuilder::new("dashboard", "dashboard".to_owned(), View::Public),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_update_dashboard_keeps_owner() {
+        let client = TestSessionClient {
+            get_mock: Mock::empty(),
+            query_mock: Mock::empty(),
+            write_mock: Mock::new(vec![()]),
+        };
+        let mut dashboard = Dashboard::new(
+            "dashboard".to_owned(),
+            &UserId("other".to_owned()),
+            "Dashboard".to_owned(),
+        );
+        dashboard.public = true;
+        super::update_dashboard(&client, &UserId("user".to_owned()), dashboard)
+            .await
+            .unwrap();
+        assert_eq!(
+            *client.write_mock.call_args.lock().unwrap(),
+            vec![DocumentWriter::Replace(ReplaceDocumentBuilder {
+                collection_name: "dashboard",
+                document_name: "dashboard".to_owned(),
+                partition_key: UserId("user".to_owned()),
+                document: r#"{"id":"dashboard","user_id":"user","name":"Dashboard","panels":"[]","public":true}"#.to_owned(),
+            })]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_delete_other_users_dashboard() {
+        let client = TestSessionClient {
+            get_mock: Mock::new(vec!["null"]),
+            query_mock: Mock::empty(),
+            write_mock: Mock::empty(),
+        };
+        let result =
+            super::delete_dashboard(&client, &UserId("user".to_owned()), "dashboard").await;
+        assert!(matches!(result, Err(Error::NotFound)));
+        assert!(client.write_mock.call_args.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_query_panel_uses_owner_items() {
+        let client = TestSessionClient {
+            get_mock: Mock::new(vec![DASHBOARD]),
+            query_mock: Mock::new(vec![r#"[{"name":"a","user_score":1500}]"#]),
+            write_mock: Mock::empty(),
+        };
+        let records = super::query_panel(&client, &UserId("owner".to_owned()), "dashboard", 0)
+            .await
+            .unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(
+            client.query_mock.call_args.lock().unwrap()[0].partition_key,
+            View::User(UserId("owner".to_owned()))
+        );
+
+        let client = TestSessionClient {
+            get_mock: Mock::new(vec![DASHBOARD]),
+            query_mock: Mock::empty(),
+            write_mock: Mock::empty(),
+        };
+        let result = super::query_panel(&client, &UserId("owner".to_owned()), "dashboard", 1).await;
+        assert!(matches!(result, Err(Error::NotFound)));
+    }
+
+    #[tokio::test]
+    async fn test_query_public_dashboard_panel() {
+        let client = TestSessionClient {
+            get_mock: Mock::new(vec!["null", DASHBOARD]),
+            query_mock: Mock::new(vec![
+                r#"[{"id":"list","user_id":"owner","mode":"{\"User\":null}","name":"List","sources":"[]","items":"[{\"id\":\"a\",\"name\":\"a\",\"score\":1500,\"wins\":0,\"losses\":0}]","favorite":false,"query":"SELECT name, user_score FROM c","public":true}]"#,
+                r#"[{"name":"a","user_score":1500}]"#,
+            ]),
+            write_mock: Mock::empty(),
+        };
+        let records = super::query_panel(&client, &UserId("user".to_owned()), "dashboard", 0)
+            .await
+            .unwrap();
+        assert_eq!(records.len(), 1);
+        let call_args = client.query_mock.call_args.lock().unwrap();
+        // Other users only see the items in the public lists of the owner
+        assert_eq!(call_args[0].collection_name, "list");
+        assert_eq!(call_args[0].partition_key, View::Public);
+        assert_eq!(
+            call_args[1].partition_key,
+            View::PublicList(vec!["a".to_owned()])
+        );
+    }
+}
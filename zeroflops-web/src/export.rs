@@ -0,0 +1,296 @@
+//! Playlist files of lists for local players
+use crate::{query::IntoQuery, Item, RawItem};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use zeroflops::{
+    storage::{CosmosQuery, QueryDocumentsBuilder, SessionClient, View},
+    Error, List, UserId,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PlaylistFormat {
+    M3u8,
+    Xspf,
+    Jspf,
+}
+
+impl PlaylistFormat {
+    pub fn from_extension(extension: &str) -> Result<PlaylistFormat, Error> {
+        match extension {
+            "m3u8" => Ok(PlaylistFormat::M3u8),
+            "xspf" => Ok(PlaylistFormat::Xspf),
+            "jspf" => Ok(PlaylistFormat::Jspf),
+            _ => Err(Error::client_error(
+                "Playlist format must be m3u8, xspf or jspf",
+            )),
+        }
+    }
+
+    /// File name for a playlist of the list
+    pub fn file_name(self, list_name: &str) -> String {
+        let name: String = list_name
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || c == '-' || c == ' ' {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        format!("{}.{}", name.trim(), self.extension())
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            PlaylistFormat::M3u8 => "m3u8",
+            PlaylistFormat::Xspf => "xspf",
+            PlaylistFormat::Jspf => "jspf",
+        }
+    }
+
+    pub fn content_type(self) -> &'static str {
+        match self {
+            PlaylistFormat::M3u8 => "audio/x-mpegurl; charset=utf-8",
+            PlaylistFormat::Xspf => "application/xspf+xml",
+            PlaylistFormat::Jspf => "application/json",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Track {
+    pub location: String,
+    pub title: String,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub duration_ms: Option<i64>,
+}
+
+impl From<Item> for Track {
+    fn from(item: Item) -> Track {
+        let artists: Vec<_> = item
+            .metadata
+            .get("artists")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+            .filter_map(Value::as_str)
+            .collect();
+        Track {
+            artist: (!artists.is_empty()).then(|| artists.join(", ")),
+            album: item
+                .metadata
+                .get("album")
+                .and_then(Value::as_str)
+                .map(ToOwned::to_owned),
+            duration_ms: item.metadata.get("duration_ms").and_then(Value::as_i64),
+            location: item.id,
+            title: item.name,
+        }
+    }
+}
+
+/// Render the items of a list, in the order of its query, as a playlist
+pub async fn export_list(
+    client: &impl SessionClient,
+    user_id: &UserId,
+    list: List,
+    format: PlaylistFormat,
+) -> Result<String, Error> {
+    let name = list.name.clone();
+    let ids = crate::query::get_item_ids(client, user_id, list).await?;
+    let mut items: HashMap<_, _> = if ids.is_empty() {
+        HashMap::new()
+    } else {
+        client
+            .query_documents::<RawItem>(QueryDocumentsBuilder::new(
+                "item",
+                View::List(user_id.clone(), ids.clone()),
+                CosmosQuery::new("SELECT * FROM item".into_query()?),
+            ))
+            .await?
+            .into_iter()
+            .map(|item| Ok((item.id.clone(), Item::try_from(item)?)))
+            .collect::<Result<_, Error>>()?
+    };
+    let tracks: Vec<_> = ids
+        .iter()
+        .filter_map(|id| items.remove(id))
+        .map(Track::from)
+        .collect();
+    Ok(render(format, &name, &tracks))
+}
+
+pub fn render(format: PlaylistFormat, name: &str, tracks: &[Track]) -> String {
+    match format {
+        PlaylistFormat::M3u8 => render_m3u8(name, tracks),
+        PlaylistFormat::Xspf => render_xspf(name, tracks),
+        PlaylistFormat::Jspf => render_jspf(name, tracks),
+    }
+}
+
+fn render_m3u8(name: &str, tracks: &[Track]) -> String {
+    let mut playlist = format!("#EXTM3U\n#PLAYLIST:{}\n", single_line(name));
+    for track in tracks {
+        // -1 is the duration for unknown lengths
+        let duration = track.duration_ms.map_or(-1, |d| d / 1000);
+        let title = match &track.artist {
+            Some(artist) => format!("{} - {}", artist, track.title),
+            None => track.title.clone(),
+        };
+        playlist.push_str(&format!("#EXTINF:{},{}\n", duration, single_line(&title)));
+        if let Some(album) = &track.album {
+            playlist.push_str(&format!("#EXTALB:{}\n", single_line(album)));
+        }
+        playlist.push_str(&single_line(&track.location));
+        playlist.push('\n');
+    }
+    playlist
+}
+
+fn single_line(s: &str) -> String {
+    s.replace(['\r', '\n'], " ")
+}
+
+fn render_xspf(name: &str, tracks: &[Track]) -> String {
+    let mut playlist = format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<playlist version=\"1\" xmlns=\"http://xspf.org/ns/0/\">\n  <title>{}</title>\n  <trackList>\n",
+        escape_xml(name)
+    );
+    for track in tracks {
+        playlist.push_str("    <track>\n");
+        playlist.push_str(&format!(
+            "      <location>{}</location>\n",
+            escape_xml(&track.location)
+        ));
+        playlist.push_str(&format!(
+            "      <title>{}</title>\n",
+            escape_xml(&track.title)
+        ));
+        if let Some(artist) = &track.artist {
+            playlist.push_str(&format!(
+                "      <creator>{}</creator>\n",
+                escape_xml(artist)
+            ));
+        }
+        if let Some(album) = &track.album {
+            playlist.push_str(&format!("      <album>{}</album>\n", escape_xml(album)));
+        }
+        if let Some(duration) = track.duration_ms {
+            playlist.push_str(&format!("      <duration>{}</duration>\n", duration));
+        }
+        playlist.push_str("    </track>\n");
+    }
+    playlist.push_str("  </trackList>\n</playlist>\n");
+    playlist
+}
+
+fn escape_xml(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+fn render_jspf(name: &str, tracks: &[Track]) -> String {
+    let tracks: Vec<_> = tracks
+        .iter()
+        .map(|track| {
+            let mut value = json!({
+                "location": [track.location],
+                "title": track.title,
+            });
+            if let Some(artist) = &track.artist {
+                value["creator"] = json!(artist);
+            }
+            if let Some(album) = &track.album {
+                value["album"] = json!(album);
+            }
+            if let Some(duration) = track.duration_ms {
+                value["duration"] = json!(duration);
+            }
+            value
+        })
+        .collect();
+    serde_json::to_string_pretty(&json!({"playlist": {"title": name, "track": tracks}}))
+        .expect("playlist should serialize")
+}
+
+#[cfg(test)]
+mod test {
+    use super::{PlaylistFormat, Track};
+
+    fn tracks() -> Vec<Track> {
+        vec![
+            Track {
+                location: String::from("spotify:track:1"),
+                title: String::from("Song & Dance"),
+                artist: Some(String::from("Artist, Other")),
+                album: Some(String::from("Album")),
+                duration_ms: Some(181000),
+            },
+            Track {
+                location: String::from("2"),
+                title: String::from("Untitled"),
+                artist: None,
+                album: None,
+                duration_ms: None,
+            },
+        ]
+    }
+
+    #[test]
+    fn test_render_m3u8() {
+        assert_eq!(
+            super::render(PlaylistFormat::M3u8, "Mix", &tracks()),
+            "#EXTM3U
+#PLAYLIST:Mix
+#EXTINF:181,Artist, Other - Song & Dance
+#EXTALB:Album
+spotify:track:1
+#EXTINF:-1,Untitled
+2
+"
+        );
+    }
+
+    #[test]
+    fn test_render_xspf() {
+        assert_eq!(
+            super::render(PlaylistFormat::Xspf, "<Mix>", &tracks()),
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<playlist version="1" xmlns="http://xspf.org/ns/0/">
+  <title>&lt;Mix&gt;</title>
+  <trackList>
+    <track>
+      <location>spotify:track:1</location>
+      <title>Song &amp; Dance</title>
+      <creator>Artist, Other</creator>
+      <album>Album</album>
+      <duration>181000</duration>
+    </track>
+    <track>
+      <location>2</location>
+      <title>Untitled</title>
+    </track>
+  </trackList>
+</playlist>
+"#
+        );
+    }
+
+    #[test]
+    fn test_render_jspf() {
+        let playlist: serde_json::Value =
+            serde_json::from_str(&super::render(PlaylistFormat::Jspf, "Mix", &tracks())).unwrap();
+        assert_eq!(
+            playlist,
+            serde_json::json!({"playlist": {"title": "Mix", "track": [
+                {"location": ["spotify:track:1"], "title": "Song & Dance", "creator": "Artist, Other", "album": "Album", "duration": 181000},
+                {"location": ["2"], "title": "Untitled"},
+            ]}})
+        );
+    }
+}
@@ -0,0 +1,236 @@
+//! Fake Spotify, setlist.fm and Google APIs with canned responses
+//!
+//! Point the upstream URLs at this server to test imports, pushes and logins offline, see
+//! [zeroflops::upstream]. Every Spotify track has the ID that was requested so any ID works.
+use axum::{
+    extract::{Host, Path, Query},
+    http::StatusCode,
+    response::Json,
+    routing::{get, post},
+    Router,
+};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use tokio::net::TcpListener;
+
+pub const ACCESS_TOKEN: &str = "fake-access-token";
+pub const REFRESH_TOKEN: &str = "fake-refresh-token";
+pub const SPOTIFY_USER: &str = "fake-user";
+pub const GOOGLE_EMAIL: &str = "fake@example.com";
+pub const TRACKS: [&str; 2] = ["fake-track-1", "fake-track-2"];
+
+pub fn router() -> Router {
+    Router::new()
+        // Spotify accounts
+        .route("/api/token", post(spotify_token))
+        // Spotify API
+        .route("/v1/me", get(spotify_user))
+        .route("/v1/me/playlists", get(playlists))
+        .route("/v1/me/tracks", get(playlist_tracks))
+        .route("/v1/me/top/tracks", get(top_tracks))
+        .route("/v1/me/player/recently-played", get(recent_tracks))
+        .route("/v1/users/:id/playlists", post(create_playlist))
+        .route("/v1/playlists/:id", get(playlist).put(update_playlist))
+        .route(
+            "/v1/playlists/:id/tracks",
+            get(playlist_tracks)
+                .put(update_playlist_tracks)
+                .post(update_playlist_tracks)
+                .delete(update_playlist_tracks),
+        )
+        .route("/v1/albums", get(albums))
+        .route("/v1/albums/:id", get(album))
+        .route("/v1/albums/:id/tracks", get(album_tracks))
+        .route("/v1/artists/:id", get(artist))
+        .route("/v1/artists/:id/albums", get(artist_albums))
+        .route("/v1/tracks", get(tracks))
+        .route("/v1/tracks/:id", get(track))
+        .route("/v1/search", get(search))
+        // Google
+        .route("/token", post(google_token))
+        .route("/v1/userinfo", get(google_user))
+        // setlist.fm
+        .route("/rest/1.0/setlist/:id", get(setlist))
+        .route("/rest/1.0/search/setlists", get(search_setlists))
+}
+
+pub async fn serve(listener: TcpListener) {
+    axum::serve(listener, router()).await.unwrap();
+}
+
+fn new_track(host: &str, id: &str) -> Value {
+    json!({
+        "id": id,
+        "name": format!("Track {id}"),
+        "album": {"href": format!("http://{host}/v1/albums/fake-album"), "name": "Fake Album"},
+        "artists": [{"name": "Fake Artist"}],
+        "duration_ms": 180000,
+        "external_urls": {"spotify": format!("https://open.spotify.com/track/{id}")},
+        "popularity": 50,
+        "track_number": 1,
+        "uri": format!("spotify:track:{id}"),
+        "external_ids": {"isrc": format!("FAKE{id}")},
+    })
+}
+
+fn new_playlist(id: &str, name: &str) -> Value {
+    json!({
+        "id": id,
+        "name": name,
+        "external_urls": {"spotify": format!("https://open.spotify.com/playlist/{id}")},
+        "snapshot_id": "fake-snapshot",
+    })
+}
+
+async fn spotify_token() -> Json<Value> {
+    Json(json!({
+        "access_token": ACCESS_TOKEN,
+        "refresh_token": REFRESH_TOKEN,
+        "token_type": "Bearer",
+        "expires_in": 3600,
+    }))
+}
+
+async fn spotify_user() -> Json<Value> {
+    Json(json!({
+        "id": SPOTIFY_USER,
+        "external_urls": {"spotify": format!("https://open.spotify.com/user/{SPOTIFY_USER}")},
+    }))
+}
+
+async fn playlists() -> Json<Value> {
+    Json(json!({"items": [new_playlist("fake-playlist", "Fake Playlist")]}))
+}
+
+async fn playlist(Path(id): Path<String>) -> Json<Value> {
+    Json(new_playlist(&id, "Fake Playlist"))
+}
+
+async fn create_playlist(Json(playlist): Json<Value>) -> (StatusCode, Json<Value>) {
+    let name = playlist["name"].as_str().unwrap_or_default();
+    (
+        StatusCode::CREATED,
+        Json(new_playlist("fake-new-playlist", name)),
+    )
+}
+
+async fn update_playlist() -> StatusCode {
+    StatusCode::OK
+}
+
+async fn playlist_tracks(Host(host): Host) -> Json<Value> {
+    let items: Vec<_> = TRACKS
+        .iter()
+        .map(|id| json!({"track": new_track(&host, id)}))
+        .collect();
+    Json(json!({"items": items, "next": null}))
+}
+
+async fn update_playlist_tracks() -> (StatusCode, Json<Value>) {
+    (
+        StatusCode::CREATED,
+        Json(json!({"snapshot_id": "fake-snapshot"})),
+    )
+}
+
+async fn top_tracks(Host(host): Host) -> Json<Value> {
+    let items: Vec<_> = TRACKS.iter().map(|id| new_track(&host, id)).collect();
+    Json(json!({"items": items, "next": null}))
+}
+
+async fn recent_tracks(Host(host): Host) -> Json<Value> {
+    let items: Vec<_> = TRACKS
+        .iter()
+        .map(|id| json!({"track": new_track(&host, id)}))
+        .collect();
+    Json(json!({"items": items}))
+}
+
+async fn album(Path(id): Path<String>) -> Json<Value> {
+    Json(json!({"href": format!("/v1/albums/{id}"), "name": "Fake Album"}))
+}
+
+fn new_album_tracks(host: &str) -> Value {
+    let items: Vec<_> = TRACKS
+        .iter()
+        .map(|id| json!({"id": id, "href": format!("http://{host}/v1/tracks/{id}")}))
+        .collect();
+    json!({"items": items, "next": null})
+}
+
+async fn album_tracks(Host(host): Host) -> Json<Value> {
+    Json(new_album_tracks(&host))
+}
+
+async fn albums(Host(host): Host) -> Json<Value> {
+    Json(json!({"albums": [{"tracks": new_album_tracks(&host)}]}))
+}
+
+async fn artist() -> Json<Value> {
+    Json(json!({"name": "Fake Artist"}))
+}
+
+async fn artist_albums() -> Json<Value> {
+    Json(json!({
+        "items": [{"id": "fake-album", "album_group": "album", "release_date": "2020-01-01"}],
+        "next": null,
+    }))
+}
+
+async fn tracks(Host(host): Host, Query(query): Query<HashMap<String, String>>) -> Json<Value> {
+    let tracks: Vec<_> = query
+        .get("ids")
+        .map(String::as_str)
+        .unwrap_or_default()
+        .split(',')
+        .filter(|id| !id.is_empty())
+        .map(|id| new_track(&host, id))
+        .collect();
+    Json(json!({"tracks": tracks}))
+}
+
+async fn track(Host(host): Host, Path(id): Path<String>) -> Json<Value> {
+    Json(new_track(&host, &id))
+}
+
+async fn search(Host(host): Host) -> Json<Value> {
+    Json(json!({"tracks": {"items": [new_track(&host, TRACKS[0])]}}))
+}
+
+async fn google_token() -> Json<Value> {
+    Json(json!({"access_token": ACCESS_TOKEN}))
+}
+
+async fn google_user() -> Json<Value> {
+    Json(json!({"email": GOOGLE_EMAIL}))
+}
+
+fn new_sets() -> Value {
+    json!({"set": [{"song": [{"name": "Fake Song"}, {"name": "Fake Cover", "cover": {"name": "Other Artist"}}]}]})
+}
+
+async fn setlist() -> Json<Value> {
+    Json(json!({
+        "eventDate": "01-01-2020",
+        "artist": {"mbid": "fake-mbid", "name": "Fake Artist"},
+        "venue": {
+            "name": "Fake Venue",
+            "city": {"name": "Fake City", "stateCode": "FC", "country": {"code": "US"}},
+        },
+        "sets": new_sets(),
+        "url": "https://www.setlist.fm/setlist/fake",
+    }))
+}
+
+async fn search_setlists() -> Json<Value> {
+    Json(json!({
+        "setlist": [{
+            "eventDate": "01-01-2020",
+            "artist": {"mbid": "fake-mbid", "name": "Fake Artist"},
+            "sets": new_sets(),
+        }],
+        "total": 1,
+        "page": 1,
+        "itemsPerPage": 20,
+    }))
+}
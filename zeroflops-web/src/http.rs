@@ -0,0 +1,218 @@
+//! Shared HTTP client for the upstream APIs
+//!
+//! Requests that are rate limited or hit a server error are retried with exponential backoff, and
+//! the number of concurrent requests to each host is limited so bulk imports are less likely to
+//! be rate limited in the first place.
+use async_trait::async_trait;
+use reqwest::{header::RETRY_AFTER, Client, RequestBuilder, Response, StatusCode};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, OnceLock},
+    time::Duration,
+};
+use tokio::sync::Semaphore;
+use tracing::Level;
+use zeroflops::Error;
+
+const MAX_RETRIES: u32 = 3;
+const INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+// Longer waits fail the request instead of holding up the caller
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(30);
+const MAX_CONCURRENT_REQUESTS: usize = 5;
+
+/// Reuses connections across requests
+pub fn client() -> &'static Client {
+    static CLIENT: OnceLock<Client> = OnceLock::new();
+    CLIENT.get_or_init(Client::new)
+}
+
+fn host_permits(host: &str) -> Arc<Semaphore> {
+    static HOSTS: OnceLock<Mutex<HashMap<String, Arc<Semaphore>>>> = OnceLock::new();
+    let mut hosts = HOSTS.get_or_init(Default::default).lock().unwrap();
+    Arc::clone(
+        hosts
+            .entry(host.to_owned())
+            .or_insert_with(|| Arc::new(Semaphore::new(MAX_CONCURRENT_REQUESTS))),
+    )
+}
+
+fn is_retryable(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+}
+
+/// How long to wait before the next attempt, if there is one
+///
+/// `retry_after` is the wait requested by the server, which replaces the backoff.
+fn retry_delay(attempt: u32, retry_after: Option<Duration>) -> Option<Duration> {
+    if attempt >= MAX_RETRIES {
+        return None;
+    }
+    match retry_after {
+        Some(delay) if delay > MAX_RETRY_AFTER => None,
+        Some(delay) => Some(delay),
+        None => Some(INITIAL_BACKOFF * 2u32.pow(attempt)),
+    }
+}
+
+/// Turn unsuccessful responses into errors
+///
+/// Not found responses are client errors since they usually mean that the ID from the user is
+/// wrong. Other statuses are upstream errors with the response body as the message.
+pub async fn check_status(response: Response) -> Result<Response, Error> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+    let host = response.url().host_str().unwrap_or_default().to_owned();
+    if status == StatusCode::NOT_FOUND {
+        return Err(Error::client_error(format!(
+            "{host} has nothing at {}",
+            response.url().path()
+        )));
+    }
+    let message = response.text().await.unwrap_or_default();
+    Err(Error::upstream_error(host, Some(status.as_u16()), message))
+}
+
+#[async_trait]
+pub trait RetryExt {
+    /// Send the request, retrying rate limits, server errors and connection failures
+    ///
+    /// Errors that remain after the retries are upstream errors. Other client errors are
+    /// returned as responses for the caller to handle.
+    async fn send_with_retry(self) -> Result<Response, Error>;
+
+    /// Send the request with retries and turn unsuccessful responses into errors
+    async fn send_checked(self) -> Result<Response, Error>;
+}
+
+#[async_trait]
+impl RetryExt for RequestBuilder {
+    async fn send_with_retry(self) -> Result<Response, Error> {
+        let (client, request) = self.build_split();
+        let request = request?;
+        let host = request.url().host_str().unwrap_or_default().to_owned();
+        let permits = host_permits(&host);
+        let mut attempt = 0;
+        loop {
+            let next_request = request
+                .try_clone()
+                .ok_or_else(|| Error::internal_error("Streaming requests can't be retried"))?;
+            let result = {
+                let _permit = permits.acquire().await.expect("semaphore is never closed");
+                client.execute(next_request).await
+            };
+            let delay = match result {
+                Ok(response) if is_retryable(response.status()) => {
+                    let status = response.status();
+                    let retry_after = response
+                        .headers()
+                        .get(RETRY_AFTER)
+                        .and_then(|v| v.to_str().ok())
+                        .and_then(|v| v.parse().ok())
+                        .map(Duration::from_secs);
+                    let Some(delay) = retry_delay(attempt, retry_after) else {
+                        let message = response.text().await.unwrap_or_default();
+                        return Err(Error::upstream_error(host, Some(status.as_u16()), message));
+                    };
+                    tracing::event!(Level::WARN, "{} returned {}, retrying", host, status);
+                    delay
+                }
+                Ok(response) => return Ok(response),
+                Err(e) if e.is_connect() || e.is_timeout() => {
+                    let Some(delay) = retry_delay(attempt, None) else {
+                        return Err(Error::upstream_error(host, None, e.to_string()));
+                    };
+                    tracing::event!(Level::WARN, "{} request failed, retrying: {}", host, e);
+                    delay
+                }
+                Err(e) => return Err(e.into()),
+            };
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+
+    async fn send_checked(self) -> Result<Response, Error> {
+        check_status(self.send_with_retry().await?).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{retry_delay, RetryExt, MAX_RETRIES};
+    use axum::{http::StatusCode, routing::get, Router};
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+    use zeroflops::Error;
+
+    #[test]
+    fn test_retry_delay() {
+        let delays: Vec<_> = (0..=MAX_RETRIES).map(|a| retry_delay(a, None)).collect();
+        assert_eq!(
+            delays,
+            vec![
+                Some(Duration::from_millis(500)),
+                Some(Duration::from_millis(1000)),
+                Some(Duration::from_millis(2000)),
+                None
+            ]
+        );
+    }
+
+    #[test]
+    fn test_retry_delay_retry_after() {
+        assert_eq!(
+            retry_delay(0, Some(Duration::from_secs(5))),
+            Some(Duration::from_secs(5))
+        );
+        assert_eq!(retry_delay(0, Some(Duration::from_secs(60))), None);
+        assert_eq!(retry_delay(MAX_RETRIES, Some(Duration::from_secs(1))), None);
+    }
+
+    #[tokio::test]
+    async fn test_send_checked_client_errors() {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let app = Router::new()
+            .route(
+                "/unauthorized",
+                get({
+                    let requests = Arc::clone(&requests);
+                    move || async move {
+                        requests.fetch_add(1, Ordering::SeqCst);
+                        (StatusCode::UNAUTHORIZED, "invalid token")
+                    }
+                }),
+            )
+            .route("/missing", get(|| async { StatusCode::NOT_FOUND }));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        // Client errors are returned right away instead of being retried or decoded
+        let result = super::client()
+            .get(format!("{url}/unauthorized"))
+            .send_checked()
+            .await;
+        match result {
+            Err(Error::UpstreamError(e)) => {
+                assert_eq!(e.host, "127.0.0.1");
+                assert_eq!(e.status, Some(401));
+                assert_eq!(e.message, "invalid token");
+            }
+            _ => panic!("expected an upstream error"),
+        }
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+
+        let result = super::client()
+            .get(format!("{url}/missing"))
+            .send_checked()
+            .await;
+        assert!(matches!(result, Err(Error::ClientError(_))));
+    }
+}
@@ -4,7 +4,15 @@ use serde::{Deserialize, Serialize};
 use serde_json::{Map, Value};
 use zeroflops::{Error, ItemMetadata};
 
+pub mod dashboard;
+pub mod export;
+#[cfg(feature = "dev")]
+pub mod fake_upstream;
+pub mod http;
+pub mod merge;
+pub mod play;
 pub mod query;
+pub mod refresh;
 pub mod source;
 pub mod user;
 
@@ -24,9 +32,11 @@ pub const ITEM_FIELDS: [&str; 9] = [
 pub struct Token {
     pub access_token: String,
     pub refresh_token: Option<String>,
+    /// Seconds until the access token expires
+    pub expires_in: Option<i64>,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
 pub struct Item {
     pub id: String,
     pub user_id: String,
@@ -93,6 +103,28 @@ impl TryFrom<RawItem> for Item {
     }
 }
 
+/// An item's score after a match
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ScoreChange {
+    pub user_id: String,
+    pub list_id: String,
+    pub item_id: String,
+    pub score: i32,
+    // Unix timestamp in seconds
+    pub timestamp: i64,
+}
+
+/// A track play from the Spotify streaming history
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+pub struct Play {
+    pub user_id: String,
+    pub item_id: String,
+    // Unix timestamp in seconds of when the track stopped playing
+    pub timestamp: i64,
+    pub ms_played: i64,
+    pub skipped: bool,
+}
+
 #[cfg(feature = "azure")]
 impl CosmosEntity for RawItem {
     type Entity = String;
@@ -108,3 +140,84 @@ pub fn convert_items(items: &[Item]) -> Vec<ItemMetadata> {
         .map(|i| ItemMetadata::new(i.id.clone(), i.name.clone(), i.iframe.clone()))
         .collect()
 }
+
+/// Update the Elo scores and records of the items of a match
+pub fn update_stats(
+    win_score: &mut i32,
+    win_wins: &mut i32,
+    lose_score: &mut i32,
+    lose_losses: &mut i32,
+) {
+    let diff = (32. / (1. + 10f64.powf((*win_score - *lose_score) as f64 / 400.))) as i32;
+    *win_score += diff;
+    *lose_score -= diff;
+    *win_wins += 1;
+    *lose_losses += 1;
+}
+
+#[cfg(test)]
+mod test {
+
+    #[test]
+    fn test_update_stats() {
+        let mut first_score = 1500;
+        let mut first_wins = 0;
+        let mut first_losses = 0;
+        let mut second_score = 1500;
+        let mut second_wins = 0;
+        let mut second_losses = 0;
+        crate::update_stats(
+            &mut first_score,
+            &mut first_wins,
+            &mut second_score,
+            &mut second_losses,
+        );
+        assert_eq!(
+            (
+                first_score,
+                first_wins,
+                first_losses,
+                second_score,
+                second_wins,
+                second_losses
+            ),
+            (1516, 1, 0, 1484, 0, 1)
+        );
+
+        crate::update_stats(
+            &mut first_score,
+            &mut first_wins,
+            &mut second_score,
+            &mut second_losses,
+        );
+        assert_eq!(
+            (
+                first_score,
+                first_wins,
+                first_losses,
+                second_score,
+                second_wins,
+                second_losses
+            ),
+            (1530, 2, 0, 1470, 0, 2)
+        );
+
+        crate::update_stats(
+            &mut second_score,
+            &mut second_wins,
+            &mut first_score,
+            &mut first_losses,
+        );
+        assert_eq!(
+            (
+                first_score,
+                first_wins,
+                first_losses,
+                second_score,
+                second_wins,
+                second_losses
+            ),
+            (1512, 2, 1, 1488, 1, 2)
+        );
+    }
+}
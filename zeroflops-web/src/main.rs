@@ -5,8 +5,8 @@ use arrow_schema::{FieldRef, Schema};
 use async_trait::async_trait;
 use axum::{
     body::Bytes,
-    extract::{Host, OriginalUri, Path, Query, State},
-    http::StatusCode,
+    extract::{DefaultBodyLimit, Host, OriginalUri, Path, Query, State},
+    http::{header, StatusCode},
     response::{IntoResponse, Json, Redirect, Response},
     routing::{get, post},
     Router,
@@ -26,19 +26,23 @@ use tower_http::services::ServeFile;
 use tower_http::trace::TraceLayer;
 use uuid::Uuid;
 use zeroflops::{
-    spotify::{Playlists, RecentTracks},
+    spotify::{ImportSummary, Playlists, RecentTracks},
     storage::{
         CosmosQuery, CreateDocumentBuilder, DeleteDocumentBuilder, DocumentWriter,
         GetDocumentBuilder, QueryDocumentsBuilder, ReplaceDocumentBuilder, SessionClient,
         SqlSessionClient, View,
     },
-    Error, Id, InternalError, Items, List, ListMode, Lists, RawList, UserId,
+    Dashboard, Dashboards, Error, Id, InternalError, Items, List, ListMode, Lists, RawDashboard,
+    RawList, ScrobbleImport, SetlistOverride, SetlistReport, UserId,
 };
 use zeroflops_web::{
+    dashboard, export, merge, play,
     query::{self, IntoQuery},
-    source::{self, spotify},
+    refresh,
+    source::{self, goodreads, lastfm, letterboxd, setlist, spotify, upload, Registry},
+    update_stats,
     user::{self, Auth, GoogleClient, SqlStore, User},
-    Item, RawItem,
+    Item, RawItem, ScoreChange,
 };
 
 type AuthContext = axum_login::AuthSession<SqlStore>;
@@ -52,6 +56,7 @@ fn get_user_or_demo_user(auth: AuthContext) -> UserId {
     }
 }
 
+#[allow(clippy::result_large_err)]
 fn require_user(auth: AuthContext) -> Result<User, Response> {
     if let Some(user) = auth.user {
         Ok(user)
@@ -216,6 +221,47 @@ async fn query_list(
     Ok(serialize_arrow(records)?)
 }
 
+async fn export_list(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+    Query(params): Query<HashMap<String, String>>,
+    auth: AuthContext,
+) -> Result<Response, Response> {
+    let user_id = get_user_or_demo_user(auth);
+    let format = export::PlaylistFormat::from_extension(
+        params.get("format").map_or("m3u8", String::as_str),
+    )?;
+    let list = source::get_list(&state.sql_client, &user_id, &id).await?;
+    let file_name = format.file_name(&list.name);
+    let playlist = export::export_list(&state.sql_client, &user_id, list, format).await?;
+    Ok((
+        [
+            (header::CONTENT_TYPE, format.content_type().to_owned()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{file_name}\""),
+            ),
+        ],
+        playlist,
+    )
+        .into_response())
+}
+
+async fn get_score_history(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+    Query(params): Query<HashMap<String, String>>,
+    auth: AuthContext,
+) -> Result<Vec<u8>, Response> {
+    let user_id = get_user_or_demo_user(auth);
+    let list = source::get_list(&state.sql_client, &user_id, &id).await?;
+    let ids = params
+        .get("ids")
+        .map(|ids| ids.split(',').map(ToOwned::to_owned).collect());
+    let records = query::get_score_history(&state.sql_client, &user_id, list, ids).await?;
+    Ok(serialize_arrow(records)?)
+}
+
 fn serialize_arrow(mut records: Vec<Map<String, Value>>) -> Result<Vec<u8>, Error> {
     records = records
         .into_iter()
@@ -289,7 +335,150 @@ async fn update_list(
     if list.id != id {
         return Err(Error::client_error("list id doesn't match").into());
     }
-    source::update_list_items(&state.sql_client, &user_id, list).await?;
+    source::update_list_items(&state.sql_client, &state.registry, &user_id, list).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn upload_list_items(
+    Path(id): Path<String>,
+    State(state): State<Arc<AppState>>,
+    Query(upload): Query<upload::Upload>,
+    auth: AuthContext,
+    data: Bytes,
+) -> Result<StatusCode, Response> {
+    let user = require_user(auth)?;
+    let user_id = UserId(user.user_id);
+    let list = source::get_list(&state.sql_client, &user_id, &id).await?;
+    // Public lists of other users can be read but not changed
+    if list.user_id != user_id.0 {
+        return Err(Error::NotFound.into());
+    }
+    upload::upload_items(
+        &state.sql_client,
+        &state.registry,
+        &user_id,
+        list,
+        upload,
+        &data,
+    )
+    .await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn import_scrobbles(
+    Path(id): Path<String>,
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<HashMap<String, String>>,
+    auth: AuthContext,
+    data: Bytes,
+) -> Result<Json<ScrobbleImport>, Response> {
+    let user = require_user(auth)?;
+    let user_id = UserId(user.user_id);
+    let Some(file_name) = params.get("file_name") else {
+        return Err(Error::client_error("file_name is missing").into());
+    };
+    let list = source::get_list(&state.sql_client, &user_id, &id).await?;
+    if list.user_id != user_id.0 {
+        return Err(Error::NotFound.into());
+    }
+    Ok(Json(
+        lastfm::import_scrobbles(
+            &state.sql_client,
+            &state.registry,
+            &user_id,
+            list,
+            file_name.clone(),
+            &data,
+        )
+        .await?,
+    ))
+}
+
+async fn import_letterboxd(
+    Path(id): Path<String>,
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<HashMap<String, String>>,
+    auth: AuthContext,
+    data: Bytes,
+) -> Result<StatusCode, Response> {
+    let user = require_user(auth)?;
+    let user_id = UserId(user.user_id);
+    let Some(file_name) = params.get("file_name") else {
+        return Err(Error::client_error("file_name is missing").into());
+    };
+    let list = source::get_list(&state.sql_client, &user_id, &id).await?;
+    if list.user_id != user_id.0 {
+        return Err(Error::NotFound.into());
+    }
+    letterboxd::import_films(
+        &state.sql_client,
+        &state.registry,
+        &user_id,
+        list,
+        file_name.clone(),
+        &data,
+    )
+    .await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn get_setlist_reports(
+    Path(id): Path<String>,
+    State(state): State<Arc<AppState>>,
+    auth: AuthContext,
+) -> Result<Json<Vec<SetlistReport>>, Response> {
+    let user = require_user(auth)?;
+    let user_id = UserId(user.user_id);
+    let list = source::get_list(&state.sql_client, &user_id, &id).await?;
+    if list.user_id != user_id.0 {
+        return Err(Error::NotFound.into());
+    }
+    Ok(Json(setlist::get_reports(&list)))
+}
+
+/// Pinned songs are used by the setlist sources of every list so only this list is refreshed now
+async fn pin_setlist_song(
+    Path(id): Path<String>,
+    State(state): State<Arc<AppState>>,
+    auth: AuthContext,
+    Json(pin): Json<SetlistOverride>,
+) -> Result<StatusCode, Response> {
+    let user = require_user(auth)?;
+    let user_id = UserId(user.user_id);
+    let list = source::get_list(&state.sql_client, &user_id, &id).await?;
+    if list.user_id != user_id.0 {
+        return Err(Error::NotFound.into());
+    }
+    setlist::pin_song(&state.sql_client, &user_id, pin).await?;
+    source::refresh_list_items(&state.sql_client, &state.registry, &user_id, list).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn import_goodreads(
+    Path(id): Path<String>,
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<HashMap<String, String>>,
+    auth: AuthContext,
+    data: Bytes,
+) -> Result<StatusCode, Response> {
+    let user = require_user(auth)?;
+    let user_id = UserId(user.user_id);
+    let Some(file_name) = params.get("file_name") else {
+        return Err(Error::client_error("file_name is missing").into());
+    };
+    let list = source::get_list(&state.sql_client, &user_id, &id).await?;
+    if list.user_id != user_id.0 {
+        return Err(Error::NotFound.into());
+    }
+    goodreads::import_books(
+        &state.sql_client,
+        &state.registry,
+        &user_id,
+        list,
+        file_name.clone(),
+        &data,
+    )
+    .await?;
     Ok(StatusCode::NO_CONTENT)
 }
 
@@ -308,11 +497,89 @@ async fn delete_list(
             document_name: id,
             partition_key: user_id,
         }))
-        .await
-        .map_err(Error::from)?;
+        .await?;
     Ok(StatusCode::NO_CONTENT)
 }
 
+async fn get_dashboards(
+    State(state): State<Arc<AppState>>,
+    auth: AuthContext,
+) -> Result<Json<Dashboards>, Response> {
+    let user_id = get_user_or_demo_user(auth);
+    Ok(Json(Dashboards {
+        dashboards: state
+            .sql_client
+            .query_documents::<RawDashboard>(QueryDocumentsBuilder::new(
+                "dashboard",
+                View::User(user_id),
+                CosmosQuery::new("SELECT * FROM dashboard".into_query()?),
+            ))
+            .await?
+            .into_iter()
+            .map(RawDashboard::try_into)
+            .collect::<Result<_, _>>()?,
+    }))
+}
+
+async fn get_dashboard(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+    auth: AuthContext,
+) -> Result<Json<Dashboard>, Response> {
+    let user_id = get_user_or_demo_user(auth);
+    Ok(Json(
+        dashboard::get_dashboard(&state.sql_client, &user_id, &id).await?,
+    ))
+}
+
+async fn create_dashboard(
+    State(state): State<Arc<AppState>>,
+    auth: AuthContext,
+) -> Result<impl IntoResponse, Response> {
+    let user = require_user(auth)?;
+    let dashboard = Dashboard::new(
+        Uuid::new_v4().to_hyphenated().to_string(),
+        &UserId(user.user_id),
+        String::from("New Dashboard"),
+    );
+    dashboard::create_dashboard(&state.sql_client, dashboard.clone()).await?;
+    Ok((StatusCode::CREATED, Json(dashboard)))
+}
+
+async fn update_dashboard(
+    Path(id): Path<String>,
+    State(state): State<Arc<AppState>>,
+    auth: AuthContext,
+    Json(dashboard): Json<Dashboard>,
+) -> Result<StatusCode, Response> {
+    let user = require_user(auth)?;
+    if dashboard.id != id {
+        return Err(Error::client_error("dashboard id doesn't match").into());
+    }
+    dashboard::update_dashboard(&state.sql_client, &UserId(user.user_id), dashboard).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn delete_dashboard(
+    Path(id): Path<String>,
+    State(state): State<Arc<AppState>>,
+    auth: AuthContext,
+) -> Result<StatusCode, Response> {
+    let user = require_user(auth)?;
+    dashboard::delete_dashboard(&state.sql_client, &UserId(user.user_id), &id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn query_panel(
+    State(state): State<Arc<AppState>>,
+    Path((id, index)): Path<(String, usize)>,
+    auth: AuthContext,
+) -> Result<Vec<u8>, Response> {
+    let user_id = get_user_or_demo_user(auth);
+    let records = dashboard::query_panel(&state.sql_client, &user_id, &id, index).await?;
+    Ok(serialize_arrow(records)?)
+}
+
 async fn find_items(
     State(state): State<Arc<AppState>>,
     Query(params): Query<HashMap<String, String>>,
@@ -365,6 +632,13 @@ async fn handle_action(
                 return Ok(push_list(state, &mut user, id).await?);
             }
         }
+        Some("refresh") => {
+            if let Some(id) = params.get("list") {
+                let user = require_user(auth)?;
+                let user_id = UserId(user.user_id);
+                return Ok(refresh_list(state, user_id, id).await?);
+            }
+        }
         Some("import") => {
             if let (Some(source), Some(id)) = (params.remove("source"), params.remove("id")) {
                 let user = require_user(auth)?;
@@ -372,6 +646,14 @@ async fn handle_action(
                 return Ok(import_list(state, user_id, &source, id, false, false).await?);
             }
         }
+        Some("merge") => {
+            if let (Some(id), Some(duplicate)) = (params.get("item"), params.get("duplicate")) {
+                let user = require_user(auth)?;
+                let user_id = UserId(user.user_id);
+                merge::merge_items(&state.sql_client, &user_id, id, duplicate).await?;
+                return Ok(StatusCode::NO_CONTENT);
+            }
+        }
         Some("updateItems") => {
             let user_id = get_user_or_demo_user(auth);
             return Ok(update_items(state, user_id, body).await?);
@@ -424,7 +706,17 @@ async fn handle_stats_update(
         &mut lose_item.user_losses,
     );
 
-    futures::future::try_join3(
+    // Record the new scores so that score trajectories can be queried
+    let timestamp = time::OffsetDateTime::now_utc().unix_timestamp();
+    let [win_change, lose_change] = [&win_item, &lose_item].map(|item| ScoreChange {
+        user_id: user_id.0.clone(),
+        list_id: id.to_owned(),
+        item_id: item.id.clone(),
+        score: item.user_score,
+        timestamp,
+    });
+
+    futures::future::try_join5(
         client.write_document(DocumentWriter::Replace(ReplaceDocumentBuilder {
             collection_name: "list",
             document_name: id.to_owned(),
@@ -443,48 +735,42 @@ async fn handle_stats_update(
             partition_key: user_id.clone(),
             document: RawItem::from(lose_item),
         })),
+        client.write_document(DocumentWriter::Create(CreateDocumentBuilder {
+            collection_name: "history",
+            document: win_change,
+            is_upsert: false,
+        })),
+        client.write_document(DocumentWriter::Create(CreateDocumentBuilder {
+            collection_name: "history",
+            document: lose_change,
+            is_upsert: false,
+        })),
     )
     .await?;
     Ok(StatusCode::OK)
 }
 
+/// Fetch the items of the list sources again
+async fn refresh_list(
+    state: Arc<AppState>,
+    user_id: UserId,
+    id: &str,
+) -> Result<StatusCode, Error> {
+    let list = source::get_list(&state.sql_client, &user_id, id).await?;
+    if list.user_id != user_id.0 {
+        return Err(Error::NotFound);
+    }
+    source::refresh_list_items(&state.sql_client, &state.registry, &user_id, list).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
 async fn push_list(state: Arc<AppState>, user: &mut User, id: &str) -> Result<StatusCode, Error> {
     let user_id = UserId(user.user_id.clone());
-    let mut list = source::get_list(&state.sql_client, &user_id, id).await?;
-    let (_, external_id) = list.get_unique_source()?;
-    let access_token = spotify::get_access_token(&state.sql_client, user).await?;
-    let external_id = if let Some(external_id) = external_id {
-        spotify::update_playlist(access_token, &external_id.id, &list.name).await?;
-        external_id.id.clone()
-    } else {
-        let mut playlist = spotify::create_playlist(access_token, &user_id, &list.name).await?;
-        let id = Id {
-            id: playlist.id,
-            raw_id: playlist.external_urls.remove("spotify").unwrap(),
-        };
-        list.mode = match list.mode {
-            ListMode::User(_) => ListMode::User(Some(id.clone())),
-            ListMode::View(_) => ListMode::View(Some(id.clone())),
-            _ => unreachable!(),
-        };
-        list.update_iframe();
-        source::update_list(&state.sql_client, &user_id, list.clone()).await?;
-        id.id
-    };
-    let ids: Vec<_> = match list.mode {
-        ListMode::User(_) => query::get_list_items(&state.sql_client, &user_id, list)
-            .await?
-            .items
-            .into_iter()
-            .map(|i| i.unwrap().id)
-            .collect(),
-        ListMode::View(_) => query::get_view_items(&state.sql_client, &user_id, &list)
-            .await?
-            .map(|i| i.id)
-            .collect(),
-        _ => unreachable!(),
-    };
-    spotify::update_list(access_token, &external_id, &ids).await?;
+    let list = source::get_list(&state.sql_client, &user_id, id).await?;
+    let (target, _) = list.get_unique_source()?;
+    let data_source = state.registry.get_push_target(target)?;
+    let ids = query::get_item_ids(&state.sql_client, &user_id, list.clone()).await?;
+    data_source.push(&state.sql_client, user, list, ids).await?;
     Ok(StatusCode::OK)
 }
 
@@ -529,6 +815,13 @@ pub async fn import_spotify(
             create_list_doc(client, list, is_upsert).await?;
             items
         }
+        "artist" => {
+            let (mut list, items) = spotify::import_artist(user_id, id).await?;
+            list.favorite = favorite;
+            list.public = public;
+            create_list_doc(client, list, is_upsert).await?;
+            items
+        }
         "track" => {
             let id = Id {
                 id: id.clone(),
@@ -565,19 +858,6 @@ async fn get_item_doc(
     }
 }
 
-fn update_stats(
-    win_score: &mut i32,
-    win_wins: &mut i32,
-    lose_score: &mut i32,
-    lose_losses: &mut i32,
-) {
-    let diff = (32. / (1. + 10f64.powf((*win_score - *lose_score) as f64 / 400.))) as i32;
-    *win_score += diff;
-    *lose_score -= diff;
-    *win_wins += 1;
-    *lose_losses += 1;
-}
-
 async fn create_list_doc(
     client: &SqlSessionClient,
     list: List,
@@ -590,7 +870,6 @@ async fn create_list_doc(
             is_upsert,
         }))
         .await
-        .map_err(Error::from)
 }
 
 async fn update_items(
@@ -624,10 +903,9 @@ async fn update_items(
                     document: RawItem::from(item),
                 }))
                 .await
-                .map_err(Error::from)
         })
         .collect::<FuturesUnordered<_>>()
-        .try_collect()
+        .try_collect::<()>()
         .await?;
     Ok(StatusCode::NO_CONTENT)
 }
@@ -654,7 +932,7 @@ async fn delete_items(
                 .await
         })
         .collect::<FuturesUnordered<_>>()
-        .try_collect()
+        .try_collect::<()>()
         .await?;
     Ok(StatusCode::NO_CONTENT)
 }
@@ -684,6 +962,18 @@ async fn get_spotify_recent_tracks(
     ))
 }
 
+async fn import_spotify_history(
+    State(state): State<Arc<AppState>>,
+    auth: AuthContext,
+    data: Bytes,
+) -> Result<Json<ImportSummary>, Response> {
+    let user = require_user(auth)?;
+    let user_id = UserId(user.user_id);
+    Ok(Json(
+        play::import_streaming_history(&state.sql_client, &user_id, &data).await?,
+    ))
+}
+
 async fn get_spotify_playlists(
     State(state): State<Arc<AppState>>,
     auth: AuthContext,
@@ -699,6 +989,7 @@ async fn get_spotify_playlists(
 struct AppState {
     sql_store: SqlStore,
     sql_client: SqlSessionClient,
+    registry: Registry<SqlSessionClient>,
 }
 
 #[tokio::main]
@@ -714,6 +1005,11 @@ async fn main() {
     let shared_state = Arc::new(AppState {
         sql_store: session_store.clone(),
         sql_client: SqlSessionClient { path: "data" },
+        registry: Registry::default(),
+    });
+    shared_state.sql_client.create_tables().unwrap();
+    refresh::spawn(SqlSessionClient {
+        path: shared_state.sql_client.path,
     });
 
     // Reset demo user data during startup in production
@@ -743,6 +1039,9 @@ async fn main() {
                 favorite: true,
                 query: String::from("SELECT artists, AVG(user_score) FROM item GROUP BY artists"),
                 public: true,
+                refresh: None,
+                last_refreshed: None,
+                refresh_error: None,
             },
             true,
         )
@@ -761,6 +1060,9 @@ async fn main() {
                 favorite: true,
                 query: String::from("SELECT name, user_score FROM item WHERE user_score >= 1500"),
                 public: true,
+                refresh: None,
+                last_refreshed: None,
+                refresh_error: None,
             },
             true,
         )
@@ -782,7 +1084,28 @@ async fn main() {
             get(get_list).put(update_list).delete(delete_list),
         )
         .route("/lists/:id/items", get(get_list_items))
+        .route("/lists/:id/upload", post(upload_list_items))
+        .route("/lists/:id/letterboxd", post(import_letterboxd))
+        .route("/lists/:id/goodreads", post(import_goodreads))
+        .route(
+            "/lists/:id/setlists",
+            get(get_setlist_reports).put(pin_setlist_song),
+        )
+        .route(
+            "/lists/:id/lastfm",
+            post(import_scrobbles).layer(DefaultBodyLimit::max(64 * 1024 * 1024)),
+        )
         .route("/lists/:id/query", get(query_list))
+        .route("/lists/:id/history", get(get_score_history))
+        .route("/lists/:id/export", get(export_list))
+        .route("/dashboards", get(get_dashboards).post(create_dashboard))
+        .route(
+            "/dashboards/:id",
+            get(get_dashboard)
+                .put(update_dashboard)
+                .delete(delete_dashboard),
+        )
+        .route("/dashboards/:id/panels/:index/query", get(query_panel))
         .route("/items", get(find_items).delete(delete_items))
         .route("/", post(handle_action))
         .route("/login", get(login_handler))
@@ -791,6 +1114,11 @@ async fn main() {
         .route("/user", get(user_handler))
         .route("/spotify/recentTracks", get(get_spotify_recent_tracks))
         .route("/spotify/playlists", get(get_spotify_playlists))
+        .route(
+            "/spotify/history",
+            // Streaming history files are usually larger than the default limit
+            post(import_spotify_history).layer(DefaultBodyLimit::max(64 * 1024 * 1024)),
+        )
         .with_state(shared_state);
 
     let app = Router::new()
@@ -820,70 +1148,3 @@ async fn main() {
         .await
         .unwrap();
 }
-
-#[cfg(test)]
-mod test {
-
-    #[test]
-    fn test_update_stats() {
-        let mut first_score = 1500;
-        let mut first_wins = 0;
-        let mut first_losses = 0;
-        let mut second_score = 1500;
-        let mut second_wins = 0;
-        let mut second_losses = 0;
-        crate::update_stats(
-            &mut first_score,
-            &mut first_wins,
-            &mut second_score,
-            &mut second_losses,
-        );
-        assert_eq!(
-            (
-                first_score,
-                first_wins,
-                first_losses,
-                second_score,
-                second_wins,
-                second_losses
-            ),
-            (1516, 1, 0, 1484, 0, 1)
-        );
-
-        crate::update_stats(
-            &mut first_score,
-            &mut first_wins,
-            &mut second_score,
-            &mut second_losses,
-        );
-        assert_eq!(
-            (
-                first_score,
-                first_wins,
-                first_losses,
-                second_score,
-                second_wins,
-                second_losses
-            ),
-            (1530, 2, 0, 1470, 0, 2)
-        );
-
-        crate::update_stats(
-            &mut second_score,
-            &mut second_wins,
-            &mut first_score,
-            &mut first_losses,
-        );
-        assert_eq!(
-            (
-                first_score,
-                first_wins,
-                first_losses,
-                second_score,
-                second_wins,
-                second_losses
-            ),
-            (1512, 2, 1, 1488, 1, 2)
-        );
-    }
-}
@@ -0,0 +1,249 @@
+//! Merge items that are the same thing, like a custom item and a Spotify track
+use crate::{query::IntoQuery, Item, RawItem};
+use zeroflops::{
+    storage::{
+        CosmosQuery, GetDocumentBuilder, QueryDocumentsBuilder, SessionClient, SqlSessionClient,
+        View,
+    },
+    Error, ItemMetadata, List, RawList, UserId,
+};
+
+/// Merge the duplicate into the item and delete the duplicate
+///
+/// Scores are combined and every list, score change, play and setlist override of the duplicate
+/// is moved to the item. Everything is written in one transaction.
+pub async fn merge_items(
+    client: &SqlSessionClient,
+    user_id: &UserId,
+    id: &str,
+    duplicate_id: &str,
+) -> Result<(), Error> {
+    if id == duplicate_id {
+        return Err(Error::client_error("Items can't be merged with themselves"));
+    }
+    let mut item = get_item(client, user_id, id).await?;
+    let duplicate = get_item(client, user_id, duplicate_id).await?;
+    merge_item(&mut item, duplicate);
+
+    let lists = client
+        .query_documents::<RawList>(QueryDocumentsBuilder::new(
+            "list",
+            View::User(user_id.clone()),
+            CosmosQuery::new("SELECT * FROM list".into_query()?),
+        ))
+        .await?;
+    let mut changed = Vec::new();
+    for list in lists {
+        let mut list = List::try_from(list)?;
+        if merge_list_items(&mut list, &item, duplicate_id) {
+            changed.push(RawList::from(list));
+        }
+    }
+    let path = client.path;
+    let user_id = user_id.clone();
+    let id = id.to_owned();
+    let duplicate_id = duplicate_id.to_owned();
+    let item = RawItem::from(item);
+    tokio::task::spawn_blocking(move || {
+        SqlSessionClient { path }.merge_items(&user_id, &changed, &item, &id, &duplicate_id)
+    })
+    .await
+    .map_err(|e| Error::internal_error(e.to_string()))?
+}
+
+async fn get_item(client: &impl SessionClient, user_id: &UserId, id: &str) -> Result<Item, Error> {
+    client
+        .get_document::<RawItem>(GetDocumentBuilder::new(
+            "item",
+            id.to_owned(),
+            View::User(user_id.clone()),
+        ))
+        .await?
+        .ok_or(Error::NotFound)?
+        .try_into()
+}
+
+/// Score of the combined matches, weighted by the number of matches of each item
+fn combine_scores(score: i32, matches: i32, other_score: i32, other_matches: i32) -> i32 {
+    if matches + other_matches == 0 {
+        return score;
+    }
+    ((score as i64 * matches as i64 + other_score as i64 * other_matches as i64)
+        / (matches + other_matches) as i64) as i32
+}
+
+fn merge_item(item: &mut Item, duplicate: Item) {
+    item.user_score = combine_scores(
+        item.user_score,
+        item.user_wins + item.user_losses,
+        duplicate.user_score,
+        duplicate.user_wins + duplicate.user_losses,
+    );
+    item.user_wins += duplicate.user_wins;
+    item.user_losses += duplicate.user_losses;
+    item.rating = item.rating.or(duplicate.rating);
+    for (key, value) in duplicate.metadata {
+        item.metadata.entry(key).or_insert(value);
+    }
+}
+
+/// Replace the duplicate with the item in the list and return whether the list changed
+///
+/// If the list has both items their list scores are combined.
+fn merge_list_items(list: &mut List, item: &Item, duplicate_id: &str) -> bool {
+    let mut changed = false;
+    for source in &mut list.sources {
+        if let Some(ids) = &mut source.item_ids {
+            if ids.iter().any(|id| id == duplicate_id) {
+                ids.retain(|id| id != &item.id);
+                for id in ids.iter_mut().filter(|id| id.as_str() == duplicate_id) {
+                    id.clone_from(&item.id);
+                }
+                changed = true;
+            }
+        }
+    }
+    let Some(index) = list.items.iter().position(|i| i.id == duplicate_id) else {
+        return changed;
+    };
+    let duplicate = list.items.remove(index);
+    if let Some(list_item) = list.items.iter_mut().find(|i| i.id == item.id) {
+        list_item.score = combine_scores(
+            list_item.score,
+            list_item.wins + list_item.losses,
+            duplicate.score,
+            duplicate.wins + duplicate.losses,
+        );
+        list_item.wins += duplicate.wins;
+        list_item.losses += duplicate.losses;
+    } else {
+        list.items.insert(
+            index,
+            ItemMetadata {
+                id: item.id.clone(),
+                name: item.name.clone(),
+                iframe: item.iframe.clone(),
+                ..duplicate
+            },
+        );
+    }
+    true
+}
+
+#[cfg(test)]
+mod test {
+    use crate::Item;
+    use serde_json::{json, Map};
+    use zeroflops::{ItemMetadata, List, ListMode, Source, SourceType, UserId};
+
+    fn item(id: &str, score: i32, wins: i32, losses: i32) -> Item {
+        Item {
+            id: id.to_owned(),
+            user_id: String::from("user"),
+            r#type: String::from("track"),
+            name: id.to_owned(),
+            iframe: None,
+            rating: None,
+            user_score: score,
+            user_wins: wins,
+            user_losses: losses,
+            metadata: Map::new(),
+            hidden: false,
+        }
+    }
+
+    fn list_item(id: &str, score: i32, wins: i32, losses: i32) -> ItemMetadata {
+        ItemMetadata {
+            score,
+            wins,
+            losses,
+            ..ItemMetadata::new(id.to_owned(), id.to_owned(), None)
+        }
+    }
+
+    #[test]
+    fn test_combine_scores() {
+        assert_eq!(super::combine_scores(1600, 3, 1400, 1), 1550);
+        assert_eq!(super::combine_scores(1500, 0, 1600, 2), 1600);
+        assert_eq!(super::combine_scores(1500, 0, 1600, 0), 1500);
+    }
+
+    #[test]
+    fn test_merge_item() {
+        let mut merged = item("spotify:track:a", 1600, 3, 0);
+        let mut duplicate = item("custom:a", 1400, 0, 1);
+        duplicate.rating = Some(5);
+        duplicate.metadata = json!({"note": "live"}).as_object().unwrap().clone();
+        super::merge_item(&mut merged, duplicate);
+        assert_eq!(
+            merged,
+            Item {
+                rating: Some(5),
+                metadata: json!({"note": "live"}).as_object().unwrap().clone(),
+                ..item("spotify:track:a", 1550, 3, 1)
+            }
+        );
+    }
+
+    #[test]
+    fn test_merge_list_items() {
+        let mut source = Source::new(SourceType::Custom(json!({})), String::new());
+        source.item_ids = Some(vec![String::from("custom:a"), String::from("b")]);
+        let mut list = List::new(
+            String::from("list"),
+            &UserId(String::from("user")),
+            ListMode::User(None),
+            String::new(),
+            vec![source],
+            None,
+            vec![
+                list_item("custom:a", 1516, 1, 0),
+                list_item("b", 1484, 0, 1),
+            ],
+        );
+        assert!(super::merge_list_items(
+            &mut list,
+            &item("spotify:track:a", 1500, 0, 0),
+            "custom:a"
+        ));
+        assert_eq!(
+            list.sources[0].item_ids,
+            Some(vec![String::from("spotify:track:a"), String::from("b")])
+        );
+        assert_eq!(
+            list.items,
+            vec![
+                ItemMetadata {
+                    id: String::from("spotify:track:a"),
+                    name: String::from("spotify:track:a"),
+                    ..list_item("custom:a", 1516, 1, 0)
+                },
+                list_item("b", 1484, 0, 1),
+            ]
+        );
+        assert!(!super::merge_list_items(
+            &mut list,
+            &item("spotify:track:a", 1500, 0, 0),
+            "c"
+        ));
+    }
+
+    #[test]
+    fn test_merge_list_items_combines_scores() {
+        let mut list = List::new(
+            String::from("list"),
+            &UserId(String::from("user")),
+            ListMode::User(None),
+            String::new(),
+            Vec::new(),
+            None,
+            vec![list_item("a", 1600, 3, 0), list_item("b", 1400, 0, 1)],
+        );
+        assert!(super::merge_list_items(
+            &mut list,
+            &item("a", 1500, 0, 0),
+            "b"
+        ));
+        assert_eq!(list.items, vec![list_item("a", 1550, 3, 1)]);
+    }
+}
@@ -0,0 +1,215 @@
+//! Imports of the Spotify extended streaming history
+//!
+//! The privacy export contains Streaming_History_Audio_*.json files with every play.
+use crate::{
+    query::IntoQuery,
+    source::{self, spotify},
+    Item, Play,
+};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::collections::{HashMap, HashSet};
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+use zeroflops::{
+    spotify::ImportSummary,
+    storage::{CosmosQuery, QueryDocumentsBuilder, SessionClient, SqlSessionClient, View},
+    Error, UserId,
+};
+
+#[derive(Debug, Deserialize)]
+struct StreamingHistoryEntry {
+    ts: String,
+    ms_played: i64,
+    master_metadata_track_name: Option<String>,
+    master_metadata_album_artist_name: Option<String>,
+    master_metadata_album_album_name: Option<String>,
+    spotify_track_uri: Option<String>,
+    skipped: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ItemId {
+    id: String,
+}
+
+/// Add the plays of a streaming history file and create items for tracks that weren't rated yet
+pub async fn import_streaming_history(
+    client: &SqlSessionClient,
+    user_id: &UserId,
+    data: &[u8],
+) -> Result<ImportSummary, Error> {
+    let (mut plays, items) = read_streaming_history(user_id, data)?;
+    let existing: HashSet<_> = client
+        .query_documents::<ItemId>(QueryDocumentsBuilder::new(
+            "item",
+            View::User(user_id.clone()),
+            CosmosQuery::new("SELECT id FROM item".into_query()?),
+        ))
+        .await?
+        .into_iter()
+        .map(|i| i.id)
+        .collect();
+    let items: Vec<_> = items
+        .into_iter()
+        .filter(|i| !existing.contains(&i.id))
+        .collect();
+    let items = get_track_items(user_id, items).await?;
+    // Plays of other releases of a recording count for the existing item
+    let recordings = source::recording_ids(client, &items).await?;
+    for play in &mut plays {
+        if let Some(id) = recordings.get(&play.item_id) {
+            play.item_id.clone_from(id);
+        }
+    }
+    let items: Vec<_> = source::use_recording_ids(items, &recordings)
+        .into_iter()
+        .filter(|i| !existing.contains(&i.id))
+        .collect();
+    let summary = ImportSummary {
+        plays: plays.len(),
+        new_items: items.len(),
+    };
+    source::create_items(client, items, false).await?;
+    let path = client.path;
+    tokio::task::spawn_blocking(move || SqlSessionClient { path }.create_documents("play", &plays))
+        .await
+        .map_err(|e| Error::internal_error(e.to_string()))??;
+    Ok(summary)
+}
+
+/// Replace the items created from the history with the full Spotify metadata when possible
+async fn get_track_items(user_id: &UserId, items: Vec<Item>) -> Result<Vec<Item>, Error> {
+    if items.is_empty() {
+        return Ok(items);
+    }
+    let token = spotify::get_token().await?;
+    let ids: Vec<_> = items
+        .iter()
+        .filter_map(|i| i.id.strip_prefix("spotify:track:"))
+        .collect();
+    let mut tracks: HashMap<_, _> = spotify::get_tracks(&token, user_id, &ids)
+        .await?
+        .into_iter()
+        .map(|i| (i.id.clone(), i))
+        .collect();
+    Ok(items
+        .into_iter()
+        .map(|i| tracks.remove(&i.id).unwrap_or(i))
+        .collect())
+}
+
+/// Podcast episodes and audiobooks are skipped
+fn read_streaming_history(user_id: &UserId, data: &[u8]) -> Result<(Vec<Play>, Vec<Item>), Error> {
+    let entries: Vec<StreamingHistoryEntry> = serde_json::from_slice(data)
+        .map_err(|e| Error::client_error(format!("invalid streaming history: {e}")))?;
+    let mut plays = Vec::with_capacity(entries.len());
+    let mut items = HashMap::new();
+    for entry in entries {
+        let Some(uri) = &entry.spotify_track_uri else {
+            continue;
+        };
+        let timestamp = OffsetDateTime::parse(&entry.ts, &Rfc3339)
+            .map_err(|e| Error::client_error(format!("invalid timestamp {}: {e}", entry.ts)))?
+            .unix_timestamp();
+        plays.push(Play {
+            user_id: user_id.0.clone(),
+            item_id: uri.clone(),
+            timestamp,
+            ms_played: entry.ms_played,
+            skipped: entry.skipped.unwrap_or_default(),
+        });
+        items
+            .entry(uri.clone())
+            .or_insert_with(|| new_history_item(uri, &entry, user_id));
+    }
+    Ok((plays, items.into_values().collect()))
+}
+
+/// Items for tracks that are no longer available on Spotify
+fn new_history_item(uri: &str, entry: &StreamingHistoryEntry, user_id: &UserId) -> Item {
+    let mut metadata = Map::new();
+    if let Some(album) = &entry.master_metadata_album_album_name {
+        metadata.insert(String::from("album"), Value::String(album.clone()));
+    }
+    if let Some(artist) = &entry.master_metadata_album_artist_name {
+        metadata.insert(
+            String::from("artists"),
+            Value::Array(vec![Value::String(artist.clone())]),
+        );
+    }
+    Item {
+        id: uri.to_owned(),
+        user_id: user_id.0.clone(),
+        r#type: String::from("track"),
+        name: entry
+            .master_metadata_track_name
+            .clone()
+            .unwrap_or_else(|| uri.to_owned()),
+        iframe: uri
+            .strip_prefix("spotify:track:")
+            .map(|id| format!("https://open.spotify.com/embed/track/{id}?utm_source=generator")),
+        rating: None,
+        user_score: 1500,
+        user_wins: 0,
+        user_losses: 0,
+        metadata,
+        hidden: false,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::Play;
+    use serde_json::json;
+    use zeroflops::{Error, UserId};
+
+    const HISTORY: &str = r#"[
+        {"ts": "2023-01-01T12:00:00Z", "platform": "android", "ms_played": 180000, "conn_country": "US", "master_metadata_track_name": "Song", "master_metadata_album_artist_name": "Artist", "master_metadata_album_album_name": "Album", "spotify_track_uri": "spotify:track:abc", "episode_name": null, "spotify_episode_uri": null, "reason_start": "clickrow", "reason_end": "trackdone", "shuffle": false, "skipped": null, "offline": false},
+        {"ts": "2023-01-01T12:30:00Z", "platform": "android", "ms_played": 1200000, "conn_country": "US", "master_metadata_track_name": null, "master_metadata_album_artist_name": null, "master_metadata_album_album_name": null, "spotify_track_uri": null, "episode_name": "Episode", "spotify_episode_uri": "spotify:episode:def", "reason_start": "clickrow", "reason_end": "trackdone", "shuffle": false, "skipped": null, "offline": false},
+        {"ts": "2023-01-02T08:00:00Z", "platform": "android", "ms_played": 5000, "conn_country": "US", "master_metadata_track_name": "Song", "master_metadata_album_artist_name": "Artist", "master_metadata_album_album_name": "Album", "spotify_track_uri": "spotify:track:abc", "episode_name": null, "spotify_episode_uri": null, "reason_start": "fwdbtn", "reason_end": "fwdbtn", "shuffle": true, "skipped": true, "offline": false}
+    ]"#;
+
+    #[test]
+    fn test_read_streaming_history() {
+        let (plays, items) =
+            super::read_streaming_history(&UserId(String::from("user")), HISTORY.as_bytes())
+                .unwrap();
+        assert_eq!(
+            plays,
+            vec![
+                Play {
+                    user_id: String::from("user"),
+                    item_id: String::from("spotify:track:abc"),
+                    timestamp: 1672574400,
+                    ms_played: 180000,
+                    skipped: false,
+                },
+                Play {
+                    user_id: String::from("user"),
+                    item_id: String::from("spotify:track:abc"),
+                    timestamp: 1672646400,
+                    ms_played: 5000,
+                    skipped: true,
+                },
+            ]
+        );
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].name, "Song");
+        assert_eq!(
+            items[0].iframe.as_deref(),
+            Some("https://open.spotify.com/embed/track/abc?utm_source=generator")
+        );
+        assert_eq!(
+            serde_json::Value::Object(items[0].metadata.clone()),
+            json!({"album": "Album", "artists": ["Artist"]})
+        );
+    }
+
+    #[test]
+    fn test_read_invalid_streaming_history() {
+        assert!(matches!(
+            super::read_streaming_history(&UserId(String::from("user")), b"{}"),
+            Err(Error::ClientError(_))
+        ));
+    }
+}
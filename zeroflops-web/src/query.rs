@@ -10,7 +10,9 @@ use sqlparser::{
 };
 use std::collections::{HashMap, HashSet, VecDeque};
 use zeroflops::{
-    storage::{CosmosQuery, QueryDocumentsBuilder, SessionClient, SqlSessionClient, View},
+    storage::{
+        CosmosParam, CosmosQuery, QueryDocumentsBuilder, SessionClient, SqlSessionClient, View,
+    },
     Error, InternalError, ItemMetadata, Items, List, ListMode, UserId,
 };
 
@@ -51,6 +53,27 @@ pub async fn get_view_items(
     }))
 }
 
+/// IDs of the list items in the order of the list query
+pub async fn get_item_ids(
+    client: &impl SessionClient,
+    user_id: &UserId,
+    list: List,
+) -> Result<Vec<String>, Error> {
+    Ok(match list.mode {
+        ListMode::View(_) => get_view_items(client, user_id, &list)
+            .await?
+            .map(|i| i.id)
+            .collect(),
+        _ => get_list_items(client, user_id, list)
+            .await?
+            .items
+            .into_iter()
+            .flatten()
+            .map(|i| i.id)
+            .collect(),
+    })
+}
+
 /// Filter list items with the query set for the list
 pub async fn get_list_items(
     client: &impl SessionClient,
@@ -76,8 +99,7 @@ pub async fn get_list_items(
                 ),
                 CosmosQuery::new(query.clone()),
             ))
-            .await
-            .map_err(Error::from)?
+            .await?
             .into_iter()
             .map(|r| r["id"].as_str().unwrap().to_owned())
             .collect();
@@ -169,6 +191,43 @@ pub async fn query_list(
         .collect())
 }
 
+/// Get the score after each match of the list for list items, ordered by match time
+///
+/// All list items are included if no ids are given and ids that aren't in the list are ignored.
+/// Rows have the item id to tell items with the same name apart and the name to label them.
+pub async fn get_score_history(
+    client: &impl SessionClient,
+    user_id: &UserId,
+    list: List,
+    ids: Option<Vec<String>>,
+) -> Result<Vec<Map<String, Value>>, Error> {
+    let list_ids = list.items.into_iter().map(|i| i.id);
+    let ids: Vec<_> = if let Some(ids) = ids {
+        list_ids.filter(|id| ids.contains(id)).collect()
+    } else {
+        list_ids.collect()
+    };
+    if ids.is_empty() {
+        return Ok(Vec::new());
+    }
+    let view = if list.user_id != user_id.0 {
+        View::PublicList(ids)
+    } else {
+        View::List(user_id.clone(), ids)
+    };
+    client
+        .query_documents(QueryDocumentsBuilder::new(
+            "item",
+            view,
+            CosmosQuery::with_params(
+                "SELECT item.id, item.name, history.timestamp, history.score FROM history JOIN item ON history.item_id = item.id AND history.user_id = item.user_id WHERE history.list_id = ? ORDER BY history.timestamp"
+                    .into_query()?,
+                vec![CosmosParam::new(String::from("@list_id"), list.id)],
+            ),
+        ))
+        .await
+}
+
 pub fn rewrite_query(query: impl IntoQuery) -> Result<(Query, Vec<String>), Error> {
     rewrite_query_impl(query.into_query()?)
 }
@@ -248,7 +307,7 @@ fn rewrite_expr(expr: &mut Expr) {
             }
             Expr::InList { expr, .. } => {
                 if let Expr::Identifier(id) = &**expr {
-                    *expr = Box::new(rewrite_identifier(id.clone()));
+                    **expr = rewrite_identifier(id.clone());
                 }
             }
             Expr::BinaryOp { left, op: _, right } => {
@@ -283,13 +342,14 @@ fn rewrite_identifier(id: Ident) -> Expr {
 
 #[cfg(test)]
 pub mod test {
+    use super::IntoQuery;
     use async_trait::async_trait;
     use serde::{de::DeserializeOwned, Serialize};
     use std::sync::{Arc, Mutex};
     use zeroflops::{
         storage::{
-            CreateDocumentBuilder, DeleteDocumentBuilder, DocumentWriter, GetDocumentBuilder,
-            QueryDocumentsBuilder, ReplaceDocumentBuilder, SessionClient,
+            CosmosParam, CosmosQuery, CreateDocumentBuilder, DeleteDocumentBuilder, DocumentWriter,
+            GetDocumentBuilder, QueryDocumentsBuilder, ReplaceDocumentBuilder, SessionClient, View,
         },
         Error, ItemMetadata, Items, List, ListMode, UserId,
     };
@@ -393,6 +453,9 @@ pub mod test {
             favorite: false,
             query: String::from("SELECT name, user_score FROM item"),
             public: false,
+            refresh: None,
+            last_refreshed: None,
+            refresh_error: None,
         };
         assert_eq!(
             super::get_list_items(
@@ -431,6 +494,9 @@ pub mod test {
             favorite: false,
             query: String::from("SELECT name, user_score FROM item"),
             public: false,
+            refresh: None,
+            last_refreshed: None,
+            refresh_error: None,
         };
         let client = TestSessionClient {
             get_mock: Mock::empty(),
@@ -462,6 +528,85 @@ pub mod test {
         );
     }
 
+    #[tokio::test]
+    async fn test_get_score_history() {
+        let item = |id: &str| ItemMetadata {
+            id: id.to_owned(),
+            name: String::new(),
+            iframe: None,
+            score: 0,
+            wins: 0,
+            losses: 0,
+            rank: None,
+        };
+        let list = List {
+            id: String::from("list"),
+            user_id: "owner".to_owned(),
+            mode: ListMode::User(None),
+            name: String::new(),
+            sources: Vec::new(),
+            iframe: None,
+            items: vec![item("a"), item("b")],
+            favorite: false,
+            query: String::from("SELECT name, user_score FROM item"),
+            public: true,
+            refresh: None,
+            last_refreshed: None,
+            refresh_error: None,
+        };
+        let client = TestSessionClient {
+            get_mock: Mock::empty(),
+            query_mock: Mock::new(vec![
+                r#"[{"id":"b","name":"b","timestamp":0,"score":1516}]"#,
+                r#"[{"id":"a","name":"a","timestamp":0,"score":1484}]"#,
+            ]),
+            write_mock: Mock::empty(),
+        };
+        super::get_score_history(
+            &client,
+            &UserId("owner".to_owned()),
+            list.clone(),
+            Some(vec!["b".to_owned(), "c".to_owned()]),
+        )
+        .await
+        .unwrap();
+        super::get_score_history(&client, &UserId("user".to_owned()), list.clone(), None)
+            .await
+            .unwrap();
+        {
+            let call_args = client.query_mock.call_args.lock().unwrap();
+            assert_eq!(
+                call_args[0].partition_key,
+                View::List(UserId("owner".to_owned()), vec!["b".to_owned()])
+            );
+            assert_eq!(
+                call_args[1].partition_key,
+                View::PublicList(vec!["a".to_owned(), "b".to_owned()])
+            );
+            // Only the matches of the list are included
+            assert_eq!(
+                call_args[0].query,
+                CosmosQuery::with_params(
+                    "SELECT item.id, item.name, history.timestamp, history.score FROM history JOIN item ON history.item_id = item.id AND history.user_id = item.user_id WHERE history.list_id = ? ORDER BY history.timestamp"
+                        .into_query()
+                        .unwrap(),
+                    vec![CosmosParam::new(String::from("@list_id"), "list")],
+                )
+            );
+        }
+
+        // Nothing is queried if none of the ids are in the list
+        assert!(super::get_score_history(
+            &client,
+            &UserId("owner".to_owned()),
+            list,
+            Some(vec!["c".to_owned()]),
+        )
+        .await
+        .unwrap()
+        .is_empty());
+    }
+
     #[tokio::test]
     async fn test_get_list_items() {
         let list = List {
@@ -483,6 +628,9 @@ pub mod test {
             favorite: false,
             query: String::from("SELECT name, user_score FROM item"),
             public: false,
+            refresh: None,
+            last_refreshed: None,
+            refresh_error: None,
         };
         let client = TestSessionClient {
             get_mock: Mock::empty(),
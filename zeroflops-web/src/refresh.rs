@@ -0,0 +1,274 @@
+use crate::{
+    query::IntoQuery,
+    source::{self, Registry},
+};
+use serde::Deserialize;
+use std::time::Duration;
+use time::OffsetDateTime;
+use tracing::Level;
+use zeroflops::{
+    storage::{CosmosQuery, QueryDocumentsBuilder, SessionClient, SqlSessionClient, View},
+    Error, InternalError, List, RawList, UserId,
+};
+
+/// How often to check for lists that are due for a refresh
+const CHECK_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+pub trait Clock: Send + Sync {
+    /// Unix timestamp in seconds
+    fn now(&self) -> i64;
+}
+
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> i64 {
+        OffsetDateTime::now_utc().unix_timestamp()
+    }
+}
+
+#[derive(Deserialize)]
+struct UserRow {
+    user_id: String,
+}
+
+/// Periodically refresh the lists that opted in on the tokio runtime
+pub fn spawn(client: SqlSessionClient) {
+    tokio::spawn(async move {
+        let registry = Registry::default();
+        let mut interval = tokio::time::interval(CHECK_INTERVAL);
+        loop {
+            interval.tick().await;
+            if let Err(e) = refresh_due_lists(&client, &registry, &SystemClock).await {
+                tracing::event!(Level::ERROR, "Failed to refresh lists: {}", e);
+            }
+        }
+    });
+}
+
+/// Refresh the lists whose refresh interval has passed since their last refresh
+///
+/// Lists are refreshed one at a time to avoid flooding the data sources with requests. The time
+/// and error of each refresh is saved on the list. Failing to save a list is logged so the other
+/// lists and users are still refreshed.
+pub async fn refresh_due_lists<C: SessionClient + Sync>(
+    client: &C,
+    registry: &Registry<C>,
+    clock: &impl Clock,
+) -> Result<(), Error> {
+    let users = client
+        .query_documents::<UserRow>(QueryDocumentsBuilder::new(
+            "user",
+            View::Public,
+            CosmosQuery::new("SELECT user_id FROM user".into_query()?),
+        ))
+        .await?;
+    for user in users {
+        let user_id = UserId(user.user_id);
+        let lists = match client
+            .query_documents::<RawList>(QueryDocumentsBuilder::new(
+                "list",
+                View::User(user_id.clone()),
+                CosmosQuery::new("SELECT * FROM list WHERE refresh IS NOT NULL".into_query()?),
+            ))
+            .await
+        {
+            Ok(lists) => lists,
+            Err(e) => {
+                tracing::event!(Level::ERROR, "Failed to get lists of {}: {}", user_id.0, e);
+                continue;
+            }
+        };
+        for list in lists {
+            let id = list.id.clone();
+            if let Err(e) = refresh_if_due(client, registry, clock, &user_id, list).await {
+                tracing::event!(Level::ERROR, "Failed to save refresh of list {}: {}", id, e);
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn refresh_if_due<C: SessionClient + Sync>(
+    client: &C,
+    registry: &Registry<C>,
+    clock: &impl Clock,
+    user_id: &UserId,
+    list: RawList,
+) -> Result<(), Error> {
+    let list = List::try_from(list)?;
+    let Some(refresh) = list.refresh else {
+        return Ok(());
+    };
+    let now = clock.now();
+    if now - list.last_refreshed.unwrap_or_default() < refresh.seconds() {
+        return Ok(());
+    }
+    let id = list.id.clone();
+    let result = source::refresh_list_items(client, registry, user_id, list).await;
+    if let Err(e) = &result {
+        tracing::event!(Level::WARN, "Failed to refresh list {}: {}", id, e);
+    }
+    // Get the list again since refreshing updates its items
+    let mut list = source::get_list(client, user_id, &id).await?;
+    list.last_refreshed = Some(now);
+    list.refresh_error = result.err().map(|e| error_message(&e));
+    source::update_list(client, user_id, list).await
+}
+
+fn error_message(error: &Error) -> String {
+    match error {
+        Error::ClientError(message) => message.clone(),
+        Error::NotFound => String::from("Not found"),
+        Error::UpstreamError(e) => e.to_string(),
+        Error::InternalError(InternalError::Error(message)) => message.clone(),
+        Error::InternalError(e) => format!("{:?}", e),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Clock;
+    use crate::{
+        query::test::{Mock, TestSessionClient},
+        source::{DataSource, Registry},
+    };
+    use async_trait::async_trait;
+    use std::sync::{Arc, Mutex};
+    use zeroflops::{
+        storage::{DocumentWriter, ReplaceDocumentBuilder},
+        Error, ItemMetadata, Source, SourceType, UserId,
+    };
+
+    struct FakeClock(i64);
+
+    impl Clock for FakeClock {
+        fn now(&self) -> i64 {
+            self.0
+        }
+    }
+
+    /// Records the names of the resolved sources
+    struct FakeSource {
+        resolved: Arc<Mutex<Vec<String>>>,
+        error: Option<&'static str>,
+    }
+
+    #[async_trait]
+    impl DataSource<TestSessionClient> for FakeSource {
+        fn handles(&self, _source_type: &SourceType) -> bool {
+            true
+        }
+
+        async fn resolve(
+            &self,
+            _client: &TestSessionClient,
+            _user_id: &UserId,
+            source: Source,
+        ) -> Result<(Source, Vec<ItemMetadata>), Error> {
+            self.resolved.lock().unwrap().push(source.name.clone());
+            match self.error {
+                Some(e) => Err(Error::client_error(e)),
+                None => Ok((source, Vec::new())),
+            }
+        }
+    }
+
+    fn fake_registry(
+        error: Option<&'static str>,
+    ) -> (Registry<TestSessionClient>, Arc<Mutex<Vec<String>>>) {
+        let resolved = Arc::new(Mutex::new(Vec::new()));
+        let registry = Registry::new(vec![Box::new(FakeSource {
+            resolved: Arc::clone(&resolved),
+            error,
+        })]);
+        (registry, resolved)
+    }
+
+    const LISTS: &str = r#"[{"id":"daily","user_id":"user","mode":"{\"User\":null}","name":"","sources":"[{\"source_type\":{\"Custom\":\"a\"},\"name\":\"daily\"}]","iframe":null,"items":"[]","favorite":false,"query":"SELECT name, user_score FROM c","public":false,"refresh":"\"daily\"","last_refreshed":0,"refresh_error":null},{"id":"weekly","user_id":"user","mode":"{\"User\":null}","name":"","sources":"[{\"source_type\":{\"Custom\":\"b\"},\"name\":\"weekly\"}]","iframe":null,"items":"[]","favorite":false,"query":"SELECT name, user_score FROM c","public":false,"refresh":"\"weekly\"","last_refreshed":0,"refresh_error":null}]"#;
+
+    #[tokio::test]
+    async fn test_refresh_due_lists() {
+        let client = TestSessionClient {
+            get_mock: Mock::new(vec![
+                r#"{"id":"daily","user_id":"user","mode":"{\"User\":null}","name":"","sources":"[{\"source_type\":{\"Custom\":\"a\"},\"name\":\"daily\",\"item_ids\":[]}]","iframe":null,"items":"[]","favorite":false,"query":"SELECT name, user_score FROM c","public":false,"refresh":"\"daily\"","last_refreshed":0,"refresh_error":"Playlist not found"}"#,
+            ]),
+            query_mock: Mock::new(vec![r#"[{"user_id":"user"}]"#, LISTS]),
+            write_mock: Mock::new(vec![(), ()]),
+        };
+        let (registry, resolved) = fake_registry(None);
+        // Two days later only the daily list is due
+        super::refresh_due_lists(&client, &registry, &FakeClock(2 * 24 * 60 * 60))
+            .await
+            .unwrap();
+        assert_eq!(*resolved.lock().unwrap(), vec!["daily"]);
+        assert_eq!(
+            client.write_mock.call_args.lock().unwrap().last(),
+            Some(&DocumentWriter::Replace(ReplaceDocumentBuilder {
+                collection_name: "list",
+                document_name: "daily".to_owned(),
+                partition_key: UserId("user".to_owned()),
+                document: r#"{"id":"daily","user_id":"user","mode":"{\"User\":null}","name":"","sources":"[{\"source_type\":{\"Custom\":\"a\"},\"name\":\"daily\",\"item_ids\":[]}]","iframe":null,"items":"[]","favorite":false,"query":"SELECT name, user_score FROM c","public":false,"refresh":"\"daily\"","last_refreshed":172800,"refresh_error":null}"#.to_owned(),
+            }))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_refresh_due_lists_continues_after_errors() {
+        let client = TestSessionClient {
+            // The daily list was deleted while it was being refreshed
+            get_mock: Mock::new(vec![
+                "null",
+                "null",
+                r#"{"id":"weekly","user_id":"user","mode":"{\"User\":null}","name":"","sources":"[]","iframe":null,"items":"[]","favorite":false,"query":"SELECT name, user_score FROM c","public":false,"refresh":"\"weekly\"","last_refreshed":0,"refresh_error":null}"#,
+            ]),
+            query_mock: Mock::new(vec![r#"[{"user_id":"user"}]"#, LISTS]),
+            write_mock: Mock::new(vec![()]),
+        };
+        let (registry, resolved) = fake_registry(Some("Playlist not found"));
+        super::refresh_due_lists(&client, &registry, &FakeClock(8 * 24 * 60 * 60))
+            .await
+            .unwrap();
+        assert_eq!(*resolved.lock().unwrap(), vec!["daily", "weekly"]);
+        let call_args = client.write_mock.call_args.lock().unwrap();
+        assert_eq!(call_args.len(), 1);
+        assert!(matches!(
+            &call_args[0],
+            DocumentWriter::Replace(ReplaceDocumentBuilder { document_name, .. }) if document_name == "weekly"
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_refresh_due_lists_records_errors() {
+        let client = TestSessionClient {
+            get_mock: Mock::new(vec![
+                r#"{"id":"daily","user_id":"user","mode":"{\"User\":null}","name":"","sources":"[{\"source_type\":{\"Custom\":\"a\"},\"name\":\"daily\"}]","iframe":null,"items":"[]","favorite":false,"query":"SELECT name, user_score FROM c","public":false,"refresh":"\"daily\"","last_refreshed":0,"refresh_error":null}"#,
+                r#"{"id":"weekly","user_id":"user","mode":"{\"User\":null}","name":"","sources":"[{\"source_type\":{\"Custom\":\"b\"},\"name\":\"weekly\"}]","iframe":null,"items":"[]","favorite":false,"query":"SELECT name, user_score FROM c","public":false,"refresh":"\"weekly\"","last_refreshed":0,"refresh_error":null}"#,
+            ]),
+            query_mock: Mock::new(vec![r#"[{"user_id":"user"}]"#, LISTS]),
+            write_mock: Mock::new(vec![(), ()]),
+        };
+        let (registry, resolved) = fake_registry(Some("Playlist not found"));
+        super::refresh_due_lists(&client, &registry, &FakeClock(8 * 24 * 60 * 60))
+            .await
+            .unwrap();
+        assert_eq!(*resolved.lock().unwrap(), vec!["daily", "weekly"]);
+        assert_eq!(
+            *client.write_mock.call_args.lock().unwrap(),
+            vec![
+                DocumentWriter::Replace(ReplaceDocumentBuilder {
+                    collection_name: "list",
+                    document_name: "daily".to_owned(),
+                    partition_key: UserId("user".to_owned()),
+                    document: r#"{"id":"daily","user_id":"user","mode":"{\"User\":null}","name":"","sources":"[{\"source_type\":{\"Custom\":\"a\"},\"name\":\"daily\"}]","iframe":null,"items":"[]","favorite":false,"query":"SELECT name, user_score FROM c","public":false,"refresh":"\"daily\"","last_refreshed":691200,"refresh_error":"Playlist not found"}"#.to_owned(),
+                }),
+                DocumentWriter::Replace(ReplaceDocumentBuilder {
+                    collection_name: "list",
+                    document_name: "weekly".to_owned(),
+                    partition_key: UserId("user".to_owned()),
+                    document: r#"{"id":"weekly","user_id":"user","mode":"{\"User\":null}","name":"","sources":"[{\"source_type\":{\"Custom\":\"b\"},\"name\":\"weekly\"}]","iframe":null,"items":"[]","favorite":false,"query":"SELECT name, user_score FROM c","public":false,"refresh":"\"weekly\"","last_refreshed":691200,"refresh_error":"Playlist not found"}"#.to_owned(),
+                }),
+            ]
+        );
+    }
+}
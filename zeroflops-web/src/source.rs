@@ -1,52 +1,505 @@
-use crate::RawItem;
+use crate::{query::IntoQuery, user::User, RawItem};
+use async_trait::async_trait;
 use futures::{stream::FuturesUnordered, StreamExt, TryStreamExt};
 use serde_json::{Map, Value};
+use std::collections::{HashMap, HashSet};
 use zeroflops::{
     storage::{
-        CreateDocumentBuilder, DocumentWriter, GetDocumentBuilder, ReplaceDocumentBuilder,
-        SessionClient, View,
+        CosmosParam, CosmosQuery, CreateDocumentBuilder, DocumentWriter, GetDocumentBuilder,
+        QueryDocumentsBuilder, ReplaceDocumentBuilder, SessionClient, View,
     },
-    Error, InternalError, ItemMetadata, List, RawList, Source, SourceType, Spotify, UserId,
+    Error, InternalError, ItemMetadata, List, RawList, SetOperation, Source, SourceType, UserId,
 };
 
+pub mod cfb;
+pub mod goodreads;
+pub mod imdb;
+pub mod lastfm;
+pub mod letterboxd;
 pub mod setlist;
 pub mod spotify;
+pub mod upload;
 
-pub async fn update_list_items(
+/// How many levels of lists a list can include through list sources
+const MAX_LIST_DEPTH: usize = 5;
+
+/// How many ISRCs are looked up in one query when matching recordings
+const MAX_ISRCS_PER_QUERY: usize = 1000;
+
+/// A service or file format that lists can get their items from
+///
+/// Data sources are looked up by source type in a [Registry].
+#[async_trait]
+pub trait DataSource<C: SessionClient + Sync>: Send + Sync {
+    fn handles(&self, source_type: &SourceType) -> bool;
+
+    /// Fetch the items of the source and fill in its name
+    ///
+    /// New items are saved by the data source so only the list items are returned.
+    async fn resolve(
+        &self,
+        client: &C,
+        user_id: &UserId,
+        source: Source,
+    ) -> Result<(Source, Vec<ItemMetadata>), Error>;
+
+    /// Whether a resolved source can keep its items when the list is refreshed
+    async fn is_unchanged(&self, _source: &Source) -> Result<bool, Error> {
+        Ok(false)
+    }
+
+    /// The service that lists can be pushed to, see [List::get_unique_source]
+    fn push_target(&self) -> Option<&'static str> {
+        None
+    }
+
+    /// Replace the items of the external list with the given item IDs
+    async fn push(
+        &self,
+        _client: &C,
+        _user: &mut User,
+        _list: List,
+        _ids: Vec<String>,
+    ) -> Result<(), Error> {
+        Err(Error::client_error("Push is not supported for the source"))
+    }
+}
+
+/// The data sources that can be used, tests can replace them with fakes
+pub struct Registry<C> {
+    sources: Vec<Box<dyn DataSource<C>>>,
+}
+
+impl<C: SessionClient + Sync> Registry<C> {
+    pub fn new(sources: Vec<Box<dyn DataSource<C>>>) -> Registry<C> {
+        Registry { sources }
+    }
+
+    pub fn get(&self, source_type: &SourceType) -> Result<&dyn DataSource<C>, Error> {
+        self.sources
+            .iter()
+            .find(|s| s.handles(source_type))
+            .map(AsRef::as_ref)
+            .ok_or_else(|| Error::client_error("Unsupported source"))
+    }
+
+    pub fn get_push_target(&self, target: Option<&str>) -> Result<&dyn DataSource<C>, Error> {
+        self.sources
+            .iter()
+            .find(|s| target.is_some() && s.push_target() == target)
+            .map(AsRef::as_ref)
+            .ok_or_else(|| Error::client_error("Push is not supported for the source"))
+    }
+}
+
+impl<C: SessionClient + Sync> Default for Registry<C> {
+    fn default() -> Registry<C> {
+        Registry::new(vec![
+            Box::new(CustomSource),
+            Box::new(spotify::SpotifySource),
+            Box::new(setlist::SetlistSource),
+            Box::new(imdb::ImdbSource),
+            Box::new(ImportedSource),
+            Box::new(ListItemsSource),
+            Box::new(ListSetSource),
+        ])
+    }
+}
+
+/// Items that are given in the source itself
+pub struct CustomSource;
+
+#[async_trait]
+impl<C: SessionClient + Sync> DataSource<C> for CustomSource {
+    fn handles(&self, source_type: &SourceType) -> bool {
+        matches!(source_type, SourceType::Custom(_))
+    }
+
+    async fn resolve(
+        &self,
+        client: &C,
+        user_id: &UserId,
+        mut source: Source,
+    ) -> Result<(Source, Vec<ItemMetadata>), Error> {
+        let SourceType::Custom(value) = &source.source_type else {
+            return Err(Error::client_error("Unsupported source"));
+        };
+        let items = get_custom_items(user_id, value)?;
+        if source.name.is_empty() {
+            source.name = "Custom".to_owned();
+        }
+        Ok((source, save_items(client, items).await?))
+    }
+}
+
+/// Items of uploaded files and Last.fm, Letterboxd, Goodreads and CollegeFootballData exports
+pub struct ImportedSource;
+
+#[async_trait]
+impl<C: SessionClient + Sync> DataSource<C> for ImportedSource {
+    fn handles(&self, source_type: &SourceType) -> bool {
+        matches!(
+            source_type,
+            SourceType::Lastfm(_)
+                | SourceType::Letterboxd(_)
+                | SourceType::Goodreads(_)
+                | SourceType::Cfb(_)
+                | SourceType::Upload(_)
+        )
+    }
+
+    async fn resolve(
+        &self,
+        client: &C,
+        user_id: &UserId,
+        source: Source,
+    ) -> Result<(Source, Vec<ItemMetadata>), Error> {
+        let (SourceType::Lastfm(ids)
+        | SourceType::Letterboxd(ids)
+        | SourceType::Goodreads(ids)
+        | SourceType::Cfb(ids)
+        | SourceType::Upload(ids)) = &source.source_type
+        else {
+            return Err(Error::client_error("Unsupported source"));
+        };
+        let items = get_imported_items(client, user_id, ids).await?;
+        Ok((source, items))
+    }
+}
+
+/// Items of another list
+pub struct ListItemsSource;
+
+#[async_trait]
+impl<C: SessionClient + Sync> DataSource<C> for ListItemsSource {
+    fn handles(&self, source_type: &SourceType) -> bool {
+        matches!(source_type, SourceType::ListItems(_))
+    }
+
+    /// The list is refreshed before the lists that include it, see [refresh_list_items]
+    async fn resolve(
+        &self,
+        client: &C,
+        user_id: &UserId,
+        mut source: Source,
+    ) -> Result<(Source, Vec<ItemMetadata>), Error> {
+        let SourceType::ListItems(id) = &source.source_type else {
+            return Err(Error::client_error("Unsupported source"));
+        };
+        let list = get_list(client, user_id, id).await?;
+        source.name = list.name;
+        Ok((source, list.items))
+    }
+}
+
+/// Union, intersection or difference of the items of other lists
+pub struct ListSetSource;
+
+#[async_trait]
+impl<C: SessionClient + Sync> DataSource<C> for ListSetSource {
+    fn handles(&self, source_type: &SourceType) -> bool {
+        matches!(source_type, SourceType::ListSet(_))
+    }
+
+    async fn resolve(
+        &self,
+        client: &C,
+        user_id: &UserId,
+        mut source: Source,
+    ) -> Result<(Source, Vec<ItemMetadata>), Error> {
+        let SourceType::ListSet(set) = &source.source_type else {
+            return Err(Error::client_error("Unsupported source"));
+        };
+        if set.ids.is_empty() {
+            return Err(Error::client_error("List set has no lists"));
+        }
+        let mut lists = Vec::new();
+        for id in &set.ids {
+            lists.push(get_list(client, user_id, id).await?);
+        }
+        let separator = match set.operation {
+            SetOperation::Union => " + ",
+            SetOperation::Intersection => " & ",
+            SetOperation::Difference => " - ",
+        };
+        let names: Vec<_> = lists.iter().map(|l| l.name.as_str()).collect();
+        let name = names.join(separator);
+        let items = combine_list_items(set.operation, lists.into_iter().map(|l| l.items));
+        source.name = name;
+        Ok((source, items))
+    }
+}
+
+/// Apply the set operation to the items of lists, keeping the first occurrence of each item
+fn combine_list_items(
+    operation: SetOperation,
+    lists: impl IntoIterator<Item = Vec<ItemMetadata>>,
+) -> Vec<ItemMetadata> {
+    let mut lists = lists.into_iter();
+    let Some(first) = lists.next() else {
+        return Vec::new();
+    };
+    let mut seen = HashSet::new();
+    let mut items: Vec<_> = first
+        .into_iter()
+        .filter(|i| seen.insert(i.id.clone()))
+        .collect();
+    for list in lists {
+        match operation {
+            SetOperation::Union => {
+                items.extend(list.into_iter().filter(|i| seen.insert(i.id.clone())));
+            }
+            SetOperation::Intersection => {
+                let ids: HashSet<_> = list.into_iter().map(|i| i.id).collect();
+                items.retain(|i| ids.contains(&i.id));
+            }
+            SetOperation::Difference => {
+                let ids: HashSet<_> = list.into_iter().map(|i| i.id).collect();
+                items.retain(|i| !ids.contains(&i.id));
+            }
+        }
+    }
+    items
+}
+
+/// IDs of the lists that the sources of the list get their items from
+fn get_source_lists(list: &List) -> Vec<&str> {
+    let mut ids = Vec::new();
+    for source in &list.sources {
+        match &source.source_type {
+            SourceType::ListItems(id) => ids.push(id.as_str()),
+            SourceType::ListSet(set) => ids.extend(set.ids.iter().map(String::as_str)),
+            _ => {}
+        }
+    }
+    ids
+}
+
+/// Lists that the list gets its items from, directly or through other lists
+///
+/// Every list comes after the lists it includes so they can be refreshed in order. Lists that
+/// include themselves or are nested more than [MAX_LIST_DEPTH] levels deep are errors.
+async fn get_nested_lists(
     client: &impl SessionClient,
     user_id: &UserId,
+    list: &List,
+) -> Result<Vec<List>, Error> {
+    let mut nested = Vec::new();
+    let mut visited = HashSet::new();
+    // The lists that are being visited with the IDs of their remaining source lists
+    let mut stack = vec![(list.clone(), owned_ids(get_source_lists(list)))];
+    while let Some((_, ids)) = stack.last_mut() {
+        let Some(id) = ids.pop() else {
+            let (list, _) = stack.pop().unwrap();
+            if !stack.is_empty() {
+                nested.push(list);
+            }
+            continue;
+        };
+        if stack.iter().any(|(l, _)| l.id == id) {
+            return Err(Error::client_error(format!("List {} includes itself", id)));
+        }
+        if !visited.insert(id.clone()) {
+            continue;
+        }
+        if stack.len() > MAX_LIST_DEPTH {
+            return Err(Error::client_error(format!(
+                "Lists can only be nested {} levels deep",
+                MAX_LIST_DEPTH
+            )));
+        }
+        let child = get_list(client, user_id, &id).await?;
+        let ids = owned_ids(get_source_lists(&child));
+        stack.push((child, ids));
+    }
+    Ok(nested)
+}
+
+fn owned_ids(ids: Vec<&str>) -> Vec<String> {
+    // Reversed since IDs are popped from the end
+    ids.into_iter().rev().map(ToOwned::to_owned).collect()
+}
+
+pub async fn update_list_items<C: SessionClient + Sync>(
+    client: &C,
+    registry: &Registry<C>,
+    user_id: &UserId,
     mut list: List,
 ) -> Result<(), Error> {
     let current_list = get_list(client, user_id, &list.id).await?;
+    // Sources from the edit page only have types so keep what was resolved before
+    for source in &mut list.sources {
+        if let Some(current) = current_list
+            .sources
+            .iter()
+            .find(|s| s.source_type == source.source_type)
+        {
+            if source.name.is_empty() {
+                source.name.clone_from(&current.name);
+            }
+            source.snapshot_id.clone_from(&current.snapshot_id);
+            source.item_ids.clone_from(&current.item_ids);
+            source.setlist_report.clone_from(&current.setlist_report);
+        }
+    }
+    // The refresh status is only set by the background refresh
+    list.last_refreshed = current_list.last_refreshed;
+    list.refresh_error.clone_from(&current_list.refresh_error);
     // Avoid updating sources if they haven't changed
-    // TODO: we should also check the snapshot ID
     if current_list
         .sources
         .iter()
         .map(|s| &s.source_type)
         .ne(list.sources.iter().map(|s| &s.source_type))
     {
-        list.items.clear();
-        let sources = list.sources;
-        list.sources = Vec::with_capacity(sources.len());
-        for (source, items) in futures::stream::iter(
-            sources
-                .into_iter()
-                .map(|source| get_source_and_items(client, user_id, source)),
-        )
-        .buffered(5)
-        .try_collect::<Vec<_>>()
-        .await?
-        {
-            list.sources.push(source);
-            list.items.extend(items);
-        }
+        get_nested_lists(client, user_id, &list).await?;
+        resolve_sources(client, registry, user_id, &mut list, &current_list, false).await?;
     }
     list.update_iframe();
     update_list(client, user_id, list).await?;
     Ok(())
 }
 
+/// Fetch the items of the sources of the list again
+///
+/// Sources are only skipped if their data source knows they haven't changed, like Spotify
+/// playlists with the same snapshot ID. Other sources can change without a new version, like the
+/// pinned songs of setlists.
+///
+/// Lists of the user that the list includes are refreshed first so their items are up to date.
+pub async fn refresh_list_items<C: SessionClient + Sync>(
+    client: &C,
+    registry: &Registry<C>,
+    user_id: &UserId,
+    list: List,
+) -> Result<(), Error> {
+    for nested in get_nested_lists(client, user_id, &list).await? {
+        // Public lists of other users are only read
+        if nested.user_id == user_id.0 {
+            refresh_sources(client, registry, user_id, nested).await?;
+        }
+    }
+    refresh_sources(client, registry, user_id, list).await
+}
+
+async fn refresh_sources<C: SessionClient + Sync>(
+    client: &C,
+    registry: &Registry<C>,
+    user_id: &UserId,
+    mut list: List,
+) -> Result<(), Error> {
+    let current_list = list.clone();
+    resolve_sources(client, registry, user_id, &mut list, &current_list, true).await?;
+    list.update_iframe();
+    update_list(client, user_id, list).await
+}
+
+/// Replace the items of the list with the items of its sources
+///
+/// Items that were already in the list keep their scores. Sources that were resolved before reuse
+/// their items unless refreshing.
+async fn resolve_sources<C: SessionClient + Sync>(
+    client: &C,
+    registry: &Registry<C>,
+    user_id: &UserId,
+    list: &mut List,
+    current_list: &List,
+    refresh: bool,
+) -> Result<(), Error> {
+    let current_items: HashMap<_, _> = current_list
+        .items
+        .iter()
+        .map(|i| (i.id.as_str(), i))
+        .collect();
+    let current_items = &current_items;
+    let sources = std::mem::take(&mut list.sources);
+    let resolved = futures::stream::iter(sources.into_iter().map(|source| async move {
+        let data_source = registry.get(&source.source_type)?;
+        if let Some(items) =
+            get_unchanged_items(data_source, &source, current_items, refresh).await?
+        {
+            return Ok((source, items));
+        }
+        let (mut source, items) = data_source.resolve(client, user_id, source).await?;
+        source.item_ids = Some(items.iter().map(|i| i.id.clone()).collect());
+        Ok::<_, Error>((source, items))
+    }))
+    .buffered(5)
+    .try_collect::<Vec<_>>()
+    .await?;
+    list.items.clear();
+    for (source, items) in resolved {
+        list.sources.push(source);
+        list.items.extend(items);
+    }
+    // Keep the stats of items that were already in the list
+    for item in &mut list.items {
+        if let Some(current) = current_items.get(item.id.as_str()) {
+            item.score = current.score;
+            item.wins = current.wins;
+            item.losses = current.losses;
+            item.rank = current.rank;
+        }
+    }
+    Ok(())
+}
+
+/// Items of a source that don't need to be fetched again
+async fn get_unchanged_items<C: SessionClient + Sync>(
+    data_source: &dyn DataSource<C>,
+    source: &Source,
+    current_items: &HashMap<&str, &ItemMetadata>,
+    refresh: bool,
+) -> Result<Option<Vec<ItemMetadata>>, Error> {
+    let Some(ids) = &source.item_ids else {
+        return Ok(None);
+    };
+    if refresh && !data_source.is_unchanged(source).await? {
+        return Ok(None);
+    }
+    // Sources are fetched again if some of their items were removed from the list
+    Ok(ids
+        .iter()
+        .map(|id| current_items.get(id.as_str()).map(|&i| i.clone()))
+        .collect())
+}
+
+/// Add the items of an uploaded file to the list
+///
+/// Files are identified by name so uploading a file again replaces its source. Tracks of a
+/// recording that was already imported use the ID of the existing item.
+async fn import_items<C: SessionClient + Sync>(
+    client: &C,
+    registry: &Registry<C>,
+    user_id: &UserId,
+    mut list: List,
+    mut source: Source,
+    items: Vec<super::Item>,
+) -> Result<(), Error> {
+    let recordings = recording_ids(client, &items).await?;
+    if let SourceType::Lastfm(ids)
+    | SourceType::Letterboxd(ids)
+    | SourceType::Goodreads(ids)
+    | SourceType::Cfb(ids)
+    | SourceType::Upload(ids) = &mut source.source_type
+    {
+        for id in ids.iter_mut() {
+            if let Some(recording) = recordings.get(id) {
+                id.clone_from(recording);
+            }
+        }
+        let mut seen = HashSet::new();
+        ids.retain(|id| seen.insert(id.clone()));
+    }
+    update_items(client, use_recording_ids(items, &recordings)).await?;
+    if let Some(s) = list.sources.iter_mut().find(|s| s.name == source.name) {
+        *s = source;
+    } else {
+        list.sources.push(source);
+    }
+    update_list_items(client, registry, user_id, list).await
+}
+
 pub async fn update_list(
     client: &impl SessionClient,
     user_id: &UserId,
@@ -60,37 +513,51 @@ pub async fn update_list(
             document: RawList::from(list),
         }))
         .await
-        .map_err(Error::from)
 }
 
-async fn get_source_and_items(
+/// Save the new items of a source and convert them to list items
+async fn save_items(
     client: &impl SessionClient,
-    user_id: &UserId,
-    mut source: Source,
-) -> Result<(Source, Vec<ItemMetadata>), Error> {
-    let (source, items) = match source.source_type {
-        SourceType::Custom(ref value) => {
-            let items = get_custom_items(user_id, value)?;
-            source.name = "Custom".to_owned();
-            (source, items)
-        }
-        SourceType::Spotify(Spotify::Playlist(id)) => spotify::get_playlist(user_id, id).await?,
-        SourceType::Spotify(Spotify::Album(id)) => spotify::get_album(user_id, id).await?,
-        SourceType::Spotify(Spotify::Track(id)) => spotify::get_track(user_id, id).await?,
-        SourceType::Setlist(id) => setlist::get_setlist(user_id, id).await?,
-        // TODO: inherit data sources
-        SourceType::ListItems(ref id) => {
-            let list = get_list(client, user_id, id).await?;
-            source.name = list.name;
-            return Ok((source, list.items));
-        }
-    };
+    items: Vec<super::Item>,
+) -> Result<Vec<ItemMetadata>, Error> {
+    let items = match_recordings(client, items).await?;
     let list_items = crate::convert_items(&items);
     create_items(client, items, false).await?;
-    Ok((source, list_items))
+    Ok(list_items)
+}
+
+/// Items of imported files were created during the import so they only need to be looked up
+async fn get_imported_items(
+    client: &impl SessionClient,
+    user_id: &UserId,
+    ids: &[String],
+) -> Result<Vec<ItemMetadata>, Error> {
+    if ids.is_empty() {
+        return Ok(Vec::new());
+    }
+    let mut items: HashMap<_, _> = client
+        .query_documents::<Map<String, Value>>(QueryDocumentsBuilder::new(
+            "item",
+            View::List(user_id.clone(), ids.to_vec()),
+            CosmosQuery::new("SELECT id, name, iframe FROM item".into_query()?),
+        ))
+        .await?
+        .into_iter()
+        .map(|item| {
+            let id = item["id"].as_str().unwrap().to_owned();
+            let metadata = ItemMetadata::new(
+                id.clone(),
+                item["name"].as_str().unwrap().to_owned(),
+                item["iframe"].as_str().map(ToOwned::to_owned),
+            );
+            (id, metadata)
+        })
+        .collect();
+    // Deleted items are skipped
+    Ok(ids.iter().filter_map(|id| items.remove(id)).collect())
 }
 
-// TODO: support arbitrary input
+/// Files can also be uploaded as custom items, see [upload]
 fn get_custom_items(user_id: &UserId, value: &Value) -> Result<Vec<super::Item>, Error> {
     let Value::Array(a) = value else {
         return Err(Error::client_error("invalid custom type"));
@@ -106,7 +573,14 @@ fn get_custom_items(user_id: &UserId, value: &Value) -> Result<Vec<super::Item>,
                 let Some(Value::String(name)) = o.remove("name") else {
                     return Err(Error::client_error("invalid name"));
                 };
-                Ok(new_custom_item(&id, user_id, name, o))
+                let iframe = match o.remove("iframe") {
+                    Some(Value::String(iframe)) => Some(iframe),
+                    None | Some(Value::Null) => None,
+                    Some(_) => return Err(Error::client_error("invalid iframe")),
+                };
+                let mut item = new_custom_item(&id, user_id, name, o);
+                item.iframe = iframe;
+                Ok(item)
             }
             _ => Err(Error::client_error("invalid custom type")),
         })
@@ -191,17 +665,173 @@ pub async fn create_items(
         .collect::<FuturesUnordered<_>>()
         .try_collect()
         .await
-        .map_err(Error::from)
+}
+
+/// Use the IDs of existing items for items with the same ISRC
+///
+/// Duplicate recordings within the items are removed.
+async fn match_recordings(
+    client: &impl SessionClient,
+    items: Vec<super::Item>,
+) -> Result<Vec<super::Item>, Error> {
+    let recordings = recording_ids(client, &items).await?;
+    Ok(use_recording_ids(items, &recordings))
+}
+
+/// IDs of the existing or earlier items with the same ISRC, by the IDs of the later items
+///
+/// Re-releases and remasters get different Spotify URIs, so otherwise the same recording would be
+/// ranked separately depending on the source it was imported from.
+pub async fn recording_ids(
+    client: &impl SessionClient,
+    items: &[super::Item],
+) -> Result<HashMap<String, String>, Error> {
+    let isrc = |item: &super::Item| {
+        item.metadata
+            .get("isrc")
+            .and_then(Value::as_str)
+            .map(ToOwned::to_owned)
+    };
+    let Some(user_id) = items
+        .iter()
+        .find(|i| isrc(i).is_some())
+        .map(|i| UserId(i.user_id.clone()))
+    else {
+        return Ok(HashMap::new());
+    };
+    let isrcs: Vec<_> = items
+        .iter()
+        .filter_map(isrc)
+        .collect::<HashSet<_>>()
+        .into_iter()
+        .collect();
+    let mut recordings = HashMap::new();
+    // Only the existing items of the incoming recordings are loaded, in batches to stay under the
+    // query limits
+    for isrcs in isrcs.chunks(MAX_ISRCS_PER_QUERY) {
+        let query = format!(
+            "SELECT id, metadata FROM item WHERE type = 'track' AND json_extract(metadata, '$.isrc') IN ({})",
+            &"?,".repeat(isrcs.len())[..isrcs.len() * 2 - 1]
+        );
+        recordings.extend(
+            client
+                .query_documents::<Map<String, Value>>(QueryDocumentsBuilder::new(
+                    "item",
+                    View::User(user_id.clone()),
+                    CosmosQuery::with_params(
+                        query.into_query()?,
+                        isrcs
+                            .iter()
+                            .map(|isrc| CosmosParam::new(String::from("@isrcs"), isrc.clone()))
+                            .collect::<Vec<_>>(),
+                    ),
+                ))
+                .await?
+                .into_iter()
+                .filter_map(|item| {
+                    let metadata: Map<String, Value> =
+                        serde_json::from_str(item.get("metadata")?.as_str()?).ok()?;
+                    Some((
+                        metadata.get("isrc")?.as_str()?.to_owned(),
+                        item.get("id")?.as_str()?.to_owned(),
+                    ))
+                }),
+        );
+    }
+    let mut ids = HashMap::new();
+    for item in items {
+        if let Some(isrc) = isrc(item) {
+            let id = recordings.entry(isrc).or_insert(item.id.clone());
+            if *id != item.id {
+                ids.insert(item.id.clone(), id.clone());
+            }
+        }
+    }
+    Ok(ids)
+}
+
+/// Replace the IDs of the items from [recording_ids] and remove the duplicates
+pub fn use_recording_ids(
+    mut items: Vec<super::Item>,
+    recordings: &HashMap<String, String>,
+) -> Vec<super::Item> {
+    for item in &mut items {
+        if let Some(id) = recordings.get(&item.id) {
+            item.id.clone_from(id);
+        }
+    }
+    let mut ids = HashSet::new();
+    items.retain(|item| ids.insert(item.id.clone()));
+    items
+}
+
+/// Create new items and update the name, iframe and metadata of existing items
+///
+/// Unlike upserts in [create_items], the scores of existing items are kept. Ratings are only
+/// replaced if the new item has one.
+pub async fn update_items(
+    client: &impl SessionClient,
+    items: Vec<super::Item>,
+) -> Result<(), Error> {
+    items
+        .into_iter()
+        .map(|item| async move {
+            client
+                .write_document(DocumentWriter::Create(CreateDocumentBuilder {
+                    collection_name: "item_metadata",
+                    document: RawItem::from(item),
+                    is_upsert: true,
+                }))
+                .await
+        })
+        .collect::<FuturesUnordered<_>>()
+        .try_collect()
+        .await
 }
 
 #[cfg(test)]
 mod test {
+    use super::Registry;
     use crate::query::test::{Mock, TestSessionClient};
+    use std::collections::HashMap;
     use zeroflops::{
         storage::{DocumentWriter, ReplaceDocumentBuilder},
-        List, ListMode, Source, SourceType, UserId,
+        Error, ItemMetadata, List, ListMode, RawList, SetOperation, Source, SourceType, UserId,
     };
 
+    fn list_items(ids: &[&str]) -> Vec<ItemMetadata> {
+        ids.iter()
+            .map(|id| ItemMetadata::new(id.to_string(), id.to_string(), None))
+            .collect()
+    }
+
+    #[test]
+    fn test_combine_list_items() {
+        let lists = || {
+            vec![
+                list_items(&["a", "b", "c", "a"]),
+                list_items(&["d", "c", "a"]),
+                list_items(&["a", "e"]),
+            ]
+        };
+        let ids = |items: Vec<ItemMetadata>| items.into_iter().map(|i| i.id).collect::<Vec<_>>();
+        assert_eq!(
+            ids(super::combine_list_items(SetOperation::Union, lists())),
+            vec!["a", "b", "c", "d", "e"]
+        );
+        assert_eq!(
+            ids(super::combine_list_items(
+                SetOperation::Intersection,
+                lists()
+            )),
+            vec!["a"]
+        );
+        assert_eq!(
+            ids(super::combine_list_items(SetOperation::Difference, lists())),
+            vec!["b"]
+        );
+    }
+
     #[tokio::test]
     async fn test_update_empty_list_items() {
         let client = TestSessionClient {
@@ -213,6 +843,7 @@ mod test {
         };
         super::update_list_items(
             &client,
+            &Registry::default(),
             &UserId(String::new()),
             List {
                 id: String::new(),
@@ -225,6 +856,9 @@ mod test {
                 favorite: false,
                 query: String::from("SELECT name, user_score FROM c"),
                 public: false,
+                refresh: None,
+                last_refreshed: None,
+                refresh_error: None,
             },
         )
         .await
@@ -235,7 +869,7 @@ mod test {
                 collection_name: "list",
                 document_name: "".to_owned(),
                 partition_key: UserId("".to_owned()),
-                document: r#"{"id":"","user_id":"","mode":"{\"User\":null}","name":"New List","sources":"[]","iframe":null,"items":"[]","favorite":false,"query":"SELECT name, user_score FROM c","public":false}"#.to_owned(),
+                document: r#"{"id":"","user_id":"","mode":"{\"User\":null}","name":"New List","sources":"[]","iframe":null,"items":"[]","favorite":false,"query":"SELECT name, user_score FROM c","public":false,"refresh":null,"last_refreshed":null,"refresh_error":null}"#.to_owned(),
             })]
         );
     }
@@ -245,28 +879,33 @@ mod test {
         let client = TestSessionClient {
             get_mock: Mock::new(vec![
                 r#"{"id":"","user_id":"","mode":"{\"User\":null}","name":"","sources":"[]","items":"[]","favorite":false,"query":"SELECT name, user_score FROM c"}"#,
-                r#"{"id":"","user_id":"","mode":"{\"User\":null}","name":"source","sources":"[]","items":"[]","favorite":false,"query":"SELECT name, user_score FROM c"}"#,
+                r#"{"id":"a","user_id":"","mode":"{\"User\":null}","name":"source","sources":"[]","items":"[]","favorite":false,"query":"SELECT name, user_score FROM c"}"#,
+                r#"{"id":"a","user_id":"","mode":"{\"User\":null}","name":"source","sources":"[]","items":"[]","favorite":false,"query":"SELECT name, user_score FROM c"}"#,
             ]),
             query_mock: Mock::empty(),
             write_mock: Mock::new(vec![()]),
         };
         super::update_list_items(
             &client,
+            &Registry::default(),
             &UserId(String::new()),
             List {
                 id: String::new(),
                 user_id: String::new(),
                 mode: ListMode::User(None),
                 name: String::from("New List"),
-                sources: vec![Source {
-                    source_type: SourceType::ListItems("".to_owned()),
-                    name: String::new(),
-                }],
+                sources: vec![Source::new(
+                    SourceType::ListItems("a".to_owned()),
+                    String::new(),
+                )],
                 iframe: None,
                 items: Vec::new(),
                 favorite: false,
                 query: String::from("SELECT name, user_score FROM c"),
                 public: false,
+                refresh: None,
+                last_refreshed: None,
+                refresh_error: None,
             },
         )
         .await
@@ -277,7 +916,7 @@ mod test {
                 collection_name: "list",
                 document_name: "".to_owned(),
                 partition_key: UserId("".to_owned()),
-                document: r#"{"id":"","user_id":"","mode":"{\"User\":null}","name":"New List","sources":"[{\"source_type\":{\"ListItems\":\"\"},\"name\":\"source\"}]","iframe":null,"items":"[]","favorite":false,"query":"SELECT name, user_score FROM c","public":false}"#.to_owned(),
+                document: r#"{"id":"","user_id":"","mode":"{\"User\":null}","name":"New List","sources":"[{\"source_type\":{\"ListItems\":\"a\"},\"name\":\"source\",\"item_ids\":[]}]","iframe":null,"items":"[]","favorite":false,"query":"SELECT name, user_score FROM c","public":false,"refresh":null,"last_refreshed":null,"refresh_error":null}"#.to_owned(),
             })]
         );
     }
@@ -287,28 +926,82 @@ mod test {
         let client = TestSessionClient {
             get_mock: Mock::new(vec![
                 r#"{"id":"","user_id":"","mode":"{\"User\":null}","name":"","sources":"[]","items":"[]","favorite":false,"query":"SELECT name, user_score FROM c"}"#,
-                r#"{"id":"","user_id":"","mode":"{\"User\":null}","name":"source","sources":"[]","items":"[{\"id\":\"\",\"name\":\"item\",\"score\":0,\"wins\":0,\"losses\":0}]","favorite":false,"query":"SELECT name, user_score FROM c"}"#,
+                r#"{"id":"a","user_id":"","mode":"{\"User\":null}","name":"source","sources":"[]","items":"[{\"id\":\"\",\"name\":\"item\",\"score\":0,\"wins\":0,\"losses\":0}]","favorite":false,"query":"SELECT name, user_score FROM c"}"#,
+                r#"{"id":"a","user_id":"","mode":"{\"User\":null}","name":"source","sources":"[]","items":"[{\"id\":\"\",\"name\":\"item\",\"score\":0,\"wins\":0,\"losses\":0}]","favorite":false,"query":"SELECT name, user_score FROM c"}"#,
+            ]),
+            query_mock: Mock::empty(),
+            write_mock: Mock::new(vec![()]),
+        };
+        super::update_list_items(
+            &client,
+            &Registry::default(),
+            &UserId(String::new()),
+            List {
+                id: String::new(),
+                user_id: String::new(),
+                mode: ListMode::User(None),
+                name: String::from("New List"),
+                sources: vec![Source::new(
+                    SourceType::ListItems("a".to_owned()),
+                    String::new(),
+                )],
+                iframe: None,
+                items: Vec::new(),
+                favorite: false,
+                query: String::from("SELECT name, user_score FROM c"),
+                public: false,
+                refresh: None,
+                last_refreshed: None,
+                refresh_error: None,
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            *client.write_mock.call_args.lock().unwrap(),
+            vec![DocumentWriter::Replace(ReplaceDocumentBuilder {
+                collection_name: "list",
+                document_name: "".to_owned(),
+                partition_key: UserId("".to_owned()),
+                document: r#"{"id":"","user_id":"","mode":"{\"User\":null}","name":"New List","sources":"[{\"source_type\":{\"ListItems\":\"a\"},\"name\":\"source\",\"item_ids\":[\"\"]}]","iframe":null,"items":"[{\"id\":\"\",\"name\":\"item\",\"iframe\":null,\"score\":0,\"wins\":0,\"losses\":0,\"rank\":null}]","favorite":false,"query":"SELECT name, user_score FROM c","public":false,"refresh":null,"last_refreshed":null,"refresh_error":null}"#.to_owned(),
+            })]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_update_list_items_reuses_resolved_sources() {
+        let client = TestSessionClient {
+            get_mock: Mock::new(vec![
+                r#"{"id":"","user_id":"","mode":"{\"User\":null}","name":"","sources":"[{\"source_type\":{\"ListItems\":\"a\"},\"name\":\"A\",\"item_ids\":[\"x\"]}]","items":"[{\"id\":\"x\",\"name\":\"X\",\"score\":1600,\"wins\":1,\"losses\":0}]","favorite":false,"query":"SELECT name, user_score FROM c"}"#,
+                r#"{"id":"a","user_id":"","mode":"{\"User\":null}","name":"A","sources":"[]","items":"[{\"id\":\"x\",\"name\":\"X\",\"score\":1600,\"wins\":1,\"losses\":0}]","favorite":false,"query":"SELECT name, user_score FROM c"}"#,
+                r#"{"id":"b","user_id":"","mode":"{\"User\":null}","name":"B","sources":"[]","items":"[{\"id\":\"y\",\"name\":\"Y\",\"score\":1400,\"wins\":0,\"losses\":1}]","favorite":false,"query":"SELECT name, user_score FROM c"}"#,
+                r#"{"id":"b","user_id":"","mode":"{\"User\":null}","name":"B","sources":"[]","items":"[{\"id\":\"y\",\"name\":\"Y\",\"score\":1400,\"wins\":0,\"losses\":1}]","favorite":false,"query":"SELECT name, user_score FROM c"}"#,
             ]),
             query_mock: Mock::empty(),
             write_mock: Mock::new(vec![()]),
         };
         super::update_list_items(
             &client,
+            &Registry::default(),
             &UserId(String::new()),
             List {
                 id: String::new(),
                 user_id: String::new(),
                 mode: ListMode::User(None),
                 name: String::from("New List"),
-                sources: vec![Source {
-                    source_type: SourceType::ListItems("".to_owned()),
-                    name: String::new(),
-                }],
+                // Only the new source is fetched
+                sources: vec![
+                    Source::new(SourceType::ListItems("a".to_owned()), String::new()),
+                    Source::new(SourceType::ListItems("b".to_owned()), String::new()),
+                ],
                 iframe: None,
                 items: Vec::new(),
                 favorite: false,
                 query: String::from("SELECT name, user_score FROM c"),
                 public: false,
+                refresh: None,
+                last_refreshed: None,
+                refresh_error: None,
             },
         )
         .await
@@ -319,8 +1012,148 @@ mod test {
                 collection_name: "list",
                 document_name: "".to_owned(),
                 partition_key: UserId("".to_owned()),
-                document: r#"{"id":"","user_id":"","mode":"{\"User\":null}","name":"New List","sources":"[{\"source_type\":{\"ListItems\":\"\"},\"name\":\"source\"}]","iframe":null,"items":"[{\"id\":\"\",\"name\":\"item\",\"iframe\":null,\"score\":0,\"wins\":0,\"losses\":0,\"rank\":null}]","favorite":false,"query":"SELECT name, user_score FROM c","public":false}"#.to_owned(),
+                document: r#"{"id":"","user_id":"","mode":"{\"User\":null}","name":"New List","sources":"[{\"source_type\":{\"ListItems\":\"a\"},\"name\":\"A\",\"item_ids\":[\"x\"]},{\"source_type\":{\"ListItems\":\"b\"},\"name\":\"B\",\"item_ids\":[\"y\"]}]","iframe":null,"items":"[{\"id\":\"x\",\"name\":\"X\",\"iframe\":null,\"score\":1600,\"wins\":1,\"losses\":0,\"rank\":null},{\"id\":\"y\",\"name\":\"Y\",\"iframe\":null,\"score\":1400,\"wins\":0,\"losses\":1,\"rank\":null}]","favorite":false,"query":"SELECT name, user_score FROM c","public":false,"refresh":null,"last_refreshed":null,"refresh_error":null}"#.to_owned(),
             })]
         );
     }
+
+    #[tokio::test]
+    async fn test_refresh_list_items_refreshes_nested_lists() {
+        let client = TestSessionClient {
+            get_mock: Mock::new(vec![
+                r#"{"id":"a","user_id":"user","mode":"{\"User\":null}","name":"A","sources":"[{\"source_type\":{\"ListItems\":\"b\"},\"name\":\"B\"}]","items":"[]","favorite":false,"query":"SELECT name, user_score FROM c"}"#,
+                r#"{"id":"b","user_id":"user","mode":"{\"User\":null}","name":"B","sources":"[]","items":"[{\"id\":\"x\",\"name\":\"X\",\"score\":1500,\"wins\":0,\"losses\":0}]","favorite":false,"query":"SELECT name, user_score FROM c"}"#,
+                r#"{"id":"b","user_id":"user","mode":"{\"User\":null}","name":"B","sources":"[]","items":"[{\"id\":\"x\",\"name\":\"X\",\"score\":1500,\"wins\":0,\"losses\":0}]","favorite":false,"query":"SELECT name, user_score FROM c"}"#,
+                r#"{"id":"a","user_id":"user","mode":"{\"User\":null}","name":"A","sources":"[{\"source_type\":{\"ListItems\":\"b\"},\"name\":\"B\",\"item_ids\":[\"x\"]}]","items":"[{\"id\":\"x\",\"name\":\"X\",\"score\":1500,\"wins\":0,\"losses\":0}]","favorite":false,"query":"SELECT name, user_score FROM c"}"#,
+            ]),
+            query_mock: Mock::empty(),
+            write_mock: Mock::new(vec![(), (), ()]),
+        };
+        let list = List::new(
+            String::from("root"),
+            &UserId(String::from("user")),
+            ListMode::User(None),
+            String::from("Root"),
+            vec![Source::new(
+                SourceType::ListItems("a".to_owned()),
+                String::new(),
+            )],
+            None,
+            Vec::new(),
+        );
+        super::refresh_list_items(
+            &client,
+            &Registry::default(),
+            &UserId(String::from("user")),
+            list,
+        )
+        .await
+        .unwrap();
+        let writes = client.write_mock.call_args.lock().unwrap();
+        let names: Vec<_> = writes
+            .iter()
+            .map(|w| match w {
+                DocumentWriter::Replace(r) => r.document_name.as_str(),
+                _ => panic!("lists should be replaced"),
+            })
+            .collect();
+        // Nested lists are refreshed before the lists that include them
+        assert_eq!(names, vec!["b", "a", "root"]);
+        let DocumentWriter::Replace(root) = &writes[2] else {
+            unreachable!()
+        };
+        let root =
+            List::try_from(serde_json::from_str::<RawList>(&root.document).unwrap()).unwrap();
+        assert_eq!(
+            root.items.iter().map(|i| i.id.as_str()).collect::<Vec<_>>(),
+            vec!["x"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_refresh_list_items_detects_cycles() {
+        let client = TestSessionClient {
+            get_mock: Mock::new(vec![
+                r#"{"id":"a","user_id":"user","mode":"{\"User\":null}","name":"A","sources":"[{\"source_type\":{\"ListSet\":{\"operation\":\"union\",\"ids\":[\"b\",\"root\"]}},\"name\":\"\"}]","items":"[]","favorite":false,"query":"SELECT name, user_score FROM c"}"#,
+                r#"{"id":"b","user_id":"user","mode":"{\"User\":null}","name":"B","sources":"[]","items":"[]","favorite":false,"query":"SELECT name, user_score FROM c"}"#,
+            ]),
+            query_mock: Mock::empty(),
+            write_mock: Mock::empty(),
+        };
+        let list = List::new(
+            String::from("root"),
+            &UserId(String::from("user")),
+            ListMode::User(None),
+            String::from("Root"),
+            vec![Source::new(
+                SourceType::ListItems("a".to_owned()),
+                String::new(),
+            )],
+            None,
+            Vec::new(),
+        );
+        let result = super::refresh_list_items(
+            &client,
+            &Registry::default(),
+            &UserId(String::from("user")),
+            list,
+        )
+        .await;
+        assert!(matches!(result, Err(Error::ClientError(_))));
+    }
+
+    #[tokio::test]
+    async fn test_match_recordings() {
+        let client = TestSessionClient {
+            get_mock: Mock::empty(),
+            query_mock: Mock::new(vec![
+                r#"[{"id":"old","metadata":"{\"isrc\":\"Y\"}"},{"id":"other","metadata":"{}"}]"#,
+            ]),
+            write_mock: Mock::empty(),
+        };
+        let item = |id: &str, isrc: Option<&str>| crate::Item {
+            id: id.to_owned(),
+            user_id: String::from("user"),
+            r#type: String::from("track"),
+            name: id.to_owned(),
+            iframe: None,
+            rating: None,
+            user_score: 1500,
+            user_wins: 0,
+            user_losses: 0,
+            metadata: isrc
+                .map(|isrc| serde_json::Map::from_iter([(String::from("isrc"), isrc.into())]))
+                .unwrap_or_default(),
+            hidden: false,
+        };
+        let items = vec![
+            item("a", Some("X")),
+            item("b", Some("Y")),
+            item("c", Some("X")),
+            item("d", None),
+        ];
+        let recordings = super::recording_ids(&client, &items).await.unwrap();
+        // Only the items with the ISRCs of the new items are loaded
+        assert_eq!(
+            client.query_mock.call_args.lock().unwrap()[0]
+                .query
+                .query
+                .to_string(),
+            "SELECT id, metadata FROM item WHERE type = 'track' AND json_extract(metadata, '$.isrc') IN (?, ?)"
+        );
+        assert_eq!(
+            recordings,
+            HashMap::from([
+                (String::from("b"), String::from("old")),
+                (String::from("c"), String::from("a")),
+            ])
+        );
+        assert_eq!(
+            super::use_recording_ids(items, &recordings)
+                .iter()
+                .map(|i| i.id.as_str())
+                .collect::<Vec<_>>(),
+            vec!["a", "old", "d"]
+        );
+    }
 }
@@ -0,0 +1,608 @@
+//! College football teams and games from CollegeFootballData files
+//!
+//! Teams and games can be exported from the CollegeFootballData API as CSV or JSON, with either
+//! the snake_case columns of the CSV exports or the camelCase fields of the JSON API. Rows with a
+//! school are teams and rows with a home and away team are games.
+use super::{
+    upload::{self, Format, Row},
+    Registry,
+};
+use crate::{query::IntoQuery, update_stats, Item, RawItem, ScoreChange};
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::collections::{HashMap, HashSet};
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+use zeroflops::{
+    storage::{CosmosQuery, QueryDocumentsBuilder, SessionClient, SqlSessionClient, View},
+    Error, List, RawList, Source, SourceType, UserId,
+};
+
+#[derive(Debug, PartialEq)]
+struct Team {
+    school: String,
+    conference: Option<String>,
+    division: Option<String>,
+    abbreviation: Option<String>,
+    mascot: Option<String>,
+}
+
+#[derive(Debug, PartialEq)]
+struct Game {
+    /// CollegeFootballData ID, or the date and teams if the file has no IDs
+    id: String,
+    season: Option<i64>,
+    start_date: Option<String>,
+    home_team: String,
+    home_conference: Option<String>,
+    home_points: Option<i64>,
+    away_team: String,
+    away_conference: Option<String>,
+    away_points: Option<i64>,
+    conference_game: bool,
+}
+
+impl Game {
+    /// Schools of the winner and loser, if the game was played and wasn't a tie
+    fn result(&self) -> Option<(&str, &str)> {
+        let (home, away) = (self.home_points?, self.away_points?);
+        if home > away {
+            Some((&self.home_team, &self.away_team))
+        } else if away > home {
+            Some((&self.away_team, &self.home_team))
+        } else {
+            None
+        }
+    }
+}
+
+/// Record of a team in a season
+#[derive(Debug, Default, PartialEq)]
+struct Record {
+    wins: i64,
+    losses: i64,
+    ties: i64,
+    conference_wins: i64,
+    conference_losses: i64,
+}
+
+/// Row of the seeded_game table
+#[derive(Debug, Serialize)]
+struct SeededGame {
+    user_id: String,
+    list_id: String,
+    game_id: String,
+}
+
+/// Result of a CollegeFootballData import
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct CfbImport {
+    pub teams: usize,
+    pub games: usize,
+    /// Games that were added to the match history
+    pub matches: usize,
+}
+
+/// Add the teams of CollegeFootballData files to the list
+///
+/// Teams come from the team files, or from the games if there are only game files. The records of
+/// the latest season of the games are added to the teams. With `seed_matches` the results of the
+/// games between teams of the list are also played as matches in the order of the games.
+/// Importing files with the same names replaces the previous import and games that were already
+/// played as matches in the list are skipped.
+pub async fn import_cfb(
+    client: &SqlSessionClient,
+    registry: &Registry<SqlSessionClient>,
+    user_id: &UserId,
+    list: List,
+    files: Vec<(String, Vec<u8>)>,
+    seed_matches: bool,
+) -> Result<CfbImport, Error> {
+    let mut teams = Vec::new();
+    let mut games = Vec::new();
+    for (file_name, data) in &files {
+        let (file_teams, file_games) =
+            read_file(file_name, data).map_err(|e| prefix_error(e, file_name))?;
+        teams.extend(file_teams);
+        games.extend(file_games);
+    }
+    if teams.is_empty() {
+        teams = get_game_teams(&games);
+    }
+    let records = get_records(&games);
+    let items: Vec<_> = teams
+        .into_iter()
+        .map(|team| {
+            let record = records.get(&team.school);
+            new_team_item(team, record, user_id)
+        })
+        .collect();
+    let mut summary = CfbImport {
+        teams: items.len(),
+        games: games.len(),
+        matches: 0,
+    };
+    let ids = items.iter().map(|i| i.id.clone()).collect();
+    let file_names: Vec<_> = files.iter().map(|(name, _)| name.as_str()).collect();
+    let list_id = list.id.clone();
+    super::import_items(
+        client,
+        registry,
+        user_id,
+        list,
+        Source::new(SourceType::Cfb(ids), file_names.join(", ")),
+        items,
+    )
+    .await?;
+    if seed_matches {
+        summary.matches = seed_game_matches(client, user_id, &list_id, &games).await?;
+    }
+    Ok(summary)
+}
+
+fn prefix_error(error: Error, file_name: &str) -> Error {
+    match error {
+        Error::ClientError(message) => Error::client_error(format!("{file_name}: {message}")),
+        e => e,
+    }
+}
+
+fn read_file(file_name: &str, data: &[u8]) -> Result<(Vec<Team>, Vec<Game>), Error> {
+    let rows = match Format::from_file_name(file_name) {
+        Some(Format::Csv) => upload::read_csv(data)?,
+        Some(Format::Json) => upload::read_json(data)?,
+        Some(Format::Ndjson) => upload::read_ndjson(data),
+        None => return Err(Error::client_error("unknown file format")),
+    };
+    read_rows(rows)
+}
+
+fn read_rows(rows: Vec<Row>) -> Result<(Vec<Team>, Vec<Game>), Error> {
+    let mut teams = Vec::new();
+    let mut games = Vec::new();
+    let mut errors = Vec::new();
+    for (i, row) in rows.into_iter().enumerate() {
+        let result = row.and_then(|row| {
+            if text(&row, &["school"]).is_some() {
+                teams.push(read_team(&row));
+                Ok(())
+            } else if text(&row, &["home_team", "homeTeam"]).is_some() {
+                games.push(read_game(&row)?);
+                Ok(())
+            } else {
+                Err(String::from("expected a team or a game"))
+            }
+        });
+        if let Err(e) = result {
+            errors.push(format!("row {}: {e}", i + 1));
+        }
+    }
+    upload::check_row_errors(errors)?;
+    Ok((teams, games))
+}
+
+fn read_team(row: &Map<String, Value>) -> Team {
+    Team {
+        school: text(row, &["school"]).unwrap_or_default(),
+        conference: text(row, &["conference"]),
+        division: text(row, &["division"]),
+        abbreviation: text(row, &["abbreviation"]),
+        mascot: text(row, &["mascot"]),
+    }
+}
+
+fn read_game(row: &Map<String, Value>) -> Result<Game, String> {
+    let home_team = text(row, &["home_team", "homeTeam"]).ok_or("missing home_team")?;
+    let away_team = text(row, &["away_team", "awayTeam"]).ok_or("missing away_team")?;
+    let start_date = text(row, &["start_date", "startDate"]);
+    Ok(Game {
+        id: text(row, &["id"]).unwrap_or_else(|| {
+            format!(
+                "{}:{home_team}:{away_team}",
+                start_date.as_deref().unwrap_or_default()
+            )
+        }),
+        season: number(row, &["season"])?,
+        start_date,
+        home_team,
+        home_conference: text(row, &["home_conference", "homeConference"]),
+        home_points: number(row, &["home_points", "homePoints"])?,
+        away_team,
+        away_conference: text(row, &["away_conference", "awayConference"]),
+        away_points: number(row, &["away_points", "awayPoints"])?,
+        conference_game: match field(row, &["conference_game", "conferenceGame"]) {
+            Some(Value::Bool(b)) => *b,
+            Some(Value::String(s)) => s.eq_ignore_ascii_case("true"),
+            _ => false,
+        },
+    })
+}
+
+/// The first of the columns that has a value
+fn field<'a>(row: &'a Map<String, Value>, columns: &[&str]) -> Option<&'a Value> {
+    columns
+        .iter()
+        .filter_map(|column| row.get(*column))
+        .find(|v| match v {
+            Value::Null => false,
+            Value::String(s) => !s.trim().is_empty(),
+            _ => true,
+        })
+}
+
+fn text(row: &Map<String, Value>, columns: &[&str]) -> Option<String> {
+    match field(row, columns)? {
+        Value::String(s) => Some(s.trim().to_owned()),
+        Value::Number(n) => Some(n.to_string()),
+        _ => None,
+    }
+}
+
+fn number(row: &Map<String, Value>, columns: &[&str]) -> Result<Option<i64>, String> {
+    match field(row, columns) {
+        Some(Value::Number(n)) => n
+            .as_i64()
+            .map(Some)
+            .ok_or_else(|| format!("invalid {} {n}", columns[0])),
+        Some(Value::String(s)) => s
+            .trim()
+            .parse()
+            .map(Some)
+            .map_err(|_| format!("invalid {} {s}", columns[0])),
+        Some(v) => Err(format!("invalid {} {v}", columns[0])),
+        None => Ok(None),
+    }
+}
+
+/// Teams of the games in the order they first played
+fn get_game_teams(games: &[Game]) -> Vec<Team> {
+    let mut teams: Vec<Team> = Vec::new();
+    let mut indices = HashMap::new();
+    for game in games {
+        let sides = [
+            (&game.home_team, &game.home_conference),
+            (&game.away_team, &game.away_conference),
+        ];
+        for (school, conference) in sides {
+            let index = *indices.entry(school.clone()).or_insert_with(|| {
+                teams.push(Team {
+                    school: school.clone(),
+                    conference: None,
+                    division: None,
+                    abbreviation: None,
+                    mascot: None,
+                });
+                teams.len() - 1
+            });
+            // Teams can change conferences so the latest game wins
+            if conference.is_some() {
+                teams[index].conference.clone_from(conference);
+            }
+        }
+    }
+    teams
+}
+
+/// Records of the teams in the latest season of the games
+fn get_records(games: &[Game]) -> HashMap<String, (Option<i64>, Record)> {
+    let season = games.iter().filter_map(|g| g.season).max();
+    let mut records: HashMap<String, (Option<i64>, Record)> = HashMap::new();
+    for game in games.iter().filter(|g| g.season == season) {
+        let (Some(home), Some(away)) = (game.home_points, game.away_points) else {
+            continue;
+        };
+        match game.result() {
+            Some((winner, loser)) => {
+                let winner = get_record(&mut records, winner);
+                winner.wins += 1;
+                winner.conference_wins += i64::from(game.conference_game);
+                let loser = get_record(&mut records, loser);
+                loser.losses += 1;
+                loser.conference_losses += i64::from(game.conference_game);
+            }
+            None if home == away => {
+                get_record(&mut records, &game.home_team).ties += 1;
+                get_record(&mut records, &game.away_team).ties += 1;
+            }
+            None => {}
+        }
+    }
+    for (record_season, _) in records.values_mut() {
+        *record_season = season;
+    }
+    records
+}
+
+fn get_record<'a>(
+    records: &'a mut HashMap<String, (Option<i64>, Record)>,
+    school: &str,
+) -> &'a mut Record {
+    &mut records.entry(school.to_owned()).or_default().1
+}
+
+fn team_id(school: &str) -> String {
+    format!("cfb:{school}")
+}
+
+fn new_team_item(team: Team, record: Option<&(Option<i64>, Record)>, user_id: &UserId) -> Item {
+    let mut metadata = Map::new();
+    let strings = [
+        ("conference", team.conference),
+        ("division", team.division),
+        ("abbreviation", team.abbreviation),
+        ("mascot", team.mascot),
+    ];
+    for (key, value) in strings {
+        if let Some(value) = value {
+            metadata.insert(String::from(key), Value::String(value));
+        }
+    }
+    if let Some((season, record)) = record {
+        if let Some(season) = season {
+            metadata.insert(String::from("season"), Value::Number((*season).into()));
+        }
+        let numbers = [
+            ("wins", record.wins),
+            ("losses", record.losses),
+            ("ties", record.ties),
+            ("conference_wins", record.conference_wins),
+            ("conference_losses", record.conference_losses),
+        ];
+        for (key, value) in numbers {
+            metadata.insert(String::from(key), Value::Number(value.into()));
+        }
+    }
+    Item {
+        id: team_id(&team.school),
+        user_id: user_id.0.clone(),
+        r#type: String::from("team"),
+        name: team.school,
+        iframe: None,
+        rating: None,
+        user_score: 1500,
+        user_wins: 0,
+        user_losses: 0,
+        metadata,
+        hidden: false,
+    }
+}
+
+/// Play the games between teams of the list as matches and return the number of matches
+///
+/// Score changes are recorded at the start of the games so score trajectories follow the season.
+/// Games that were already seeded in the list are skipped and everything is saved in one
+/// transaction so importing the same games again doesn't count them twice.
+async fn seed_game_matches(
+    client: &SqlSessionClient,
+    user_id: &UserId,
+    list_id: &str,
+    games: &[Game],
+) -> Result<usize, Error> {
+    let mut list = super::get_list(client, user_id, list_id).await?;
+    let positions: HashMap<_, _> = list
+        .items
+        .iter()
+        .enumerate()
+        .map(|(i, item)| (item.id.clone(), i))
+        .collect();
+    let ids: Vec<_> = positions.keys().cloned().collect();
+    if ids.is_empty() {
+        return Ok(0);
+    }
+    let mut items: HashMap<_, _> = client
+        .query_documents::<RawItem>(QueryDocumentsBuilder::new(
+            "item",
+            View::List(user_id.clone(), ids),
+            CosmosQuery::new("SELECT * FROM item".into_query()?),
+        ))
+        .await?
+        .into_iter()
+        .map(|item| Ok((item.id.clone(), Item::try_from(item)?)))
+        .collect::<Result<_, Error>>()?;
+    let path = client.path;
+    let seeded: HashSet<_> = {
+        let (user_id, list_id) = (user_id.clone(), list_id.to_owned());
+        tokio::task::spawn_blocking(move || {
+            SqlSessionClient { path }.get_seeded_games(&user_id, &list_id)
+        })
+        .await
+        .map_err(|e| Error::internal_error(e.to_string()))??
+        .into_iter()
+        .collect()
+    };
+    let mut results: Vec<_> = games
+        .iter()
+        .filter(|game| !seeded.contains(&game.id))
+        .filter_map(|game| {
+            let (winner, loser) = game.result()?;
+            Some((game, team_id(winner), team_id(loser)))
+        })
+        .filter(|(_, winner, loser)| items.contains_key(winner) && items.contains_key(loser))
+        .collect();
+    // Dates are ISO 8601 so they sort chronologically
+    results.sort_by_key(|r| r.0.start_date.as_deref());
+    let now = OffsetDateTime::now_utc().unix_timestamp();
+    let mut changes = Vec::with_capacity(results.len() * 2);
+    let mut played = HashSet::new();
+    for (game, winner, loser) in &results {
+        let timestamp = game
+            .start_date
+            .as_deref()
+            .and_then(|d| OffsetDateTime::parse(d, &Rfc3339).ok())
+            .map_or(now, OffsetDateTime::unix_timestamp);
+        let (win, lose) = (positions[winner], positions[loser]);
+        let (mut win_item, mut lose_item) = (list.items[win].clone(), list.items[lose].clone());
+        update_stats(
+            &mut win_item.score,
+            &mut win_item.wins,
+            &mut lose_item.score,
+            &mut lose_item.losses,
+        );
+        list.items[win] = win_item;
+        list.items[lose] = lose_item;
+        let mut win_item = items.remove(winner).unwrap();
+        let mut lose_item = items.remove(loser).unwrap();
+        update_stats(
+            &mut win_item.user_score,
+            &mut win_item.user_wins,
+            &mut lose_item.user_score,
+            &mut lose_item.user_losses,
+        );
+        for item in [&win_item, &lose_item] {
+            changes.push(ScoreChange {
+                user_id: user_id.0.clone(),
+                list_id: list_id.to_owned(),
+                item_id: item.id.clone(),
+                score: item.user_score,
+                timestamp,
+            });
+        }
+        items.insert(winner.clone(), win_item);
+        items.insert(loser.clone(), lose_item);
+        played.extend([winner, loser]);
+    }
+    if results.is_empty() {
+        return Ok(0);
+    }
+    items.retain(|id, _| played.contains(id));
+    let items: Vec<_> = items.into_values().map(RawItem::from).collect();
+    let seeded_games: Vec<_> = results
+        .iter()
+        .map(|(game, _, _)| SeededGame {
+            user_id: user_id.0.clone(),
+            list_id: list_id.to_owned(),
+            game_id: game.id.clone(),
+        })
+        .collect();
+    let matches = results.len();
+    let list = RawList::from(list);
+    tokio::task::spawn_blocking(move || {
+        SqlSessionClient { path }.save_seeded_matches(&list, &items, &changes, &seeded_games)
+    })
+    .await
+    .map_err(|e| Error::internal_error(e.to_string()))??;
+    Ok(matches)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Game, Record, Team};
+    use serde_json::json;
+    use zeroflops::{Error, UserId};
+
+    const TEAMS: &str = "id,school,mascot,abbreviation,conference,division
+333,Alabama,Crimson Tide,ALA,SEC,West
+61,Georgia,Bulldogs,UGA,SEC,East
+";
+
+    const GAMES: &str = r#"[
+        {"id": 1, "season": 2022, "startDate": "2022-12-03T20:00:00.000Z", "homeTeam": "Georgia", "homeConference": "SEC", "homePoints": 50, "awayTeam": "LSU", "awayConference": "SEC", "awayPoints": 30, "conferenceGame": true},
+        {"id": 2, "season": 2023, "startDate": "2023-09-02T16:00:00.000Z", "homeTeam": "Alabama", "homeConference": "SEC", "homePoints": 56, "awayTeam": "Middle Tennessee", "awayConference": "Conference USA", "awayPoints": 7, "conferenceGame": false},
+        {"id": 3, "season": 2023, "startDate": "2023-12-02T21:00:00.000Z", "homeTeam": "Georgia", "homeConference": "SEC", "homePoints": 24, "awayTeam": "Alabama", "awayConference": "SEC", "awayPoints": 27, "conferenceGame": true},
+        {"id": 4, "season": 2023, "startDate": "2024-01-01T22:00:00.000Z", "homeTeam": "Alabama", "homeConference": "SEC", "homePoints": null, "awayTeam": "Michigan", "awayConference": "Big Ten", "awayPoints": null, "conferenceGame": false}
+    ]"#;
+
+    #[test]
+    fn test_read_teams() {
+        let (teams, games) = super::read_file("teams.csv", TEAMS.as_bytes()).unwrap();
+        assert!(games.is_empty());
+        assert_eq!(
+            teams[0],
+            Team {
+                school: String::from("Alabama"),
+                conference: Some(String::from("SEC")),
+                division: Some(String::from("West")),
+                abbreviation: Some(String::from("ALA")),
+                mascot: Some(String::from("Crimson Tide")),
+            }
+        );
+        assert_eq!(teams[1].school, "Georgia");
+    }
+
+    #[test]
+    fn test_read_games() {
+        let (teams, games) = super::read_file("games.json", GAMES.as_bytes()).unwrap();
+        assert!(teams.is_empty());
+        assert_eq!(
+            games[2],
+            Game {
+                id: String::from("3"),
+                season: Some(2023),
+                start_date: Some(String::from("2023-12-02T21:00:00.000Z")),
+                home_team: String::from("Georgia"),
+                home_conference: Some(String::from("SEC")),
+                home_points: Some(24),
+                away_team: String::from("Alabama"),
+                away_conference: Some(String::from("SEC")),
+                away_points: Some(27),
+                conference_game: true,
+            }
+        );
+        assert_eq!(games[2].result(), Some(("Alabama", "Georgia")));
+        assert_eq!(games[3].result(), None);
+
+        let teams = super::get_game_teams(&games);
+        assert_eq!(
+            teams.iter().map(|t| t.school.as_str()).collect::<Vec<_>>(),
+            vec!["Georgia", "LSU", "Alabama", "Middle Tennessee", "Michigan"]
+        );
+    }
+
+    #[test]
+    fn test_read_snake_case_games() {
+        let (_, games) = super::read_file(
+            "games.csv",
+            b"id,season,start_date,home_team,home_points,away_team,away_points,conference_game\n1,2023,2023-09-02T16:00:00.000Z,Alabama,56,Middle Tennessee,7,false\n",
+        )
+        .unwrap();
+        assert_eq!(games[0].id, "1");
+        assert_eq!(games[0].home_points, Some(56));
+        assert_eq!(games[0].away_team, "Middle Tennessee");
+        assert!(!games[0].conference_game);
+    }
+
+    #[test]
+    fn test_read_invalid_rows() {
+        let result = super::read_file(
+            "games.json",
+            br#"[{"homeTeam": "Alabama", "awayTeam": "Georgia", "homePoints": "a lot"}, {"name": "Alabama"}, {"homeTeam": "Alabama"}]"#,
+        );
+        let Err(Error::ClientError(error)) = result else {
+            panic!("expected row errors");
+        };
+        assert_eq!(
+            error,
+            "row 1: invalid home_points a lot\nrow 2: expected a team or a game\nrow 3: missing away_team"
+        );
+    }
+
+    #[test]
+    fn test_team_records() {
+        let (_, games) = super::read_file("games.json", GAMES.as_bytes()).unwrap();
+        let records = super::get_records(&games);
+        // Only the latest season is counted
+        assert!(!records.contains_key("LSU"));
+        assert_eq!(
+            records["Alabama"],
+            (
+                Some(2023),
+                Record {
+                    wins: 2,
+                    conference_wins: 1,
+                    ..Record::default()
+                }
+            )
+        );
+        let (teams, _) = super::read_file("teams.csv", TEAMS.as_bytes()).unwrap();
+        let item = super::new_team_item(
+            teams.into_iter().nth(1).unwrap(),
+            records.get("Georgia"),
+            &UserId(String::from("user")),
+        );
+        assert_eq!(item.id, "cfb:Georgia");
+        assert_eq!(item.r#type, "team");
+        assert_eq!(
+            serde_json::Value::Object(item.metadata),
+            json!({"conference": "SEC", "division": "East", "abbreviation": "UGA", "mascot": "Bulldogs", "season": 2023, "wins": 0, "losses": 1, "ties": 0, "conference_wins": 0, "conference_losses": 1})
+        );
+    }
+}
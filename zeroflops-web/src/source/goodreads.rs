@@ -0,0 +1,182 @@
+//! Books from Goodreads library exports
+//!
+//! The export is a goodreads_library_export.csv file with a row for every shelved book.
+use super::{upload, Registry};
+use serde_json::{Map, Value};
+use zeroflops::{storage::SessionClient, Error, List, Source, SourceType, UserId};
+
+/// Add the books of a Goodreads library export to the list
+///
+/// Importing a file with the same name replaces the previous import.
+pub async fn import_books<C: SessionClient + Sync>(
+    client: &C,
+    registry: &Registry<C>,
+    user_id: &UserId,
+    list: List,
+    file_name: String,
+    data: &[u8],
+) -> Result<(), Error> {
+    let items = read_books(user_id, data)?;
+    let ids = items.iter().map(|i| i.id.clone()).collect();
+    super::import_items(
+        client,
+        registry,
+        user_id,
+        list,
+        Source::new(SourceType::Goodreads(ids), file_name),
+        items,
+    )
+    .await
+}
+
+fn read_books(user_id: &UserId, data: &[u8]) -> Result<Vec<crate::Item>, Error> {
+    let rows = upload::read_csv(data)?;
+    let mut items = Vec::with_capacity(rows.len());
+    let mut errors = Vec::new();
+    for (i, row) in rows.into_iter().enumerate() {
+        match row.and_then(|row| read_book(user_id, &row)) {
+            Ok(item) => items.push(item),
+            Err(e) => errors.push(format!("row {}: {e}", i + 1)),
+        }
+    }
+    upload::check_row_errors(errors)?;
+    Ok(items)
+}
+
+fn read_book(user_id: &UserId, row: &Map<String, Value>) -> Result<crate::Item, String> {
+    let cell = |column| upload::cell(row, column);
+    let number = |column| match cell(column) {
+        Some(n) => n
+            .parse::<i64>()
+            .map(Some)
+            .map_err(|_| format!("invalid {column} {n}")),
+        None => Ok(None),
+    };
+    let id = cell("Book Id").ok_or("missing Book Id")?;
+    let title = cell("Title").ok_or("missing Title")?;
+    // Unrated books have a rating of 0
+    let rating = match number("My Rating")? {
+        Some(0) | None => None,
+        Some(stars @ 1..=5) => Some(stars as i32 * 2),
+        Some(stars) => return Err(format!("invalid My Rating {stars}")),
+    };
+    let mut metadata = Map::new();
+    let strings = [
+        ("author", cell("Author")),
+        ("additional_authors", cell("Additional Authors")),
+        ("isbn13", cell("ISBN13").map(unquote)),
+        ("shelf", cell("Exclusive Shelf")),
+    ];
+    // Dates use slashes like 2021/03/14
+    let dates = [
+        ("date_read", cell("Date Read")),
+        ("date_added", cell("Date Added")),
+    ];
+    for (key, value) in strings {
+        if let Some(value) = value.filter(|v| !v.is_empty()) {
+            metadata.insert(String::from(key), Value::String(value.to_owned()));
+        }
+    }
+    for (key, value) in dates {
+        if let Some(date) = value {
+            metadata.insert(String::from(key), Value::String(date.replace('/', "-")));
+        }
+    }
+    // The publication year of the edition is used if the original one is missing
+    let year = match number("Original Publication Year")? {
+        Some(year) => Some(year),
+        None => number("Year Published")?,
+    };
+    let numbers = [
+        ("year", year),
+        ("pages", number("Number of Pages")?),
+        ("read_count", number("Read Count")?),
+    ];
+    for (key, value) in numbers {
+        if let Some(n) = value {
+            metadata.insert(String::from(key), Value::Number(n.into()));
+        }
+    }
+    if let Some(rating) = cell("Average Rating").and_then(|r| r.parse().ok()) {
+        metadata.insert(
+            String::from("average_rating"),
+            serde_json::Number::from_f64(rating).map_or(Value::Null, Value::Number),
+        );
+    }
+    Ok(crate::Item {
+        id: format!("goodreads:{id}"),
+        user_id: user_id.0.clone(),
+        r#type: String::from("book"),
+        name: title.to_owned(),
+        iframe: None,
+        rating,
+        user_score: 1500,
+        user_wins: 0,
+        user_losses: 0,
+        metadata,
+        hidden: false,
+    })
+}
+
+/// ISBNs are exported as formulas like ="9780441013593" to keep spreadsheets from mangling them
+fn unquote(s: &str) -> &str {
+    s.trim_start_matches('=').trim_matches('"')
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+    use zeroflops::{Error, UserId};
+
+    const LIBRARY: &str = r#"Book Id,Title,Author,Author l-f,Additional Authors,ISBN,ISBN13,My Rating,Average Rating,Publisher,Binding,Number of Pages,Year Published,Original Publication Year,Date Read,Date Added,Bookshelves,Bookshelves with positions,Exclusive Shelf,My Review,Spoiler,Private Notes,Read Count,Owned Copies
+44767458,Dune,Frank Herbert,"Herbert, Frank",,"=""0441013597""","=""9780441013593""",5,4.27,Ace,Paperback,658,2005,1965,2021/03/14,2021/01/02,,,read,,,,1,0
+1,Emma,Jane Austen,"Austen, Jane",,"=""""","=""""",0,4.03,Penguin,Paperback,,2003,,,2022/05/01,to-read,to-read (#1),to-read,,,,0,0
+"#;
+
+    #[test]
+    fn test_read_books() {
+        let items = super::read_books(&UserId(String::from("user")), LIBRARY.as_bytes()).unwrap();
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0].id, "goodreads:44767458");
+        assert_eq!(items[0].name, "Dune");
+        assert_eq!(items[0].rating, Some(10));
+        assert_eq!(
+            serde_json::Value::Object(items[0].metadata.clone()),
+            json!({
+                "author": "Frank Herbert",
+                "isbn13": "9780441013593",
+                "shelf": "read",
+                "date_read": "2021-03-14",
+                "date_added": "2021-01-02",
+                "year": 1965,
+                "pages": 658,
+                "read_count": 1,
+                "average_rating": 4.27,
+            })
+        );
+        assert_eq!(items[1].rating, None);
+        assert_eq!(
+            serde_json::Value::Object(items[1].metadata.clone()),
+            json!({
+                "author": "Jane Austen",
+                "shelf": "to-read",
+                "date_added": "2022-05-01",
+                "year": 2003,
+                "read_count": 0,
+                "average_rating": 4.03,
+            })
+        );
+    }
+
+    #[test]
+    fn test_read_invalid_books() {
+        let result = super::read_books(
+            &UserId(String::from("user")),
+            b"Book Id,Title,My Rating\n1,,3\n2,Emma,7\n",
+        );
+        let Err(Error::ClientError(error)) = result else {
+            panic!("expected row errors");
+        };
+        assert_eq!(error, "row 1: missing Title\nrow 2: invalid My Rating 7");
+    }
+}
@@ -0,0 +1,336 @@
+//! Items from the IMDb datasets (https://developer.imdb.com/non-commercial-datasets/)
+//!
+//! The extracted title.basics.tsv and title.ratings.tsv files are read from the IMDB_DIR directory.
+use super::DataSource;
+use async_trait::async_trait;
+use serde_json::{Map, Value};
+use std::{
+    collections::{HashMap, HashSet},
+    fs::File,
+    io::{BufRead, BufReader},
+    path::PathBuf,
+};
+use zeroflops::{
+    storage::SessionClient, Error, Imdb, ImdbQuery, ItemMetadata, Source, SourceType, UserId,
+};
+
+// Filtered queries can match most of the dataset
+const MAX_QUERY_ITEMS: usize = 1000;
+
+struct Rating {
+    average_rating: f64,
+    num_votes: u32,
+}
+
+struct Title {
+    id: String,
+    title_type: String,
+    name: String,
+    year: Option<i32>,
+    runtime: Option<u32>,
+    genres: Vec<String>,
+}
+
+pub struct ImdbSource;
+
+#[async_trait]
+impl<C: SessionClient + Sync> DataSource<C> for ImdbSource {
+    fn handles(&self, source_type: &SourceType) -> bool {
+        matches!(source_type, SourceType::Imdb(_))
+    }
+
+    async fn resolve(
+        &self,
+        client: &C,
+        user_id: &UserId,
+        source: Source,
+    ) -> Result<(Source, Vec<ItemMetadata>), Error> {
+        let SourceType::Imdb(imdb) = source.source_type else {
+            return Err(Error::client_error("Unsupported source"));
+        };
+        let (source, items) = get_titles(user_id, imdb).await?;
+        Ok((source, super::save_items(client, items).await?))
+    }
+}
+
+pub async fn get_titles(user_id: &UserId, imdb: Imdb) -> Result<(Source, Vec<crate::Item>), Error> {
+    let dir = PathBuf::from(
+        std::env::var("IMDB_DIR").map_err(|_| Error::internal_error("IMDB_DIR is missing"))?,
+    );
+    let user_id = user_id.clone();
+    // The dumps are large so avoid blocking the runtime while reading them
+    tokio::task::spawn_blocking(move || {
+        let ratings = BufReader::new(File::open(dir.join("title.ratings.tsv"))?);
+        let basics = BufReader::new(File::open(dir.join("title.basics.tsv"))?);
+        read_titles(&user_id, imdb, ratings, basics)
+    })
+    .await
+    .map_err(|e| Error::internal_error(e.to_string()))?
+}
+
+fn read_titles(
+    user_id: &UserId,
+    imdb: Imdb,
+    ratings: impl BufRead,
+    basics: impl BufRead,
+) -> Result<(Source, Vec<crate::Item>), Error> {
+    let ratings = read_ratings(ratings)?;
+    let (name, titles) = match &imdb {
+        Imdb::Titles(ids) => {
+            let wanted: HashSet<_> = ids.iter().map(String::as_str).collect();
+            let mut titles: HashMap<_, _> =
+                read_basics(basics, |t| wanted.contains(t.id.as_str()))?
+                    .into_iter()
+                    .map(|t| (t.id.clone(), t))
+                    .collect();
+            let missing: Vec<_> = ids
+                .iter()
+                .filter(|id| !titles.contains_key(*id))
+                .map(String::as_str)
+                .collect();
+            if !missing.is_empty() {
+                return Err(Error::client_error(format!(
+                    "unknown IMDb titles: {}",
+                    missing.join(", ")
+                )));
+            }
+            // Keep the order of the source
+            let titles: Vec<_> = ids.iter().filter_map(|id| titles.remove(id)).collect();
+            (String::from("IMDb titles"), titles)
+        }
+        Imdb::Query(query) => {
+            let mut titles = read_basics(basics, |t| {
+                matches(query, t, ratings.get(&t.id).map_or(0, |r| r.num_votes))
+            })?;
+            titles
+                .sort_by_key(|t| std::cmp::Reverse(ratings.get(&t.id).map_or(0, |r| r.num_votes)));
+            titles.truncate(MAX_QUERY_ITEMS);
+            (query_name(query), titles)
+        }
+    };
+    let items = titles
+        .into_iter()
+        .map(|t| {
+            let rating = ratings.get(&t.id);
+            new_imdb_item(t, rating, user_id)
+        })
+        .collect();
+    Ok((Source::new(SourceType::Imdb(imdb), name), items))
+}
+
+fn matches(query: &ImdbQuery, title: &Title, num_votes: u32) -> bool {
+    query.title_type.iter().all(|t| *t == title.title_type)
+        && query.year.iter().all(|y| Some(*y) == title.year)
+        && query.genre.iter().all(|g| {
+            title
+                .genres
+                .iter()
+                .any(|genre| genre.eq_ignore_ascii_case(g))
+        })
+        && query.min_votes.iter().all(|v| num_votes >= *v)
+}
+
+fn query_name(query: &ImdbQuery) -> String {
+    let mut name = vec![String::from("IMDb")];
+    if let Some(year) = query.year {
+        name.push(year.to_string());
+    }
+    if let Some(genre) = &query.genre {
+        name.push(genre.clone());
+    }
+    name.push(
+        query
+            .title_type
+            .as_ref()
+            .map_or(String::from("titles"), |t| format!("{t} titles")),
+    );
+    if let Some(min_votes) = query.min_votes {
+        name.push(format!("with {min_votes}+ votes"));
+    }
+    name.join(" ")
+}
+
+/// Missing values are \N in the datasets
+fn field(s: &str) -> Option<&str> {
+    if s == "\\N" {
+        None
+    } else {
+        Some(s)
+    }
+}
+
+fn read_ratings(reader: impl BufRead) -> Result<HashMap<String, Rating>, Error> {
+    let mut ratings = HashMap::new();
+    // Skip the header
+    for line in reader.lines().skip(1) {
+        let line = line?;
+        let mut columns = line.split('\t');
+        let (Some(id), Some(average_rating), Some(num_votes)) =
+            (columns.next(), columns.next(), columns.next())
+        else {
+            return Err(Error::internal_error(format!(
+                "Unexpected row in title.ratings.tsv: {line}"
+            )));
+        };
+        ratings.insert(
+            id.to_owned(),
+            Rating {
+                average_rating: average_rating.parse().unwrap_or_default(),
+                num_votes: num_votes.parse().unwrap_or_default(),
+            },
+        );
+    }
+    Ok(ratings)
+}
+
+fn read_basics(
+    reader: impl BufRead,
+    mut filter: impl FnMut(&Title) -> bool,
+) -> Result<Vec<Title>, Error> {
+    let mut titles = Vec::new();
+    for line in reader.lines().skip(1) {
+        let line = line?;
+        // tconst, titleType, primaryTitle, originalTitle, isAdult, startYear, endYear,
+        // runtimeMinutes, genres
+        let columns: Vec<_> = line.split('\t').collect();
+        let [id, title_type, name, _, _, year, _, runtime, genres] = columns[..] else {
+            return Err(Error::internal_error(format!(
+                "Unexpected row in title.basics.tsv: {line}"
+            )));
+        };
+        let title = Title {
+            id: id.to_owned(),
+            title_type: title_type.to_owned(),
+            name: name.to_owned(),
+            year: field(year).and_then(|y| y.parse().ok()),
+            runtime: field(runtime).and_then(|r| r.parse().ok()),
+            genres: field(genres)
+                .map(|g| g.split(',').map(ToOwned::to_owned).collect())
+                .unwrap_or_default(),
+        };
+        if filter(&title) {
+            titles.push(title);
+        }
+    }
+    Ok(titles)
+}
+
+fn new_imdb_item(title: Title, rating: Option<&Rating>, user_id: &UserId) -> crate::Item {
+    let mut metadata = Map::new();
+    if let Some(year) = title.year {
+        metadata.insert(String::from("year"), Value::Number(year.into()));
+    }
+    metadata.insert(
+        String::from("genres"),
+        Value::Array(title.genres.into_iter().map(Value::String).collect()),
+    );
+    if let Some(runtime) = title.runtime {
+        metadata.insert(String::from("runtime"), Value::Number(runtime.into()));
+    }
+    if let Some(rating) = rating {
+        metadata.insert(
+            String::from("averageRating"),
+            serde_json::Number::from_f64(rating.average_rating).map_or(Value::Null, Value::Number),
+        );
+        metadata.insert(
+            String::from("numVotes"),
+            Value::Number(rating.num_votes.into()),
+        );
+    }
+    crate::Item {
+        id: format!("imdb:{}", title.id),
+        user_id: user_id.0.clone(),
+        r#type: title.title_type,
+        name: title.name,
+        iframe: None,
+        rating: None,
+        user_score: 1500,
+        user_wins: 0,
+        user_losses: 0,
+        metadata,
+        hidden: false,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+    use zeroflops::{Error, Imdb, ImdbQuery, UserId};
+
+    const BASICS: &str = "tconst\ttitleType\tprimaryTitle\toriginalTitle\tisAdult\tstartYear\tendYear\truntimeMinutes\tgenres
+tt0111161\tmovie\tThe Shawshank Redemption\tThe Shawshank Redemption\t0\t1994\t\\N\t142\tDrama
+tt0110912\tmovie\tPulp Fiction\tPulp Fiction\t0\t1994\t\\N\t154\tCrime,Drama
+tt0109830\tmovie\tForrest Gump\tForrest Gump\t0\t1994\t\\N\t142\tDrama,Romance
+tt0903747\ttvSeries\tBreaking Bad\tBreaking Bad\t0\t2008\t2013\t49\tCrime,Drama,Thriller
+";
+
+    const RATINGS: &str = "tconst\taverageRating\tnumVotes
+tt0111161\t9.3\t2900000
+tt0110912\t8.9\t2200000
+tt0109830\t8.8\t2300000
+tt0903747\t9.5\t2100000
+";
+
+    #[test]
+    fn test_read_titles() {
+        let (source, items) = super::read_titles(
+            &UserId(String::from("user")),
+            Imdb::Titles(vec![String::from("tt0903747"), String::from("tt0111161")]),
+            RATINGS.as_bytes(),
+            BASICS.as_bytes(),
+        )
+        .unwrap();
+        assert_eq!(source.name, "IMDb titles");
+        assert_eq!(
+            items.iter().map(|i| i.id.as_str()).collect::<Vec<_>>(),
+            vec!["imdb:tt0903747", "imdb:tt0111161"]
+        );
+        assert_eq!(items[1].r#type, "movie");
+        assert_eq!(
+            serde_json::Value::Object(items[1].metadata.clone()),
+            json!({
+                "year": 1994,
+                "genres": ["Drama"],
+                "runtime": 142,
+                "averageRating": 9.3,
+                "numVotes": 2900000,
+            })
+        );
+    }
+
+    #[test]
+    fn test_read_unknown_titles() {
+        let result = super::read_titles(
+            &UserId(String::from("user")),
+            Imdb::Titles(vec![String::from("tt0000000")]),
+            RATINGS.as_bytes(),
+            BASICS.as_bytes(),
+        );
+        assert!(matches!(result, Err(Error::ClientError(_))));
+    }
+
+    #[test]
+    fn test_query_titles() {
+        let (source, items) = super::read_titles(
+            &UserId(String::from("user")),
+            Imdb::Query(ImdbQuery {
+                title_type: Some(String::from("movie")),
+                year: Some(1994),
+                genre: Some(String::from("drama")),
+                min_votes: Some(2_250_000),
+            }),
+            RATINGS.as_bytes(),
+            BASICS.as_bytes(),
+        )
+        .unwrap();
+        assert_eq!(
+            source.name,
+            "IMDb 1994 drama movie titles with 2250000+ votes"
+        );
+        // Ordered by votes
+        assert_eq!(
+            items.iter().map(|i| i.name.as_str()).collect::<Vec<_>>(),
+            vec!["The Shawshank Redemption", "Forrest Gump"]
+        );
+    }
+}
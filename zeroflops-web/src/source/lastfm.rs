@@ -0,0 +1,413 @@
+//! Items from Last.fm scrobble exports
+//!
+//! Scrobbled tracks are matched to Spotify tracks with a search so the items can be ranked and
+//! pushed like any other Spotify item.
+use super::{spotify, upload, Registry};
+use futures::StreamExt;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use time::{format_description::well_known::Rfc3339, OffsetDateTime, PrimitiveDateTime};
+use zeroflops::{
+    storage::SessionClient, Error, List, ScrobbleImport, Source, SourceType, UnmatchedTrack, UserId,
+};
+
+#[derive(Debug, PartialEq)]
+struct Scrobble {
+    artist: String,
+    track: String,
+    // Unix timestamp in seconds
+    timestamp: Option<i64>,
+}
+
+#[derive(Debug, PartialEq)]
+struct TrackPlays {
+    artist: String,
+    track: String,
+    play_count: usize,
+    first_played: Option<i64>,
+    last_played: Option<i64>,
+}
+
+impl TrackPlays {
+    fn merge(&mut self, other: &TrackPlays) {
+        self.play_count += other.play_count;
+        self.first_played = self
+            .first_played
+            .into_iter()
+            .chain(other.first_played)
+            .min();
+        self.last_played = self.last_played.max(other.last_played);
+    }
+}
+
+type Row = Result<Option<Scrobble>, String>;
+
+/// Add the scrobbled tracks to the list
+///
+/// Importing a file with the same name replaces the previous import.
+pub async fn import_scrobbles<C: SessionClient + Sync>(
+    client: &C,
+    registry: &Registry<C>,
+    user_id: &UserId,
+    list: List,
+    file_name: String,
+    data: &[u8],
+) -> Result<ScrobbleImport, Error> {
+    let scrobbles = read_scrobbles(&file_name, data)?;
+    let count = scrobbles.len();
+    let token = spotify::get_token().await?;
+    let results: Vec<_> =
+        futures::stream::iter(group_scrobbles(scrobbles).into_iter().map(|plays| {
+            let token = &token;
+            async move {
+                let item = spotify::search_song(
+                    token,
+                    plays.track.clone(),
+                    Some(plays.artist.clone()),
+                    user_id,
+                )
+                .await;
+                (plays, item)
+            }
+        }))
+        .buffered(5)
+        .collect()
+        .await;
+    // Different spellings can match the same Spotify track
+    let mut matches: Vec<(crate::Item, TrackPlays)> = Vec::new();
+    let mut indices: HashMap<String, usize> = HashMap::new();
+    let mut unmatched = Vec::new();
+    for (plays, item) in results {
+        match item {
+            Ok(item) => {
+                if let Some(&i) = indices.get(&item.id) {
+                    matches[i].1.merge(&plays);
+                } else {
+                    indices.insert(item.id.clone(), matches.len());
+                    matches.push((item, plays));
+                }
+            }
+            Err(Error::ClientError(_)) => unmatched.push(UnmatchedTrack {
+                artist: plays.artist,
+                track: plays.track,
+                play_count: plays.play_count,
+            }),
+            Err(e) => return Err(e),
+        }
+    }
+    let items: Vec<_> = matches
+        .into_iter()
+        .map(|(mut item, plays)| {
+            add_play_metadata(&mut item.metadata, &plays);
+            item
+        })
+        .collect();
+    let ids = items.iter().map(|i| i.id.clone()).collect();
+    let summary = ScrobbleImport {
+        scrobbles: count,
+        items: items.len(),
+        unmatched,
+    };
+    super::import_items(
+        client,
+        registry,
+        user_id,
+        list,
+        Source::new(SourceType::Lastfm(ids), file_name),
+        items,
+    )
+    .await?;
+    Ok(summary)
+}
+
+fn add_play_metadata(metadata: &mut Map<String, Value>, plays: &TrackPlays) {
+    metadata.insert(String::from("play_count"), plays.play_count.into());
+    if let Some(first_played) = plays.first_played {
+        metadata.insert(String::from("first_played"), first_played.into());
+    }
+    if let Some(last_played) = plays.last_played {
+        metadata.insert(String::from("last_played"), last_played.into());
+    }
+}
+
+/// Most played tracks come first
+fn group_scrobbles(scrobbles: Vec<Scrobble>) -> Vec<TrackPlays> {
+    let mut tracks: HashMap<_, TrackPlays> = HashMap::new();
+    for scrobble in scrobbles {
+        let plays = TrackPlays {
+            play_count: 1,
+            first_played: scrobble.timestamp,
+            last_played: scrobble.timestamp,
+            artist: scrobble.artist,
+            track: scrobble.track,
+        };
+        let key = (plays.artist.to_lowercase(), plays.track.to_lowercase());
+        if let Some(current) = tracks.get_mut(&key) {
+            current.merge(&plays);
+        } else {
+            tracks.insert(key, plays);
+        }
+    }
+    let mut tracks: Vec<_> = tracks.into_values().collect();
+    tracks.sort_by(|a, b| {
+        b.play_count
+            .cmp(&a.play_count)
+            .then_with(|| a.artist.cmp(&b.artist))
+            .then_with(|| a.track.cmp(&b.track))
+    });
+    tracks
+}
+
+fn read_scrobbles(file_name: &str, data: &[u8]) -> Result<Vec<Scrobble>, Error> {
+    let rows = if file_name.to_lowercase().ends_with(".json") {
+        read_json(data)?
+    } else {
+        read_csv(data)?
+    };
+    let mut scrobbles = Vec::with_capacity(rows.len());
+    let mut errors = Vec::new();
+    for (i, row) in rows.into_iter().enumerate() {
+        match row {
+            Ok(Some(scrobble)) => scrobbles.push(scrobble),
+            Ok(None) => {}
+            Err(e) => errors.push(format!("row {}: {e}", i + 1)),
+        }
+    }
+    upload::check_row_errors(errors)?;
+    Ok(scrobbles)
+}
+
+/// Exports either have a header row or use the artist, album, track and date columns
+fn read_csv(data: &[u8]) -> Result<Vec<Row>, Error> {
+    let mut records = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(data)
+        .into_records();
+    let mut rows = Vec::new();
+    let Some(first) = records.next() else {
+        return Ok(rows);
+    };
+    let first = first.map_err(|e| Error::client_error(e.to_string()))?;
+    let find = |names: &[&str]| {
+        first
+            .iter()
+            .position(|c| names.contains(&c.trim().to_lowercase().as_str()))
+    };
+    let header = match (find(&["artist"]), find(&["track", "title", "name"])) {
+        (Some(artist), Some(track)) => Some((artist, track, find(&["date", "timestamp", "uts"]))),
+        _ => None,
+    };
+    let (artist, track, date) = header.unwrap_or((0, 2, Some(3)));
+    let read_row = |record: &csv::StringRecord| {
+        new_scrobble(
+            record.get(artist),
+            record.get(track),
+            date.and_then(|d| record.get(d)),
+        )
+        .map(Some)
+    };
+    if header.is_none() {
+        rows.push(read_row(&first));
+    }
+    for record in records {
+        rows.push(record.map_err(|e| e.to_string()).and_then(|r| read_row(&r)));
+    }
+    Ok(rows)
+}
+
+fn read_json(data: &[u8]) -> Result<Vec<Row>, Error> {
+    let value = serde_json::from_slice(data)
+        .map_err(|e| Error::client_error(format!("invalid JSON: {e}")))?;
+    let mut tracks = Vec::new();
+    collect_tracks(value, &mut tracks);
+    Ok(tracks.into_iter().map(json_scrobble).collect())
+}
+
+/// Exports are either lists of scrobbles or pages of user.getRecentTracks responses
+fn collect_tracks(value: Value, tracks: &mut Vec<Value>) {
+    match value {
+        Value::Array(a) => {
+            for value in a {
+                collect_tracks(value, tracks);
+            }
+        }
+        Value::Object(mut o) => {
+            if let Some(page) = o.remove("recenttracks") {
+                collect_tracks(page, tracks);
+            } else if o.get("track").is_some_and(Value::is_array) {
+                collect_tracks(o.remove("track").unwrap_or_default(), tracks);
+            } else {
+                tracks.push(Value::Object(o));
+            }
+        }
+        value => tracks.push(value),
+    }
+}
+
+fn json_scrobble(value: Value) -> Row {
+    let Value::Object(o) = value else {
+        return Err(String::from("expected an object"));
+    };
+    // The track that is playing right now doesn't have a date yet
+    if o.get("@attr").and_then(|a| a.get("nowplaying")).is_some() {
+        return Ok(None);
+    }
+    let field = |keys: &[&str]| keys.iter().find_map(|k| o.get(*k)).and_then(text);
+    new_scrobble(
+        field(&["artist"]).as_deref(),
+        field(&["name", "track", "title"]).as_deref(),
+        field(&["date", "timestamp", "uts"]).as_deref(),
+    )
+    .map(Some)
+}
+
+/// API responses wrap values like {"#text": "Artist", "mbid": ""}
+fn text(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        Value::Object(o) => ["uts", "#text", "name"]
+            .iter()
+            .find_map(|k| o.get(*k))
+            .and_then(text),
+        _ => None,
+    }
+}
+
+fn new_scrobble(
+    artist: Option<&str>,
+    track: Option<&str>,
+    date: Option<&str>,
+) -> Result<Scrobble, String> {
+    let artist = non_empty(artist).ok_or("missing artist")?;
+    let track = non_empty(track).ok_or("missing track")?;
+    let timestamp = match non_empty(date) {
+        Some(date) => Some(parse_date(date).ok_or_else(|| format!("invalid date {date}"))?),
+        None => None,
+    };
+    Ok(Scrobble {
+        artist: artist.to_owned(),
+        track: track.to_owned(),
+        timestamp,
+    })
+}
+
+fn non_empty(s: Option<&str>) -> Option<&str> {
+    s.map(str::trim).filter(|s| !s.is_empty())
+}
+
+fn parse_date(date: &str) -> Option<i64> {
+    if let Ok(uts) = date.parse() {
+        return Some(uts);
+    }
+    if let Ok(date) = OffsetDateTime::parse(date, &Rfc3339) {
+        return Some(date.unix_timestamp());
+    }
+    // The format used by the Last.fm website and API, e.g. 31 Dec 2020, 23:59
+    let format = time::format_description::parse(
+        "[day padding:none] [month repr:short] [year] [hour]:[minute]",
+    )
+    .unwrap();
+    PrimitiveDateTime::parse(&date.replace(',', ""), &format)
+        .ok()
+        .map(|date| date.assume_utc().unix_timestamp())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Scrobble, TrackPlays};
+    use zeroflops::Error;
+
+    #[test]
+    fn test_read_csv_scrobbles() {
+        // Without a header row
+        let scrobbles = super::read_scrobbles(
+            "scrobbles.csv",
+            b"Radiohead,OK Computer,Airbag,\"31 Dec 2020, 23:59\"\nRadiohead,OK Computer,Lucky,1609459200\n",
+        )
+        .unwrap();
+        assert_eq!(
+            scrobbles,
+            vec![
+                Scrobble {
+                    artist: String::from("Radiohead"),
+                    track: String::from("Airbag"),
+                    timestamp: Some(1609459140),
+                },
+                Scrobble {
+                    artist: String::from("Radiohead"),
+                    track: String::from("Lucky"),
+                    timestamp: Some(1609459200),
+                },
+            ]
+        );
+
+        let scrobbles = super::read_scrobbles(
+            "scrobbles.csv",
+            b"uts,artist,track\n1609459200,Artist,Song\n",
+        )
+        .unwrap();
+        assert_eq!(scrobbles[0].track, "Song");
+
+        let result = super::read_scrobbles("scrobbles.csv", b"artist,track\nArtist,\n");
+        let Err(Error::ClientError(error)) = result else {
+            panic!("expected row errors");
+        };
+        assert_eq!(error, "row 1: missing track");
+    }
+
+    #[test]
+    fn test_read_json_scrobbles() {
+        let scrobbles = super::read_scrobbles(
+            "scrobbles.json",
+            br##"[{"recenttracks": {"track": [
+                {"artist": {"mbid": "", "#text": "Artist"}, "name": "Song", "album": {"#text": "Album"}, "@attr": {"nowplaying": "true"}},
+                {"artist": {"mbid": "", "#text": "Artist"}, "name": "Song", "album": {"#text": "Album"}, "date": {"uts": "1609459200", "#text": "01 Jan 2021, 00:00"}}
+            ]}}]"##,
+        )
+        .unwrap();
+        assert_eq!(
+            scrobbles,
+            vec![Scrobble {
+                artist: String::from("Artist"),
+                track: String::from("Song"),
+                timestamp: Some(1609459200),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_group_scrobbles() {
+        let scrobble = |artist: &str, track: &str, timestamp| Scrobble {
+            artist: artist.to_owned(),
+            track: track.to_owned(),
+            timestamp,
+        };
+        let tracks = super::group_scrobbles(vec![
+            scrobble("Artist", "Song", Some(200)),
+            scrobble("Artist", "Other Song", Some(150)),
+            scrobble("artist", "song", Some(100)),
+            scrobble("Artist", "Song", None),
+        ]);
+        assert_eq!(
+            tracks,
+            vec![
+                TrackPlays {
+                    artist: String::from("Artist"),
+                    track: String::from("Song"),
+                    play_count: 3,
+                    first_played: Some(100),
+                    last_played: Some(200),
+                },
+                TrackPlays {
+                    artist: String::from("Artist"),
+                    track: String::from("Other Song"),
+                    play_count: 1,
+                    first_played: Some(150),
+                    last_played: Some(150),
+                },
+            ]
+        );
+    }
+}
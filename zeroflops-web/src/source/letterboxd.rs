@@ -0,0 +1,233 @@
+//! Movies from Letterboxd exports
+//!
+//! The diary.csv, ratings.csv, reviews.csv and watched.csv files of the export all have the Date,
+//! Name and Year columns. Films that were logged more than once become a single item.
+use super::{upload, Registry};
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use zeroflops::{storage::SessionClient, Error, List, Source, SourceType, UserId};
+
+#[derive(Debug, PartialEq)]
+struct Film {
+    name: String,
+    year: Option<i64>,
+    // Not in the export but can be added as a Director column
+    director: Option<String>,
+    // Half stars from 1 to 10
+    rating: Option<i32>,
+    // When the film was logged
+    date: Option<String>,
+    first_watched: Option<String>,
+    last_watched: Option<String>,
+    watch_count: usize,
+}
+
+impl Film {
+    /// Exports are sorted by date so the rating of the later entry wins
+    fn merge(&mut self, other: Film) {
+        if other.rating.is_some() {
+            self.rating = other.rating;
+        }
+        if self.director.is_none() {
+            self.director = other.director;
+        }
+        self.date = self.date.take().max(other.date);
+        self.first_watched = self
+            .first_watched
+            .take()
+            .into_iter()
+            .chain(other.first_watched)
+            .min();
+        self.last_watched = self.last_watched.take().max(other.last_watched);
+        self.watch_count += other.watch_count;
+    }
+}
+
+/// Add the films of a Letterboxd CSV file to the list
+///
+/// Importing a file with the same name replaces the previous import.
+pub async fn import_films<C: SessionClient + Sync>(
+    client: &C,
+    registry: &Registry<C>,
+    user_id: &UserId,
+    list: List,
+    file_name: String,
+    data: &[u8],
+) -> Result<(), Error> {
+    let items: Vec<_> = read_films(data)?
+        .into_iter()
+        .map(|film| new_film_item(film, user_id))
+        .collect();
+    let ids = items.iter().map(|i| i.id.clone()).collect();
+    super::import_items(
+        client,
+        registry,
+        user_id,
+        list,
+        Source::new(SourceType::Letterboxd(ids), file_name),
+        items,
+    )
+    .await
+}
+
+fn read_films(data: &[u8]) -> Result<Vec<Film>, Error> {
+    let mut films: Vec<Film> = Vec::new();
+    let mut indices: HashMap<_, usize> = HashMap::new();
+    let mut errors = Vec::new();
+    for (i, row) in upload::read_csv(data)?.into_iter().enumerate() {
+        match row.and_then(|row| read_film(&row)) {
+            Ok(film) => {
+                let key = (film.name.clone(), film.year);
+                if let Some(&index) = indices.get(&key) {
+                    films[index].merge(film);
+                } else {
+                    indices.insert(key, films.len());
+                    films.push(film);
+                }
+            }
+            Err(e) => errors.push(format!("row {}: {e}", i + 1)),
+        }
+    }
+    upload::check_row_errors(errors)?;
+    Ok(films)
+}
+
+fn read_film(row: &Map<String, Value>) -> Result<Film, String> {
+    let cell = |column| upload::cell(row, column);
+    let name = cell("Name").ok_or("missing Name")?;
+    let year = match cell("Year") {
+        Some(year) => Some(year.parse().map_err(|_| format!("invalid Year {year}"))?),
+        None => None,
+    };
+    let rating = match cell("Rating") {
+        Some(rating) => {
+            Some(parse_rating(rating).ok_or_else(|| format!("invalid Rating {rating}"))?)
+        }
+        None => None,
+    };
+    // Only the diary has watched dates
+    let watched = cell("Watched Date").map(ToOwned::to_owned);
+    Ok(Film {
+        name: name.to_owned(),
+        year,
+        director: cell("Director")
+            .or_else(|| cell("Directors"))
+            .map(ToOwned::to_owned),
+        rating,
+        date: cell("Date").map(ToOwned::to_owned),
+        first_watched: watched.clone(),
+        watch_count: usize::from(watched.is_some()),
+        last_watched: watched,
+    })
+}
+
+/// Letterboxd ratings are from half a star to 5 stars
+fn parse_rating(rating: &str) -> Option<i32> {
+    let rating = (rating.parse::<f64>().ok()? * 2.0).round();
+    (1.0..=10.0).contains(&rating).then_some(rating as i32)
+}
+
+fn new_film_item(film: Film, user_id: &UserId) -> crate::Item {
+    let mut metadata = Map::new();
+    if let Some(year) = film.year {
+        metadata.insert(String::from("year"), Value::Number(year.into()));
+    }
+    let strings = [
+        ("director", film.director),
+        ("date", film.date),
+        ("first_watched", film.first_watched),
+        ("last_watched", film.last_watched),
+    ];
+    for (key, value) in strings {
+        if let Some(value) = value {
+            metadata.insert(String::from(key), Value::String(value));
+        }
+    }
+    if film.watch_count > 0 {
+        metadata.insert(
+            String::from("watch_count"),
+            Value::Number(film.watch_count.into()),
+        );
+    }
+    crate::Item {
+        // Diary entries have their own URIs so the film is identified by name and year
+        id: match film.year {
+            Some(year) => format!("letterboxd:{} ({year})", film.name),
+            None => format!("letterboxd:{}", film.name),
+        },
+        user_id: user_id.0.clone(),
+        r#type: String::from("movie"),
+        name: film.name,
+        iframe: None,
+        rating: film.rating,
+        user_score: 1500,
+        user_wins: 0,
+        user_losses: 0,
+        metadata,
+        hidden: false,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Film;
+    use serde_json::json;
+    use zeroflops::{Error, UserId};
+
+    const DIARY: &str = "Date,Name,Year,Letterboxd URI,Rating,Rewatch,Tags,Watched Date
+2021-01-02,Parasite,2019,https://boxd.it/1a,4,,,2021-01-01
+2021-03-05,Heat,1995,https://boxd.it/1b,,,,2021-03-05
+2022-06-10,Parasite,2019,https://boxd.it/1c,4.5,Yes,,2022-06-09
+";
+
+    #[test]
+    fn test_read_diary() {
+        let films = super::read_films(DIARY.as_bytes()).unwrap();
+        assert_eq!(
+            films,
+            vec![
+                Film {
+                    name: String::from("Parasite"),
+                    year: Some(2019),
+                    director: None,
+                    rating: Some(9),
+                    date: Some(String::from("2022-06-10")),
+                    first_watched: Some(String::from("2021-01-01")),
+                    last_watched: Some(String::from("2022-06-09")),
+                    watch_count: 2,
+                },
+                Film {
+                    name: String::from("Heat"),
+                    year: Some(1995),
+                    director: None,
+                    rating: None,
+                    date: Some(String::from("2021-03-05")),
+                    first_watched: Some(String::from("2021-03-05")),
+                    last_watched: Some(String::from("2021-03-05")),
+                    watch_count: 1,
+                },
+            ]
+        );
+        let item = super::new_film_item(
+            films.into_iter().next().unwrap(),
+            &UserId(String::from("user")),
+        );
+        assert_eq!(item.id, "letterboxd:Parasite (2019)");
+        assert_eq!(item.rating, Some(9));
+        assert_eq!(
+            serde_json::Value::Object(item.metadata),
+            json!({"year": 2019, "date": "2022-06-10", "first_watched": "2021-01-01", "last_watched": "2022-06-09", "watch_count": 2})
+        );
+    }
+
+    #[test]
+    fn test_read_invalid_ratings() {
+        let result = super::read_films(
+            b"Date,Name,Year,Letterboxd URI,Rating\n2021-01-02,,2019,https://boxd.it/1a,4\n2021-01-02,Heat,1995,https://boxd.it/1b,6\n",
+        );
+        let Err(Error::ClientError(error)) = result else {
+            panic!("expected row errors");
+        };
+        assert_eq!(error, "row 1: missing Name\nrow 2: invalid Rating 6");
+    }
+}
@@ -1,8 +1,29 @@
+use super::DataSource;
+use crate::{
+    http::{self, RetryExt},
+    query::IntoQuery,
+};
+use async_trait::async_trait;
 use futures::StreamExt;
-use reqwest::Client;
+use reqwest::StatusCode;
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::{
+    collections::{HashMap, HashSet},
+    time::Duration,
+};
 use time::Date;
-use zeroflops::{Error, Id, Source, SourceType, UserId};
+use zeroflops::{
+    storage::{
+        CosmosParam, CosmosQuery, CreateDocumentBuilder, DocumentWriter, QueryDocumentsBuilder,
+        SessionClient, View,
+    },
+    upstream, Error, Id, ItemMetadata, List, SetlistOverride, SetlistQuery, SetlistReport,
+    SongMatch, Source, SourceType, UserId,
+};
+
+// Each year is a separate search
+const MAX_YEARS: i32 = 20;
 
 #[derive(Debug, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
@@ -14,8 +35,28 @@ struct Setlist {
     pub url: String,
 }
 
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SetlistPage {
+    pub setlist: Vec<SearchSetlist>,
+    pub total: usize,
+    pub page: usize,
+    pub items_per_page: usize,
+}
+
+/// Search results can be missing venue fields like the state code
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SearchSetlist {
+    pub event_date: String,
+    pub artist: Artist,
+    pub sets: Sets,
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 struct Artist {
+    #[serde(default)]
+    pub mbid: String,
     pub name: String,
 }
 
@@ -55,18 +96,77 @@ struct Song {
     pub cover: Option<Artist>,
 }
 
-pub async fn get_setlist(user_id: &UserId, id: Id) -> Result<(Source, Vec<crate::Item>), Error> {
-    let token = crate::source::spotify::get_token().await?;
-    let token = &token;
+#[derive(Debug, PartialEq)]
+struct SongPlays {
+    name: String,
+    // Artist of the original song
+    cover: Option<String>,
+    play_count: usize,
+    first_played: Date,
+    last_played: Date,
+}
 
-    let setlist: Setlist = Client::new()
-        .get(format!("https://api.setlist.fm/rest/1.0/setlist/{}", id.id))
-        .header("Accept", "application/json")
-        .header(
-            "x-api-key",
-            std::env::var("SETLIST_KEY").expect("SETLIST_KEY is missing"),
+/// Row of the setlist_override table
+#[derive(Debug, Deserialize, Serialize)]
+struct PinnedSong {
+    user_id: String,
+    artist_mbid: String,
+    /// Lowercase so the override works for every spelling in setlists
+    song: String,
+    item_id: String,
+}
+
+pub struct SetlistSource;
+
+#[async_trait]
+impl<C: SessionClient + Sync> DataSource<C> for SetlistSource {
+    fn handles(&self, source_type: &SourceType) -> bool {
+        matches!(
+            source_type,
+            SourceType::Setlist(_) | SourceType::SetlistSearch(_)
         )
-        .send()
+    }
+
+    async fn resolve(
+        &self,
+        client: &C,
+        user_id: &UserId,
+        source: Source,
+    ) -> Result<(Source, Vec<ItemMetadata>), Error> {
+        match source.source_type {
+            SourceType::Setlist(id) => {
+                let (mut source, items, report) = get_setlist(client, user_id, id).await?;
+                source.setlist_report = Some(report);
+                Ok((source, super::save_items(client, items).await?))
+            }
+            SourceType::SetlistSearch(query) => {
+                let (mut source, items, report) = search_setlists(client, user_id, query).await?;
+                source.setlist_report = Some(report);
+                let items = super::match_recordings(client, items).await?;
+                let list_items = crate::convert_items(&items);
+                // Play counts depend on the query so they're updated for existing items
+                super::update_items(client, items).await?;
+                Ok((source, list_items))
+            }
+            _ => Err(Error::client_error("Unsupported source")),
+        }
+    }
+}
+
+pub async fn get_setlist(
+    cosmos_client: &impl SessionClient,
+    user_id: &UserId,
+    id: Id,
+) -> Result<(Source, Vec<crate::Item>, SetlistReport), Error> {
+    let setlist: Setlist = http::client()
+        .get(format!(
+            "{}/rest/1.0/setlist/{}",
+            upstream::setlist_api(),
+            id.id
+        ))
+        .header("Accept", "application/json")
+        .header("x-api-key", upstream::key("SETLIST_KEY"))
+        .send_checked()
         .await?
         .json()
         .await?;
@@ -79,22 +179,28 @@ pub async fn get_setlist(user_id: &UserId, id: Id) -> Result<(Source, Vec<crate:
             s.song.into_iter().filter_map(|s| match s.tape {
                 None => Some((
                     s.name.clone(),
-                    Some(s.cover.as_ref().unwrap_or(&setlist.artist).name.clone()),
+                    s.cover.as_ref().unwrap_or(&setlist.artist).name.clone(),
                 )),
                 Some(_) => None,
             })
         })
         .collect();
-    let items =
-        futures::stream::iter(songs.into_iter().map(|(song, artist)| {
-            crate::source::spotify::search_song(token, song, artist, user_id)
-        }))
-        .buffered(5)
-        .collect::<Vec<_>>()
-        .await
-        .into_iter()
-        .filter_map(Result::ok)
-        .collect();
+    let mut report = SetlistReport {
+        artist: setlist.artist.name.clone(),
+        artist_mbid: setlist.artist.mbid.clone(),
+        ..Default::default()
+    };
+    let items = match_songs(
+        cosmos_client,
+        user_id,
+        &setlist.artist.mbid,
+        &songs,
+        &mut report,
+    )
+    .await?
+    .into_iter()
+    .flatten()
+    .collect();
     let read_format = time::format_description::parse("[day]-[month]-[year]").unwrap();
     let write_format = time::format_description::parse("[month repr:short] [day], [year]").unwrap();
     let date = Date::parse(&setlist.event_date, &read_format)
@@ -108,11 +214,526 @@ pub async fn get_setlist(user_id: &UserId, id: Id) -> Result<(Source, Vec<crate:
         setlist.venue.city.state_code,
         setlist.venue.city.country.code
     );
+    report.source.clone_from(&name);
+    Ok((Source::new(SourceType::Setlist(id), name), items, report))
+}
+
+/// Songs played in the setlists of an artist, most played first
+///
+/// Songs that can't be found on Spotify are added as setlist items so every song can be ranked.
+pub async fn search_setlists(
+    cosmos_client: &impl SessionClient,
+    user_id: &UserId,
+    query: SetlistQuery,
+) -> Result<(Source, Vec<crate::Item>, SetlistReport), Error> {
+    let years: Vec<_> = match query.start_year {
+        Some(start) => (start..=query.end_year.unwrap_or(start))
+            .map(Some)
+            .collect(),
+        None if query.tour.is_some() => vec![None],
+        None => {
+            return Err(Error::client_error(
+                "Setlist searches need a tour or a start year",
+            ))
+        }
+    };
+    if years.len() > MAX_YEARS as usize {
+        return Err(Error::client_error(format!(
+            "Setlist searches can have at most {MAX_YEARS} years"
+        )));
+    }
+    let client = http::client();
+    let mut setlists = Vec::new();
+    for year in years {
+        let mut page = 1;
+        loop {
+            let mut params = vec![
+                ("artistMbid", query.artist_mbid.clone()),
+                ("p", page.to_string()),
+            ];
+            if let Some(tour) = &query.tour {
+                params.push(("tourName", tour.clone()));
+            }
+            if let Some(year) = year {
+                params.push(("year", year.to_string()));
+            }
+            let response = client
+                .get(format!(
+                    "{}/rest/1.0/search/setlists",
+                    upstream::setlist_api()
+                ))
+                .query(&params)
+                .header("Accept", "application/json")
+                .header("x-api-key", upstream::key("SETLIST_KEY"))
+                .send_with_retry()
+                .await?;
+            // Searches without any results are not found errors
+            if response.status() == StatusCode::NOT_FOUND {
+                break;
+            }
+            let result: SetlistPage = http::check_status(response).await?.json().await?;
+            let done =
+                result.setlist.is_empty() || result.page * result.items_per_page >= result.total;
+            setlists.extend(result.setlist);
+            if done {
+                break;
+            }
+            page += 1;
+            // The API allows 2 requests per second
+            tokio::time::sleep(Duration::from_millis(500)).await;
+        }
+    }
+
+    let artist = setlists
+        .first()
+        .map_or_else(|| query.artist_mbid.clone(), |s| s.artist.name.clone());
+    let songs = count_songs(setlists)?;
+    let queries: Vec<_> = songs
+        .iter()
+        .map(|song| {
+            (
+                song.name.clone(),
+                song.cover.clone().unwrap_or_else(|| artist.clone()),
+            )
+        })
+        .collect();
+    let mut report = SetlistReport {
+        artist: artist.clone(),
+        artist_mbid: query.artist_mbid.clone(),
+        ..Default::default()
+    };
+    let results = match_songs(
+        cosmos_client,
+        user_id,
+        &query.artist_mbid,
+        &queries,
+        &mut report,
+    )
+    .await?;
+    let mut ids = HashSet::new();
+    let mut items = Vec::with_capacity(songs.len());
+    for (song, result) in songs.into_iter().zip(results) {
+        let mut item = result.unwrap_or_else(|| new_song_item(&query.artist_mbid, &song, user_id));
+        // Songs with different names can match the same track so the most played one is kept
+        if !ids.insert(item.id.clone()) {
+            continue;
+        }
+        add_song_metadata(&mut item.metadata, &song);
+        items.push(item);
+    }
+    let name = match (&query.tour, query.start_year, query.end_year) {
+        (Some(tour), _, _) => format!("{artist}: {tour}"),
+        (None, Some(start), Some(end)) if end != start => format!("{artist} {start}-{end}"),
+        (None, Some(start), _) => format!("{artist} {start}"),
+        (None, None, _) => artist,
+    };
+    report.source.clone_from(&name);
     Ok((
-        Source {
-            source_type: SourceType::Setlist(id),
-            name,
-        },
+        Source::new(SourceType::SetlistSearch(query), name),
         items,
+        report,
     ))
 }
+
+/// Matching reports of the setlist sources of the list
+///
+/// Reports are saved when the sources are resolved so sources that haven't been resolved yet
+/// don't have one.
+pub fn get_reports(list: &List) -> Vec<SetlistReport> {
+    list.sources
+        .iter()
+        .filter_map(|source| source.setlist_report.clone())
+        .collect()
+}
+
+/// Use a Spotify track for a song of the artist instead of searching for it
+pub async fn pin_song(
+    cosmos_client: &impl SessionClient,
+    user_id: &UserId,
+    pin: SetlistOverride,
+) -> Result<(), Error> {
+    let song = song_key(&pin.song);
+    if pin.artist_mbid.is_empty() || song.is_empty() {
+        return Err(Error::client_error("artist_mbid and song are required"));
+    }
+    let id = parse_track_id(&pin.track)
+        .ok_or_else(|| Error::client_error(format!("Invalid Spotify track {}", pin.track)))?;
+    let token = crate::source::spotify::get_token().await?;
+    let item = crate::source::spotify::get_tracks(&token, user_id, &[id])
+        .await?
+        .into_iter()
+        .next()
+        .ok_or_else(|| Error::client_error(format!("Couldn't find Spotify track {id}")))?;
+    let item_id = item.id.clone();
+    super::create_items(cosmos_client, vec![item], false).await?;
+    cosmos_client
+        .write_document(DocumentWriter::Create(CreateDocumentBuilder {
+            collection_name: "setlist_override",
+            document: PinnedSong {
+                user_id: user_id.0.clone(),
+                artist_mbid: pin.artist_mbid,
+                song,
+                item_id,
+            },
+            is_upsert: true,
+        }))
+        .await
+}
+
+/// Find the Spotify tracks of songs given as song and artist names
+///
+/// Songs pinned by the user aren't searched. Songs that can't be found are None.
+async fn match_songs(
+    cosmos_client: &impl SessionClient,
+    user_id: &UserId,
+    artist_mbid: &str,
+    songs: &[(String, String)],
+    report: &mut SetlistReport,
+) -> Result<Vec<Option<crate::Item>>, Error> {
+    let token = crate::source::spotify::get_token().await?;
+    let token = &token;
+    let overrides = get_overrides(cosmos_client, user_id, artist_mbid).await?;
+    let pinned_ids: Vec<_> = songs
+        .iter()
+        .filter_map(|(song, _)| overrides.get(&song_key(song)))
+        .filter_map(|id| id.strip_prefix("spotify:track:"))
+        .collect();
+    let mut pinned: HashMap<_, _> = if pinned_ids.is_empty() {
+        HashMap::new()
+    } else {
+        crate::source::spotify::get_tracks(token, user_id, &pinned_ids)
+            .await?
+            .into_iter()
+            .map(|item| (item.id.clone(), item))
+            .collect()
+    };
+    // Owned songs keep the futures Send
+    let requests: Vec<_> = songs
+        .iter()
+        .map(|(song, artist)| {
+            // Pinned tracks that were removed from Spotify are searched again
+            let item = overrides
+                .get(&song_key(song))
+                .and_then(|id| pinned.remove(id));
+            (song.clone(), artist.clone(), item)
+        })
+        .collect();
+    let results =
+        futures::stream::iter(requests.into_iter().map(|(song, artist, item)| async move {
+            match item {
+                Some(item) => Ok((item, true)),
+                None => crate::source::spotify::search_song(token, song, Some(artist), user_id)
+                    .await
+                    .map(|item| (item, false)),
+            }
+        }))
+        .buffered(5)
+        .collect::<Vec<_>>()
+        .await;
+    let mut items = Vec::with_capacity(songs.len());
+    for ((song, artist), result) in songs.iter().zip(results) {
+        match result {
+            Ok((item, pinned)) => {
+                let song_match = SongMatch {
+                    song: song.clone(),
+                    item_id: item.id.clone(),
+                    item_name: item.name.clone(),
+                    pinned,
+                };
+                if !pinned && is_ambiguous(song, artist, &item) {
+                    report.ambiguous.push(song_match);
+                } else {
+                    report.matched.push(song_match);
+                }
+                items.push(Some(item));
+            }
+            Err(Error::ClientError(_)) => {
+                report.unmatched.push(song.clone());
+                items.push(None);
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(items)
+}
+
+/// Item IDs of the songs pinned by the user, keyed by [song_key]
+async fn get_overrides(
+    cosmos_client: &impl SessionClient,
+    user_id: &UserId,
+    artist_mbid: &str,
+) -> Result<HashMap<String, String>, Error> {
+    Ok(cosmos_client
+        .query_documents::<PinnedSong>(QueryDocumentsBuilder::new(
+            "setlist_override",
+            View::User(user_id.clone()),
+            CosmosQuery::with_params(
+                "SELECT * FROM setlist_override WHERE artist_mbid = ?".into_query()?,
+                vec![CosmosParam::new(
+                    String::from("@artist_mbid"),
+                    artist_mbid.to_owned(),
+                )],
+            ),
+        ))
+        .await?
+        .into_iter()
+        .map(|pinned| (pinned.song, pinned.item_id))
+        .collect())
+}
+
+fn song_key(song: &str) -> String {
+    song.trim().to_lowercase()
+}
+
+/// Accepts track URLs, URIs and IDs
+fn parse_track_id(track: &str) -> Option<&str> {
+    let track = track.trim();
+    let id = match track.strip_prefix("spotify:track:") {
+        Some(id) => id,
+        None => track.rsplit_once("/track/").map_or(track, |(_, id)| id),
+    };
+    let id = id.split('?').next().unwrap_or_default();
+    (!id.is_empty() && id.chars().all(|c| c.is_ascii_alphanumeric())).then_some(id)
+}
+
+/// The first search result can be another song, like a remix or a different song with the same
+/// words in the name, so it should be checked by the user
+fn is_ambiguous(song: &str, artist: &str, item: &crate::Item) -> bool {
+    let has_artist = match item.metadata.get("artists") {
+        Some(Value::Array(artists)) => artists
+            .iter()
+            .filter_map(Value::as_str)
+            .any(|a| a.eq_ignore_ascii_case(artist)),
+        _ => false,
+    };
+    !has_artist || normalize_title(&item.name) != normalize_title(song)
+}
+
+/// Remove version suffixes like " - Remastered 2011" or " (Live)"
+fn normalize_title(title: &str) -> String {
+    let end = [" - ", " (", " ["]
+        .iter()
+        .filter_map(|suffix| title.find(suffix))
+        .min()
+        .unwrap_or(title.len());
+    title[..end].trim().to_lowercase()
+}
+
+/// Songs are ordered by play count and then by their first performance
+fn count_songs(setlists: Vec<SearchSetlist>) -> Result<Vec<SongPlays>, Error> {
+    let format = time::format_description::parse("[day]-[month]-[year]").unwrap();
+    let mut songs: Vec<SongPlays> = Vec::new();
+    let mut indices = HashMap::new();
+    for setlist in setlists {
+        let date = Date::parse(&setlist.event_date, &format)
+            .map_err(|e| Error::internal_error(format!("Unexpected date from setlist.fm: {e}")))?;
+        let played = setlist
+            .sets
+            .set
+            .into_iter()
+            .flat_map(|s| s.song)
+            .filter(|s| s.tape.is_none() && !s.name.trim().is_empty());
+        for song in played {
+            let key = song.name.trim().to_lowercase();
+            if let Some(&i) = indices.get(&key) {
+                let plays: &mut SongPlays = &mut songs[i];
+                plays.play_count += 1;
+                plays.first_played = plays.first_played.min(date);
+                plays.last_played = plays.last_played.max(date);
+            } else {
+                indices.insert(key, songs.len());
+                songs.push(SongPlays {
+                    name: song.name.trim().to_owned(),
+                    cover: song.cover.map(|a| a.name),
+                    play_count: 1,
+                    first_played: date,
+                    last_played: date,
+                });
+            }
+        }
+    }
+    songs.sort_by(|a, b| {
+        b.play_count
+            .cmp(&a.play_count)
+            .then_with(|| a.first_played.cmp(&b.first_played))
+    });
+    Ok(songs)
+}
+
+fn add_song_metadata(metadata: &mut Map<String, Value>, song: &SongPlays) {
+    metadata.insert(String::from("play_count"), song.play_count.into());
+    metadata.insert(
+        String::from("first_played"),
+        Value::String(song.first_played.to_string()),
+    );
+    metadata.insert(
+        String::from("last_played"),
+        Value::String(song.last_played.to_string()),
+    );
+    metadata.insert(String::from("cover"), Value::Bool(song.cover.is_some()));
+    if let Some(artist) = &song.cover {
+        metadata.insert(
+            String::from("original_artist"),
+            Value::String(artist.clone()),
+        );
+    }
+}
+
+/// Items for songs that aren't on Spotify
+fn new_song_item(artist_mbid: &str, song: &SongPlays, user_id: &UserId) -> crate::Item {
+    crate::Item {
+        id: format!("setlist:{artist_mbid}:{}", song.name.to_lowercase()),
+        user_id: user_id.0.clone(),
+        r#type: String::from("song"),
+        name: song.name.clone(),
+        iframe: None,
+        rating: None,
+        user_score: 1500,
+        user_wins: 0,
+        user_losses: 0,
+        metadata: Map::new(),
+        hidden: false,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{SetlistPage, SongPlays};
+    use serde_json::{json, Value};
+    use time::{Date, Month};
+    use zeroflops::{Id, List, ListMode, SetlistReport, Source, SourceType, UserId};
+
+    #[test]
+    fn test_count_songs() {
+        let page: SetlistPage = serde_json::from_str(
+            r#"{"type": "setlists", "itemsPerPage": 20, "page": 1, "total": 2, "setlist": [
+                {"id": "1", "eventDate": "12-05-2024", "artist": {"mbid": "a", "name": "Band"}, "venue": {"name": "Arena", "city": {"name": "Paris", "country": {"code": "FR"}}}, "tour": {"name": "Tour"}, "sets": {"set": [
+                    {"song": [{"name": "Opener"}, {"name": "Intro Tape", "tape": true}, {"name": "Hit"}]},
+                    {"encore": 1, "song": [{"name": "Heroes", "cover": {"mbid": "b", "name": "David Bowie"}}]}
+                ]}},
+                {"id": "2", "eventDate": "10-05-2024", "artist": {"mbid": "a", "name": "Band"}, "venue": {"name": "Hall", "city": {"name": "Lyon", "country": {"code": "FR"}}}, "sets": {"set": [
+                    {"song": [{"name": "hit"}, {"name": ""}]}
+                ]}}
+            ]}"#,
+        )
+        .unwrap();
+        let date = |day| Date::from_calendar_date(2024, Month::May, day).unwrap();
+        assert_eq!(
+            super::count_songs(page.setlist).unwrap(),
+            vec![
+                SongPlays {
+                    name: String::from("Hit"),
+                    cover: None,
+                    play_count: 2,
+                    first_played: date(10),
+                    last_played: date(12),
+                },
+                SongPlays {
+                    name: String::from("Opener"),
+                    cover: None,
+                    play_count: 1,
+                    first_played: date(12),
+                    last_played: date(12),
+                },
+                SongPlays {
+                    name: String::from("Heroes"),
+                    cover: Some(String::from("David Bowie")),
+                    play_count: 1,
+                    first_played: date(12),
+                    last_played: date(12),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_is_ambiguous() {
+        let track = |name: &str, artists: Value| crate::Item {
+            id: String::from("spotify:track:a"),
+            user_id: String::from("user"),
+            r#type: String::from("track"),
+            name: name.to_owned(),
+            iframe: None,
+            rating: None,
+            user_score: 1500,
+            user_wins: 0,
+            user_losses: 0,
+            metadata: json!({ "artists": artists }).as_object().unwrap().clone(),
+            hidden: false,
+        };
+        let band = json!(["Band"]);
+        assert!(!super::is_ambiguous(
+            "Hit",
+            "band",
+            &track("Hit - Remastered 2011", band.clone())
+        ));
+        assert!(!super::is_ambiguous(
+            "hit",
+            "Band",
+            &track("Hit (Live)", band.clone())
+        ));
+        assert!(super::is_ambiguous(
+            "Hit",
+            "Band",
+            &track("Hit Me", band.clone())
+        ));
+        assert!(super::is_ambiguous(
+            "Heroes",
+            "David Bowie",
+            &track("Heroes", band)
+        ));
+    }
+
+    #[test]
+    fn test_get_reports() {
+        let report = SetlistReport {
+            source: String::from("Band 2024"),
+            artist: String::from("Band"),
+            artist_mbid: String::from("a"),
+            unmatched: vec![String::from("Hit")],
+            ..Default::default()
+        };
+        let mut resolved = Source::new(
+            SourceType::Setlist(Id {
+                id: String::from("1"),
+                raw_id: String::from("1"),
+            }),
+            String::from("Band 2024"),
+        );
+        resolved.setlist_report = Some(report.clone());
+        let unresolved = Source::new(
+            SourceType::Setlist(Id {
+                id: String::from("2"),
+                raw_id: String::from("2"),
+            }),
+            String::new(),
+        );
+        let list = List::new(
+            String::from("list"),
+            &UserId(String::from("user")),
+            ListMode::User(None),
+            String::new(),
+            vec![resolved, unresolved],
+            None,
+            Vec::new(),
+        );
+        assert_eq!(super::get_reports(&list), vec![report]);
+    }
+
+    #[test]
+    fn test_parse_track_id() {
+        assert_eq!(
+            super::parse_track_id("spotify:track:abc123"),
+            Some("abc123")
+        );
+        assert_eq!(
+            super::parse_track_id("https://open.spotify.com/intl-de/track/abc123?si=x"),
+            Some("abc123")
+        );
+        assert_eq!(super::parse_track_id(" abc123 "), Some("abc123"));
+        assert_eq!(
+            super::parse_track_id("https://open.spotify.com/album/abc"),
+            None
+        );
+    }
+}
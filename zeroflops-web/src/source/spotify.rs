@@ -1,15 +1,25 @@
-use crate::query::IntoQuery;
-use reqwest::Client;
-use serde::{Deserialize, Serialize};
-use serde_json::{Map, Value};
-use std::collections::HashMap;
+use super::DataSource;
+use crate::{
+    http::{self, RetryExt},
+    query::IntoQuery,
+};
+use async_trait::async_trait;
+use reqwest::{Client, StatusCode};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::{
+    collections::{BTreeSet, HashMap, HashSet, VecDeque},
+    sync::{Mutex, OnceLock},
+};
+use time::OffsetDateTime;
 use tracing::Level;
 use zeroflops::{
     spotify::{Playlist, Playlists, RecentTrack},
     storage::{
         CosmosParam, CosmosQuery, QueryDocumentsBuilder, SessionClient, SqlSessionClient, View,
     },
-    Error, Id, List, ListMode, Source, SourceType, Spotify, UserId,
+    upstream, Error, Id, ItemMetadata, List, ListMode, Source, SourceType, Spotify, TimeRange,
+    UserId,
 };
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -36,10 +46,11 @@ struct Item {
 #[derive(Debug, Deserialize, Serialize)]
 struct AlbumItems {
     pub items: Vec<AlbumTrack>,
+    pub next: Option<String>,
 }
 
 // TODO: include album and artist metadata
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 struct Track {
     pub id: String,
     pub name: String,
@@ -50,24 +61,62 @@ struct Track {
     pub popularity: i32,
     pub track_number: i32,
     pub uri: String,
+    // Missing for local files
+    #[serde(default)]
+    pub external_ids: HashMap<String, String>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct TopTracks {
+    pub items: Vec<Track>,
+    pub next: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct Tracks {
+    pub tracks: Vec<Option<Track>>,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
 struct AlbumTrack {
+    pub id: String,
     pub href: String,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 struct Album {
     pub href: String,
     pub name: String,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 struct Artist {
     pub name: String,
 }
 
+#[derive(Debug, Deserialize, Serialize)]
+struct ArtistAlbums {
+    pub items: Vec<ArtistAlbum>,
+    pub next: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct ArtistAlbum {
+    pub id: String,
+    pub album_group: String,
+    pub release_date: String,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct Albums {
+    pub albums: Vec<Option<ArtistAlbumTracks>>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct ArtistAlbumTracks {
+    pub tracks: AlbumItems,
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 struct Search {
     pub tracks: SearchTracks,
@@ -94,31 +143,112 @@ pub struct PlayHistory {
     track: Track,
 }
 
+pub struct SpotifySource;
+
+#[async_trait]
+impl<C: SessionClient + Sync> DataSource<C> for SpotifySource {
+    fn handles(&self, source_type: &SourceType) -> bool {
+        matches!(source_type, SourceType::Spotify(_))
+    }
+
+    async fn resolve(
+        &self,
+        client: &C,
+        user_id: &UserId,
+        source: Source,
+    ) -> Result<(Source, Vec<ItemMetadata>), Error> {
+        let SourceType::Spotify(spotify) = source.source_type else {
+            return Err(Error::client_error("Unsupported source"));
+        };
+        let (source, items) = match spotify {
+            Spotify::Playlist(id) => get_playlist(user_id, id).await?,
+            Spotify::Album(id) => get_album(user_id, id).await?,
+            Spotify::Track(id) => get_track(user_id, id).await?,
+            Spotify::Artist(id) => get_artist(user_id, id).await?,
+            Spotify::LikedSongs => get_liked_songs(client, user_id).await?,
+            Spotify::TopTracks(time_range) => get_top_tracks(client, user_id, time_range).await?,
+        };
+        Ok((source, super::save_items(client, items).await?))
+    }
+
+    /// Playlists are unchanged if their snapshot ID is the same
+    async fn is_unchanged(&self, source: &Source) -> Result<bool, Error> {
+        let SourceType::Spotify(Spotify::Playlist(id)) = &source.source_type else {
+            return Ok(false);
+        };
+        let snapshot_id = get_playlist_snapshot(id).await?;
+        Ok(snapshot_id.is_some() && snapshot_id == source.snapshot_id)
+    }
+
+    fn push_target(&self) -> Option<&'static str> {
+        Some("spotify")
+    }
+
+    /// Lists without a playlist get a new playlist
+    async fn push(
+        &self,
+        client: &C,
+        user: &mut crate::user::User,
+        mut list: List,
+        ids: Vec<String>,
+    ) -> Result<(), Error> {
+        let user_id = UserId(user.user_id.clone());
+        let (_, external_id) = list.get_unique_source()?;
+        let access_token = get_access_token(client, user).await?;
+        let external_id = if let Some(external_id) = external_id {
+            update_playlist(access_token, &external_id.id, &list.name).await?;
+            external_id.id.clone()
+        } else {
+            let mut playlist = create_playlist(access_token, &user_id, &list.name).await?;
+            let id = Id {
+                id: playlist.id,
+                raw_id: playlist.external_urls.remove("spotify").unwrap(),
+            };
+            list.mode = match list.mode {
+                ListMode::User(_) => ListMode::User(Some(id.clone())),
+                ListMode::View(_) => ListMode::View(Some(id.clone())),
+                _ => unreachable!(),
+            };
+            list.update_iframe();
+            super::update_list(client, &user_id, list).await?;
+            id.id
+        };
+        // Songs from setlists that couldn't be found on Spotify can't be pushed
+        let ids: Vec<_> = ids
+            .into_iter()
+            .filter(|id| id.starts_with("spotify:"))
+            .collect();
+        update_list(access_token, &external_id, &ids).await
+    }
+}
+
 pub async fn get_playlist(
     user_id: &UserId,
     playlist_id: Id,
 ) -> Result<(Source, Vec<crate::Item>), Error> {
     let token = get_token().await?;
-    let client = Client::new();
+    let client = http::client();
 
     let playlist: Playlist = client
         .get(format!(
-            "https://api.spotify.com/v1/playlists/{}?limit=50",
+            "{}/v1/playlists/{}?limit=50",
+            upstream::spotify_api(),
             playlist_id.id
         ))
         .header("Authorization", format!("Bearer {}", token.access_token))
-        .send()
+        .send_checked()
         .await?
         .json()
         .await?;
 
     let mut playlist_items: PlaylistItems = client
         .get(format!(
-            "https://api.spotify.com/v1/playlists/{}/tracks",
+            "{}/v1/playlists/{}/tracks",
+            upstream::spotify_api(),
             playlist_id.id
         ))
         .header("Authorization", format!("Bearer {}", token.access_token))
-        .send()
+        .send_checked()
         .await?
         .json()
         .await?;
@@ -131,7 +261,7 @@ pub async fn get_playlist(
         playlist_items = client
             .get(uri)
             .header("Authorization", format!("Bearer {}", token.access_token))
-            .send()
+            .send_checked()
             .await?
             .json()
             .await?;
@@ -142,13 +272,29 @@ pub async fn get_playlist(
                 .map(|i| new_spotify_item(i.track, user_id)),
         );
     }
-    Ok((
-        Source {
-            source_type: SourceType::Spotify(Spotify::Playlist(playlist_id)),
-            name: playlist.name,
-        },
-        items,
-    ))
+    let mut source = Source::new(
+        SourceType::Spotify(Spotify::Playlist(playlist_id)),
+        playlist.name,
+    );
+    source.snapshot_id = playlist.snapshot_id;
+    Ok((source, items))
+}
+
+/// The snapshot ID changes whenever the tracks of the playlist change
+pub async fn get_playlist_snapshot(playlist_id: &Id) -> Result<Option<String>, Error> {
+    let token = get_token().await?;
+    let playlist: Playlist = http::client()
+        .get(format!(
+            "{}/v1/playlists/{}?fields=id,name,external_urls,snapshot_id",
+            upstream::spotify_api(),
+            playlist_id.id
+        ))
+        .header("Authorization", format!("Bearer {}", token.access_token))
+        .send_checked()
+        .await?
+        .json()
+        .await?;
+    Ok(playlist.snapshot_id)
 }
 
 pub async fn import_playlist(
@@ -177,24 +323,24 @@ pub async fn import_playlist(
 
 pub async fn get_album(user_id: &UserId, id: Id) -> Result<(Source, Vec<crate::Item>), Error> {
     let token = get_token().await?;
-    let client = Client::new();
-    let client = &client;
+    let client = http::client();
 
     let album: Album = client
-        .get(format!("https://api.spotify.com/v1/albums/{}", id.id))
+        .get(format!("{}/v1/albums/{}", upstream::spotify_api(), id.id))
         .header("Authorization", format!("Bearer {}", token.access_token))
-        .send()
+        .send_checked()
         .await?
         .json()
         .await?;
 
     let album_items: AlbumItems = client
         .get(format!(
-            "https://api.spotify.com/v1/albums/{}/tracks?limit=50",
+            "{}/v1/albums/{}/tracks?limit=50",
+            upstream::spotify_api(),
             id.id
         ))
         .header("Authorization", format!("Bearer {}", token.access_token))
-        .send()
+        .send_checked()
         .await?
         .json()
         .await?;
@@ -203,16 +349,13 @@ pub async fn get_album(user_id: &UserId, id: Id) -> Result<(Source, Vec<crate::I
         let response = client
             .get(&item.href)
             .header("Authorization", format!("Bearer {}", token.access_token))
-            .send()
+            .send_checked()
             .await?;
         tracing::event!(Level::DEBUG, "{} {}", item.href, response.status());
         items.push(new_spotify_item(response.json().await?, user_id));
     }
     Ok((
-        Source {
-            source_type: SourceType::Spotify(Spotify::Album(id)),
-            name: album.name,
-        },
+        Source::new(SourceType::Spotify(Spotify::Album(id)), album.name),
         items,
     ))
 }
@@ -241,22 +384,312 @@ pub async fn import_album(user_id: &UserId, id: String) -> Result<(List, Vec<cra
 pub async fn get_track(user_id: &UserId, id: Id) -> Result<(Source, Vec<crate::Item>), Error> {
     let token = get_token().await?;
 
-    let track: Track = Client::new()
-        .get(format!("https://api.spotify.com/v1/tracks/{}", id.id))
+    let track: Track = http::client()
+        .get(format!("{}/v1/tracks/{}", upstream::spotify_api(), id.id))
         .header("Authorization", format!("Bearer {}", token.access_token))
-        .send()
+        .send_checked()
         .await?
         .json()
         .await?;
     Ok((
-        Source {
-            source_type: SourceType::Spotify(Spotify::Track(id)),
-            name: track.name.clone(),
-        },
+        Source::new(SourceType::Spotify(Spotify::Track(id)), track.name.clone()),
         vec![new_spotify_item(track, user_id)],
     ))
 }
 
+/// Tracks of the albums, singles or compilations of an artist
+///
+/// Re-releases like deluxe editions share ISRCs with the original tracks so each recording is
+/// only added once, preferring albums over singles and compilations and earlier releases.
+pub async fn get_artist(user_id: &UserId, id: Id) -> Result<(Source, Vec<crate::Item>), Error> {
+    let token = get_token().await?;
+    let client = http::client();
+
+    let artist: Artist = client
+        .get(format!("{}/v1/artists/{}", upstream::spotify_api(), id.id))
+        .header("Authorization", format!("Bearer {}", token.access_token))
+        .send_checked()
+        .await?
+        .json()
+        .await?;
+
+    let groups = get_album_groups(&id.raw_id);
+    let mut albums = Vec::new();
+    let mut next = Some(format!(
+        "{}/v1/artists/{}/albums?include_groups={}&limit=50",
+        upstream::spotify_api(),
+        id.id,
+        groups.join(",")
+    ));
+    while let Some(uri) = next {
+        let artist_albums: ArtistAlbums = client
+            .get(uri)
+            .header("Authorization", format!("Bearer {}", token.access_token))
+            .send_checked()
+            .await?
+            .json()
+            .await?;
+        albums.extend(artist_albums.items);
+        next = artist_albums.next;
+    }
+    sort_albums(&mut albums, &groups);
+
+    let mut ids = Vec::new();
+    // The API accepts up to 20 album IDs per request
+    for chunk in albums.chunks(20) {
+        let album_ids: Vec<_> = chunk.iter().map(|a| a.id.as_str()).collect();
+        let page: Albums = client
+            .get(format!(
+                "{}/v1/albums?ids={}",
+                upstream::spotify_api(),
+                album_ids.join(",")
+            ))
+            .header("Authorization", format!("Bearer {}", token.access_token))
+            .send_checked()
+            .await?
+            .json()
+            .await?;
+        for album in page.albums.into_iter().flatten() {
+            let mut tracks = album.tracks;
+            loop {
+                ids.extend(tracks.items.into_iter().map(|t| t.id));
+                let Some(uri) = tracks.next else {
+                    break;
+                };
+                tracks = client
+                    .get(uri)
+                    .header("Authorization", format!("Bearer {}", token.access_token))
+                    .send_checked()
+                    .await?
+                    .json()
+                    .await?;
+            }
+        }
+    }
+    let ids: Vec<_> = ids.iter().map(String::as_str).collect();
+    let tracks = get_full_tracks(client, &token, &ids).await?;
+    Ok((
+        Source::new(SourceType::Spotify(Spotify::Artist(id)), artist.name),
+        dedup_tracks(tracks)
+            .into_iter()
+            .map(|track| new_spotify_item(track, user_id))
+            .collect(),
+    ))
+}
+
+pub async fn import_artist(
+    user_id: &UserId,
+    id: String,
+) -> Result<(List, Vec<crate::Item>), Error> {
+    let id = Id {
+        id: id.clone(),
+        raw_id: format!(
+            "https://open.spotify.com/embed/artist/{}?utm_source=generator",
+            id
+        ),
+    };
+    let (source, items) = get_artist(user_id, id.clone()).await?;
+    let list = List::new(
+        id.id,
+        user_id,
+        ListMode::External,
+        source.name.clone(),
+        vec![source],
+        Some(id.raw_id),
+        crate::convert_items(&items),
+    );
+    Ok((list, items))
+}
+
+const ALBUM_GROUPS: [&str; 3] = ["album", "single", "compilation"];
+
+/// Albums and singles are used unless the URL has an include_groups parameter
+fn get_album_groups(raw_id: &str) -> Vec<&'static str> {
+    let requested: Vec<_> = raw_id
+        .split_once('?')
+        .into_iter()
+        .flat_map(|(_, query)| query.split('&'))
+        .filter_map(|param| param.strip_prefix("include_groups="))
+        .flat_map(|groups| {
+            urlencoding::decode(groups)
+                .map(|g| g.split(',').map(str::to_lowercase).collect::<Vec<_>>())
+                .unwrap_or_default()
+        })
+        .collect();
+    let groups: Vec<_> = ALBUM_GROUPS
+        .into_iter()
+        .filter(|g| requested.iter().any(|r| r == g))
+        .collect();
+    if groups.is_empty() {
+        vec!["album", "single"]
+    } else {
+        groups
+    }
+}
+
+/// Releases are sorted by the order of the groups and then by release date
+fn sort_albums(albums: &mut [ArtistAlbum], groups: &[&str]) {
+    albums.sort_by(|a, b| {
+        let group = |album: &ArtistAlbum| groups.iter().position(|g| *g == album.album_group);
+        group(a)
+            .cmp(&group(b))
+            .then_with(|| a.release_date.cmp(&b.release_date))
+    });
+}
+
+/// Keep the first release of each recording
+fn dedup_tracks(tracks: Vec<Track>) -> Vec<Track> {
+    let mut recordings = HashSet::new();
+    tracks
+        .into_iter()
+        .filter(|t| {
+            recordings.insert(
+                t.external_ids
+                    .get("isrc")
+                    .map_or_else(|| t.id.clone(), |isrc| isrc.to_uppercase()),
+            )
+        })
+        .collect()
+}
+
+/// Tracks that can't be found are skipped
+pub async fn get_tracks(
+    token: &crate::Token,
+    user_id: &UserId,
+    ids: &[&str],
+) -> Result<Vec<crate::Item>, Error> {
+    Ok(get_full_tracks(http::client(), token, ids)
+        .await?
+        .into_iter()
+        .map(|track| new_spotify_item(track, user_id))
+        .collect())
+}
+
+async fn get_full_tracks(
+    client: &Client,
+    token: &crate::Token,
+    ids: &[&str],
+) -> Result<Vec<Track>, Error> {
+    let mut tracks = Vec::with_capacity(ids.len());
+    // The API accepts up to 50 IDs per request
+    for ids in ids.chunks(50) {
+        let page: Tracks = client
+            .get(format!(
+                "{}/v1/tracks?ids={}",
+                upstream::spotify_api(),
+                ids.join(",")
+            ))
+            .header("Authorization", format!("Bearer {}", token.access_token))
+            .send_checked()
+            .await?
+            .json()
+            .await?;
+        tracks.extend(page.tracks.into_iter().flatten());
+    }
+    Ok(tracks)
+}
+
+/// Tracks saved in the library of the user
+pub async fn get_liked_songs(
+    cosmos_client: &impl SessionClient,
+    user_id: &UserId,
+) -> Result<(Source, Vec<crate::Item>), Error> {
+    let access_token = get_user_access_token(cosmos_client, user_id).await?;
+    let client = http::client();
+    let mut items = Vec::new();
+    let mut next = Some(format!("{}/v1/me/tracks?limit=50", upstream::spotify_api()));
+    while let Some(uri) = next {
+        let liked_songs: PlaylistItems = get_user_page(client, &uri, &access_token).await?;
+        items.extend(
+            liked_songs
+                .items
+                .into_iter()
+                .map(|i| new_spotify_item(i.track, user_id)),
+        );
+        next = liked_songs.next;
+    }
+    Ok((
+        Source::new(
+            SourceType::Spotify(Spotify::LikedSongs),
+            String::from("Liked Songs"),
+        ),
+        items,
+    ))
+}
+
+/// The most played tracks of the user, ordered by affinity
+pub async fn get_top_tracks(
+    cosmos_client: &impl SessionClient,
+    user_id: &UserId,
+    time_range: TimeRange,
+) -> Result<(Source, Vec<crate::Item>), Error> {
+    let access_token = get_user_access_token(cosmos_client, user_id).await?;
+    let client = http::client();
+    let mut items = Vec::new();
+    let mut next = Some(format!(
+        "{}/v1/me/top/tracks?time_range={}&limit=50",
+        upstream::spotify_api(),
+        time_range.as_str()
+    ));
+    while let Some(uri) = next {
+        let top_tracks: TopTracks = get_user_page(client, &uri, &access_token).await?;
+        items.extend(
+            top_tracks
+                .items
+                .into_iter()
+                .map(|track| new_spotify_item(track, user_id)),
+        );
+        next = top_tracks.next;
+    }
+    let name = match time_range {
+        TimeRange::ShortTerm => "Top Tracks (last 4 weeks)",
+        TimeRange::MediumTerm => "Top Tracks (last 6 months)",
+        TimeRange::LongTerm => "Top Tracks (last year)",
+    };
+    Ok((
+        Source::new(
+            SourceType::Spotify(Spotify::TopTracks(time_range)),
+            String::from(name),
+        ),
+        items,
+    ))
+}
+
+/// Sources from the library of a user need their token instead of the app token
+async fn get_user_access_token(
+    cosmos_client: &impl SessionClient,
+    user_id: &UserId,
+) -> Result<String, Error> {
+    let Some(mut user) = crate::user::get_user(cosmos_client, user_id)
+        .await?
+        .filter(|u| u.spotify_credentials.is_some())
+    else {
+        return Err(Error::client_error(
+            "Spotify isn't linked, log in with Spotify in the settings to use Liked Songs and Top Tracks",
+        ));
+    };
+    Ok(get_access_token(cosmos_client, &mut user).await?.to_owned())
+}
+
+async fn get_user_page<T: DeserializeOwned>(
+    client: &Client,
+    uri: &str,
+    access_token: &str,
+) -> Result<T, Error> {
+    let resp = client
+        .get(uri)
+        .header("Authorization", format!("Bearer {}", access_token))
+        .send_with_retry()
+        .await?;
+    // Users that linked Spotify before these sources existed haven't granted the library scopes
+    if let StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN = resp.status() {
+        return Err(Error::client_error(
+            "Spotify denied access to your library, log in with Spotify again in the settings",
+        ));
+    }
+    Ok(http::check_status(resp).await?.json().await?)
+}
+
 pub async fn create_playlist(
     access_token: &str,
     user_id: &UserId,
@@ -266,14 +699,15 @@ pub async fn create_playlist(
     let playlist = CreatePlaylist {
         name: name.to_owned(),
     };
-    Ok(Client::new()
+    Ok(http::client()
         .post(format!(
-            "https://api.spotify.com/v1/users/{}/playlists",
+            "{}/v1/users/{}/playlists",
+            upstream::spotify_api(),
             user_id.0
         ))
         .header("Authorization", format!("Bearer {}", access_token))
         .json(&playlist)
-        .send()
+        .send_checked()
         .await?
         .json()
         .await?)
@@ -288,127 +722,359 @@ pub async fn update_playlist(
     let playlist = UpdatePlaylist {
         name: name.to_owned(),
     };
-    Client::new()
+    http::client()
         .put(format!(
-            "https://api.spotify.com/v1/playlists/{playlist_id}"
+            "{}/v1/playlists/{playlist_id}",
+            upstream::spotify_api()
         ))
         .header("Authorization", format!("Bearer {}", access_token))
         .json(&playlist)
-        .send()
+        .send_checked()
         .await?;
     Ok(())
 }
 
+#[derive(Debug, Deserialize)]
+struct PlaylistUris {
+    pub items: Vec<PlaylistUri>,
+    pub next: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PlaylistUri {
+    pub track: Option<TrackUri>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TrackUri {
+    pub uri: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Snapshot {
+    pub snapshot_id: String,
+}
+
+/// Changes that turn the tracks of a playlist into the tracks of a list
+#[derive(Debug, Default, PartialEq)]
+struct PlaylistDiff {
+    /// URIs and positions to remove, last position first so earlier positions stay valid
+    removals: Vec<(String, usize)>,
+    /// Range starts and insert befores of single track moves, in order
+    moves: Vec<(usize, usize)>,
+    /// Positions to insert runs of URIs at, in order
+    additions: Vec<(usize, Vec<String>)>,
+}
+
+/// Find the fewest removals, moves and additions to get from the current to the target tracks
+///
+/// Tracks are matched by URI, so duplicates are matched in order. Unavailable tracks have no URI
+/// and stay as placeholders that are never matched, moved or removed. The matched tracks in the
+/// longest increasing subsequence of target positions stay put and the others are moved next to
+/// their predecessor.
+fn diff_playlist(current: &[Option<String>], target: &[String]) -> PlaylistDiff {
+    let mut target_positions: HashMap<&str, VecDeque<usize>> = HashMap::new();
+    for (i, uri) in target.iter().enumerate() {
+        target_positions.entry(uri).or_default().push_back(i);
+    }
+    let mut diff = PlaylistDiff::default();
+    // Target positions of the remaining tracks, with None for placeholders
+    let mut kept = Vec::new();
+    for (i, uri) in current.iter().enumerate() {
+        let Some(uri) = uri else {
+            kept.push(None);
+            continue;
+        };
+        match target_positions
+            .get_mut(uri.as_str())
+            .and_then(VecDeque::pop_front)
+        {
+            Some(position) => kept.push(Some(position)),
+            None => diff.removals.push((uri.clone(), i)),
+        }
+    }
+    diff.removals.reverse();
+
+    let index = |kept: &[Option<usize>], position: usize| {
+        kept.iter().position(|&p| p == Some(position)).unwrap()
+    };
+    let matched: Vec<_> = kept.iter().flatten().copied().collect();
+    let mut placed: BTreeSet<_> = longest_increasing_subsequence(&matched)
+        .into_iter()
+        .collect();
+    let mut unplaced: Vec<_> = matched
+        .iter()
+        .filter(|p| !placed.contains(p))
+        .copied()
+        .collect();
+    unplaced.sort_unstable();
+    for position in unplaced {
+        let from = index(&kept, position);
+        let insert_before = match placed.range(..position).next_back() {
+            Some(&previous) => index(&kept, previous) + 1,
+            None => 0,
+        };
+        diff.moves.push((from, insert_before));
+        kept.remove(from);
+        kept.insert(
+            if from < insert_before {
+                insert_before - 1
+            } else {
+                insert_before
+            },
+            Some(position),
+        );
+        placed.insert(position);
+    }
+
+    let mut runs = Vec::new();
+    let mut run: Option<(usize, Vec<String>)> = None;
+    for (i, uri) in target.iter().enumerate() {
+        if placed.contains(&i) {
+            runs.extend(run.take());
+        } else {
+            run.get_or_insert_with(|| (i, Vec::new()))
+                .1
+                .push(uri.clone());
+        }
+    }
+    runs.extend(run);
+    // Every track before a missing track is in place once the earlier runs are added
+    for (start, uris) in runs {
+        let position = match start.checked_sub(1) {
+            Some(previous) => index(&kept, previous) + 1,
+            None => 0,
+        };
+        kept.splice(position..position, (start..start + uris.len()).map(Some));
+        diff.additions.push((position, uris));
+    }
+    diff
+}
+
+/// Values of an increasing subsequence with the most values
+fn longest_increasing_subsequence(values: &[usize]) -> Vec<usize> {
+    // Index of the smallest last value of the subsequences of each length
+    let mut tails: Vec<usize> = Vec::new();
+    let mut previous = vec![None; values.len()];
+    for (i, value) in values.iter().enumerate() {
+        let length = tails.partition_point(|&t| values[t] < *value);
+        if length > 0 {
+            previous[i] = Some(tails[length - 1]);
+        }
+        if length == tails.len() {
+            tails.push(i);
+        } else {
+            tails[length] = i;
+        }
+    }
+    let mut subsequence = Vec::with_capacity(tails.len());
+    let mut next = tails.last().copied();
+    while let Some(i) = next {
+        subsequence.push(values[i]);
+        next = previous[i];
+    }
+    subsequence.reverse();
+    subsequence
+}
+
+async fn get_playlist_uris(
+    client: &Client,
+    access_token: &str,
+    playlist_id: &str,
+) -> Result<(String, Vec<Option<String>>), Error> {
+    let snapshot: Snapshot = client
+        .get(format!(
+            "{}/v1/playlists/{}?fields=snapshot_id",
+            upstream::spotify_api(),
+            playlist_id
+        ))
+        .header("Authorization", format!("Bearer {}", access_token))
+        .send_checked()
+        .await?
+        .json()
+        .await?;
+    let mut uris = Vec::new();
+    let mut next = Some(format!(
+        "{}/v1/playlists/{}/tracks?fields=items(track(uri)),next&limit=100",
+        upstream::spotify_api(),
+        playlist_id
+    ));
+    while let Some(uri) = next {
+        let page: PlaylistUris = client
+            .get(uri)
+            .header("Authorization", format!("Bearer {}", access_token))
+            .send_checked()
+            .await?
+            .json()
+            .await?;
+        uris.extend(page.items.into_iter().map(|i| i.track.map(|t| t.uri)));
+        next = page.next;
+    }
+    Ok((snapshot.snapshot_id, uris))
+}
+
+/// Send a change to the tracks of a playlist and return the new snapshot ID
+async fn change_playlist_tracks(
+    request: reqwest::RequestBuilder,
+    access_token: &str,
+) -> Result<String, Error> {
+    let resp = request
+        .header("Authorization", format!("Bearer {}", access_token))
+        .send_checked()
+        .await?;
+    Ok(resp.json::<Snapshot>().await?.snapshot_id)
+}
+
+/// Update the tracks of a playlist with as few changes as possible
+///
+/// Unchanged tracks keep the date they were added. Each change is based on the snapshot from the
+/// previous change so Spotify can apply them on top of concurrent edits.
 pub async fn update_list(
     access_token: &str,
     playlist_id: &str,
     ids: &[String],
 ) -> Result<(), Error> {
-    let client = Client::new();
-    let mut chunks = ids.chunks(100);
+    let client = http::client();
+    let (mut snapshot_id, current) = get_playlist_uris(client, access_token, playlist_id).await?;
+    let diff = diff_playlist(&current, ids);
     let uri = format!(
-        "https://api.spotify.com/v1/playlists/{}/tracks?uris={}",
-        playlist_id,
-        // Clear playlist if empty
-        chunks.next().unwrap_or(&[]).join(",")
+        "{}/v1/playlists/{}/tracks",
+        upstream::spotify_api(),
+        playlist_id
     );
-    // TODO: error handling
-    let resp = client
-        .put(uri)
-        .header("Authorization", format!("Bearer {}", access_token))
-        .header("Content-Length", "0")
-        .send()
+    // The API accepts up to 100 tracks per request
+    for removals in diff.removals.chunks(100) {
+        let tracks: Vec<_> = removals
+            .iter()
+            .map(|(uri, position)| json!({"uri": uri, "positions": [position]}))
+            .collect();
+        snapshot_id = change_playlist_tracks(
+            client
+                .delete(&uri)
+                .json(&json!({"tracks": tracks, "snapshot_id": snapshot_id})),
+            access_token,
+        )
         .await?;
-    if resp.status().is_client_error() || resp.status().is_server_error() {
-        let error = format!(
-            "Spotify update playlist items error: {}",
-            resp.text().await?
-        );
-        return Err(Error::internal_error(error));
     }
-    for ids in chunks {
-        let uri = format!(
-            "https://api.spotify.com/v1/playlists/{}/tracks?uris={}",
-            playlist_id,
-            ids.join(",")
-        );
-        let resp = client
-            .post(uri)
-            .header("Authorization", format!("Bearer {}", access_token))
-            .header("Content-Length", "0")
-            .send()
+    for (range_start, insert_before) in diff.moves {
+        snapshot_id = change_playlist_tracks(
+            client.put(&uri).json(&json!({
+                "range_start": range_start,
+                "insert_before": insert_before,
+                "range_length": 1,
+                "snapshot_id": snapshot_id,
+            })),
+            access_token,
+        )
+        .await?;
+    }
+    for (position, uris) in diff.additions {
+        for (i, uris) in uris.chunks(100).enumerate() {
+            snapshot_id = change_playlist_tracks(
+                client.post(&uri).json(&json!({
+                    "uris": uris,
+                    "position": position + i * 100,
+                    "snapshot_id": snapshot_id,
+                })),
+                access_token,
+            )
             .await?;
-        if resp.status().is_client_error() || resp.status().is_server_error() {
-            let error = format!(
-                "Spotify update playlist items error: {}",
-                resp.text().await?
-            );
-            return Err(Error::internal_error(error));
         }
     }
     Ok(())
 }
 
+// Refresh tokens this many seconds before they expire so they don't expire mid-request
+const EXPIRY_MARGIN: i64 = 60;
+
+/// The client credentials token and its expiry
+static APP_TOKEN: tokio::sync::Mutex<Option<(crate::Token, Option<i64>)>> =
+    tokio::sync::Mutex::const_new(None);
+
+fn now() -> i64 {
+    OffsetDateTime::now_utc().unix_timestamp()
+}
+
+fn is_unexpired(expires_at: Option<i64>, now: i64) -> bool {
+    expires_at.is_some_and(|expires_at| now < expires_at - EXPIRY_MARGIN)
+}
+
+/// The client credentials token is shared by every request until it expires
 pub async fn get_token() -> Result<crate::Token, Error> {
-    Ok(Client::new()
-        .post("https://accounts.spotify.com/api/token")
+    // Holding the lock makes concurrent requests wait for a single refresh
+    let mut app_token = APP_TOKEN.lock().await;
+    if let Some((token, expires_at)) = app_token.as_ref() {
+        if is_unexpired(*expires_at, now()) {
+            return Ok(token.clone());
+        }
+    }
+    let now = now();
+    let token = get_app_token().await?;
+    *app_token = Some((token.clone(), token.expires_in.map(|e| now + e)));
+    Ok(token)
+}
+
+async fn get_app_token() -> Result<crate::Token, Error> {
+    Ok(http::client()
+        .post(format!("{}/api/token", upstream::spotify_accounts()))
         .header(
             "Authorization",
-            &format!(
-                "Basic {}",
-                std::env::var("SPOTIFY_TOKEN").expect("SPOTIFY_TOKEN is missing")
-            ),
+            &format!("Basic {}", upstream::key("SPOTIFY_TOKEN")),
         )
         .form(&[("grant_type", "client_credentials")])
-        .send()
+        .send_checked()
         .await?
         .json()
         .await?)
 }
 
+/// Reuse the access token of the user until it's about to expire
+///
+/// Refreshed tokens are saved with the user. Spotify can rotate the refresh token too, in which
+/// case the old one stops working.
 pub async fn get_access_token<'a>(
-    _client: &impl SessionClient,
+    client: &impl SessionClient,
     user: &'a mut crate::user::User,
 ) -> Result<&'a str, Error> {
     let Some(credentials) = &mut user.spotify_credentials else {
         return Err(Error::client_error("User hasn't set up Spotify auth"));
     };
-    let token = get_user_token(&credentials.refresh_token).await?;
-    credentials.access_token = token.access_token;
-    // TODO: reuse existing access token if it hasn't expired
-    /*client
-    .write_document(|db| {
-        Ok(db
-            .collection_client("users")
-            .document_client(&user.id, &user.id)?
-            .replace_document(user.clone()))
-    })
-    .await?;*/
-    Ok(&credentials.access_token)
+    let now = now();
+    if !is_unexpired(credentials.expires_at, now) {
+        let token = get_user_token(&credentials.refresh_token).await?;
+        credentials.access_token = token.access_token;
+        credentials.expires_at = token.expires_in.map(|e| now + e);
+        if let Some(refresh_token) = token.refresh_token {
+            credentials.refresh_token = refresh_token;
+        }
+        crate::user::update_spotify_credentials(client, user).await?;
+    }
+    Ok(user
+        .spotify_credentials
+        .as_ref()
+        .map(|c| c.access_token.as_str())
+        .expect("credentials were checked"))
 }
 
 async fn get_user_token(refresh_token: &str) -> Result<crate::Token, Error> {
-    Ok(Client::new()
-        .post("https://accounts.spotify.com/api/token")
+    Ok(http::client()
+        .post(format!("{}/api/token", upstream::spotify_accounts()))
         .header(
             "Authorization",
-            &format!(
-                "Basic {}",
-                std::env::var("SPOTIFY_TOKEN").expect("SPOTIFY_TOKEN is missing")
-            ),
+            &format!("Basic {}", upstream::key("SPOTIFY_TOKEN")),
         )
         .form(&[
             ("grant_type", "refresh_token"),
             ("refresh_token", refresh_token),
         ])
-        .send()
+        .send_checked()
         .await?
         .json()
         .await?)
 }
 
 fn new_spotify_item(track: Track, user_id: &UserId) -> crate::Item {
-    let metadata: Map<_, _> = [
+    let mut metadata: Map<_, _> = [
         (String::from("album"), Value::String(track.album.name)),
         (
             String::from("artists"),
@@ -435,6 +1101,10 @@ fn new_spotify_item(track: Track, user_id: &UserId) -> crate::Item {
     ]
     .into_iter()
     .collect();
+    // Recordings are identified by ISRC across releases
+    if let Some(isrc) = track.external_ids.get("isrc") {
+        metadata.insert(String::from("isrc"), Value::String(isrc.to_uppercase()));
+    }
     crate::Item {
         iframe: Some(format!(
             "https://open.spotify.com/embed/track/{}?utm_source=generator",
@@ -453,38 +1123,84 @@ fn new_spotify_item(track: Track, user_id: &UserId) -> crate::Item {
     }
 }
 
+// Enough for the setlists of a few artists without growing for the lifetime of the server
+const MAX_CACHED_SEARCHES: usize = 10_000;
+
+type SearchKey = (String, Option<String>);
+
+/// Search results of song and artist names, the oldest searches are removed first
+#[derive(Default)]
+struct SearchCache {
+    tracks: HashMap<SearchKey, Option<Track>>,
+    order: VecDeque<SearchKey>,
+}
+
+impl SearchCache {
+    fn get(&self, key: &SearchKey) -> Option<Option<Track>> {
+        self.tracks.get(key).cloned()
+    }
+
+    fn insert(&mut self, key: SearchKey, track: Option<Track>, capacity: usize) {
+        if self.tracks.insert(key.clone(), track).is_none() {
+            self.order.push_back(key);
+        }
+        while self.order.len() > capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.tracks.remove(&oldest);
+            }
+        }
+    }
+}
+
+// Search results are shared between users since they rarely change
+static SEARCH_CACHE: OnceLock<Mutex<SearchCache>> = OnceLock::new();
+
+/// Results are cached, including songs that couldn't be found
 pub async fn search_song(
     token: &crate::Token,
     name: String,
     artist: Option<String>,
     user_id: &UserId,
 ) -> Result<crate::Item, Error> {
-    let uri = if let Some(artist) = artist {
-        format!(
-            "https://api.spotify.com/v1/search?q=track:{}%20artist:{}&type=track",
-            urlencoding::encode(&name),
-            urlencoding::encode(&artist)
-        )
+    let cache = SEARCH_CACHE.get_or_init(Default::default);
+    let key = (
+        name.to_lowercase(),
+        artist.as_ref().map(|a| a.to_lowercase()),
+    );
+    let cached = cache.lock().unwrap().get(&key);
+    let track = if let Some(track) = cached {
+        track
     } else {
-        format!(
-            "https://api.spotify.com/v1/search?q=track:{}&type=track",
-            urlencoding::encode(&name),
-        )
+        let uri = if let Some(artist) = artist {
+            format!(
+                "{}/v1/search?q=track:{}%20artist:{}&type=track",
+                upstream::spotify_api(),
+                urlencoding::encode(&name),
+                urlencoding::encode(&artist)
+            )
+        } else {
+            format!(
+                "{}/v1/search?q=track:{}&type=track",
+                upstream::spotify_api(),
+                urlencoding::encode(&name),
+            )
+        };
+        let result: Search = http::client()
+            .get(&uri)
+            .header("Authorization", format!("Bearer {}", token.access_token))
+            .send_checked()
+            .await?
+            .json()
+            .await?;
+        let track = result.tracks.items.into_iter().next();
+        cache
+            .lock()
+            .unwrap()
+            .insert(key, track.clone(), MAX_CACHED_SEARCHES);
+        track
     };
-    let result: Search = Client::new()
-        .get(&uri)
-        .header("Authorization", format!("Bearer {}", token.access_token))
-        .send()
-        .await?
-        .json()
-        .await?;
     Ok(new_spotify_item(
-        result
-            .tracks
-            .items
-            .into_iter()
-            .next()
-            .ok_or(Error::client_error("Couldn't find song for query"))?,
+        track.ok_or(Error::client_error("Couldn't find song for query"))?,
         user_id,
     ))
 }
@@ -494,10 +1210,13 @@ pub async fn get_recent_tracks(
     user_id: &UserId,
     access_token: &str,
 ) -> Result<zeroflops::spotify::RecentTracks, Error> {
-    let recent_tracks: RecentTracks = Client::new()
-        .get("https://api.spotify.com/v1/me/player/recently-played?limit=50")
+    let recent_tracks: RecentTracks = http::client()
+        .get(format!(
+            "{}/v1/me/player/recently-played?limit=50",
+            upstream::spotify_api()
+        ))
         .header("Authorization", format!("Bearer {}", access_token))
-        .send()
+        .send_checked()
         .await?
         .json()
         .await?;
@@ -521,8 +1240,7 @@ pub async fn get_recent_tracks(
                     .collect::<Vec<_>>(),
             ),
         ))
-        .await
-        .map_err(Error::from)?;
+        .await?;
     let map: HashMap<_, _> = items
         .into_iter()
         .map(|r: Map<String, Value>| (r["id"].as_str().expect("string id").to_owned(), r))
@@ -561,11 +1279,226 @@ pub async fn get_recent_tracks(
 }
 
 pub async fn get_playlists(access_token: &str) -> Result<Playlists, Error> {
-    Ok(Client::new()
-        .get("https://api.spotify.com/v1/me/playlists")
+    Ok(http::client()
+        .get(format!("{}/v1/me/playlists", upstream::spotify_api()))
         .header("Authorization", format!("Bearer {}", access_token))
-        .send()
+        .send_checked()
         .await?
         .json()
         .await?)
 }
+
+#[cfg(test)]
+mod test {
+    use super::{ArtistAlbum, PlaylistDiff, SearchCache, Track};
+    use crate::{
+        query::test::{Mock, TestSessionClient},
+        user::User,
+    };
+    use serde_json::json;
+    use spotify::SpotifyCredentials;
+    use zeroflops::{Error, UserId};
+
+    fn track(id: &str, isrc: Option<&str>) -> Track {
+        serde_json::from_value(json!({
+            "id": id,
+            "name": id,
+            "album": {"href": "", "name": ""},
+            "artists": [],
+            "duration_ms": 0,
+            "external_urls": {},
+            "popularity": 0,
+            "track_number": 1,
+            "uri": format!("spotify:track:{id}"),
+            "external_ids": isrc.map(|isrc| json!({"isrc": isrc})).unwrap_or(json!({})),
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_search_cache() {
+        let key = |name: &str| (name.to_owned(), None);
+        let mut cache = SearchCache::default();
+        cache.insert(key("a"), Some(track("a", None)), 2);
+        cache.insert(key("b"), None, 2);
+        // Replacing a search keeps its place
+        cache.insert(key("a"), Some(track("a", None)), 2);
+        cache.insert(key("c"), Some(track("c", None)), 2);
+        assert!(cache.get(&key("a")).is_none());
+        assert_eq!(cache.get(&key("b")).map(|t| t.is_none()), Some(true));
+        assert_eq!(
+            cache.get(&key("c")).flatten().map(|t| t.id),
+            Some(String::from("c"))
+        );
+        assert_eq!(cache.order.len(), 2);
+    }
+
+    fn uris(uris: &[&str]) -> Vec<String> {
+        uris.iter().map(|uri| uri.to_string()).collect()
+    }
+
+    fn current(uris: &[&str]) -> Vec<Option<String>> {
+        uris.iter()
+            .map(|uri| Some(uri.to_string()).filter(|uri| !uri.is_empty()))
+            .collect()
+    }
+
+    #[test]
+    fn test_diff_playlist() {
+        assert_eq!(
+            super::diff_playlist(&current(&["a", "b", "c"]), &uris(&["a", "b", "c"])),
+            PlaylistDiff::default()
+        );
+        // Moving one track to the end is a single move instead of moving every other track
+        assert_eq!(
+            super::diff_playlist(
+                &current(&["d", "a", "b", "c"]),
+                &uris(&["a", "b", "c", "d"])
+            ),
+            PlaylistDiff {
+                moves: vec![(0, 4)],
+                ..Default::default()
+            }
+        );
+        assert_eq!(
+            super::diff_playlist(
+                &current(&["a", "x", "c", "y", "a"]),
+                &uris(&["a", "b", "c", "e"])
+            ),
+            PlaylistDiff {
+                removals: vec![
+                    (String::from("a"), 4),
+                    (String::from("y"), 3),
+                    (String::from("x"), 1)
+                ],
+                moves: Vec::new(),
+                additions: vec![(1, uris(&["b"])), (3, uris(&["e"]))],
+            }
+        );
+        assert_eq!(
+            super::diff_playlist(&[], &uris(&["a", "b"])),
+            PlaylistDiff {
+                additions: vec![(0, uris(&["a", "b"]))],
+                ..Default::default()
+            }
+        );
+        // Unavailable tracks stay in place and shift the positions of the others
+        assert_eq!(
+            super::diff_playlist(&current(&["", "x", "c", "", "a"]), &uris(&["a", "b", "c"])),
+            PlaylistDiff {
+                removals: vec![(String::from("x"), 1)],
+                moves: vec![(1, 4)],
+                additions: vec![(3, uris(&["b"]))],
+            }
+        );
+    }
+
+    #[test]
+    fn test_longest_increasing_subsequence() {
+        assert_eq!(
+            super::longest_increasing_subsequence(&[3, 0, 4, 1, 2]),
+            vec![0, 1, 2]
+        );
+        assert!(super::longest_increasing_subsequence(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_is_unexpired() {
+        assert!(super::is_unexpired(Some(1000), 900));
+        // Tokens are refreshed shortly before they expire
+        assert!(!super::is_unexpired(Some(1000), 950));
+        assert!(!super::is_unexpired(Some(1000), 1000));
+        assert!(!super::is_unexpired(None, 0));
+    }
+
+    #[tokio::test]
+    async fn test_get_access_token_unexpired() {
+        let client = TestSessionClient {
+            get_mock: Mock::empty(),
+            query_mock: Mock::empty(),
+            write_mock: Mock::empty(),
+        };
+        let mut user = User {
+            id: String::new(),
+            user_id: String::from("user"),
+            secret: String::new(),
+            spotify_credentials: Some(SpotifyCredentials {
+                user_id: String::from("user"),
+                url: String::new(),
+                access_token: String::from("token"),
+                refresh_token: String::from("refresh"),
+                expires_at: Some(super::now() + 3600),
+            }),
+            google_email: None,
+        };
+        assert_eq!(
+            super::get_access_token(&client, &mut user).await.unwrap(),
+            "token"
+        );
+    }
+
+    #[test]
+    fn test_get_album_groups() {
+        assert_eq!(
+            super::get_album_groups("https://open.spotify.com/artist/abc?si=123"),
+            vec!["album", "single"]
+        );
+        assert_eq!(
+            super::get_album_groups(
+                "https://open.spotify.com/artist/abc?include_groups=compilation%2Calbum,other"
+            ),
+            vec!["album", "compilation"]
+        );
+    }
+
+    #[test]
+    fn test_sort_albums() {
+        let album = |id: &str, album_group: &str, release_date: &str| ArtistAlbum {
+            id: id.to_owned(),
+            album_group: album_group.to_owned(),
+            release_date: release_date.to_owned(),
+        };
+        let mut albums = vec![
+            album("single", "single", "2019-05-01"),
+            album("deluxe", "album", "2021-01-01"),
+            album("album", "album", "2019-06"),
+        ];
+        super::sort_albums(&mut albums, &["album", "single"]);
+        let ids: Vec<_> = albums.iter().map(|a| a.id.as_str()).collect();
+        assert_eq!(ids, vec!["album", "deluxe", "single"]);
+    }
+
+    #[test]
+    fn test_dedup_tracks() {
+        let tracks = super::dedup_tracks(vec![
+            track("a", Some("USRC17607839")),
+            track("b", None),
+            track("c", Some("usrc17607839")),
+            track("b", None),
+            track("d", Some("GBAYE0601498")),
+        ]);
+        let ids: Vec<_> = tracks.iter().map(|t| t.id.as_str()).collect();
+        assert_eq!(ids, vec!["a", "b", "d"]);
+    }
+
+    #[tokio::test]
+    async fn test_user_source_without_spotify() {
+        let client = TestSessionClient {
+            get_mock: Mock::empty(),
+            query_mock: Mock::new(vec![
+                "[]",
+                r#"[{"id":"","user_id":"user","secret":"","spotify_credentials":null,"google_email":"user@gmail.com"}]"#,
+            ]),
+            write_mock: Mock::empty(),
+        };
+        let user_id = UserId(String::from("user"));
+        assert!(matches!(
+            super::get_liked_songs(&client, &user_id).await,
+            Err(Error::ClientError(_))
+        ));
+        assert!(matches!(
+            super::get_top_tracks(&client, &user_id, zeroflops::TimeRange::ShortTerm).await,
+            Err(Error::ClientError(_))
+        ));
+    }
+}
@@ -0,0 +1,303 @@
+//! Custom items from uploaded CSV, JSON or NDJSON files
+use super::Registry;
+use serde::Deserialize;
+use serde_json::{Map, Number, Value};
+use std::collections::HashSet;
+use zeroflops::{storage::SessionClient, Error, List, Source, SourceType, UserId};
+
+// Avoid huge error responses for files that don't match the mapping at all
+const MAX_ROW_ERRORS: usize = 20;
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    Csv,
+    Json,
+    Ndjson,
+}
+
+impl Format {
+    pub(super) fn from_file_name(file_name: &str) -> Option<Format> {
+        match file_name.rsplit_once('.')?.1.to_lowercase().as_str() {
+            "csv" => Some(Format::Csv),
+            "json" => Some(Format::Json),
+            "ndjson" | "jsonl" => Some(Format::Ndjson),
+            _ => None,
+        }
+    }
+}
+
+/// Mapping of the uploaded columns to item fields
+///
+/// Columns that aren't mapped are stored as item metadata.
+#[derive(Debug, Deserialize)]
+pub struct Upload {
+    pub file_name: String,
+    /// Guessed from the file extension if missing
+    pub format: Option<Format>,
+    #[serde(default = "default_id_column")]
+    pub id_column: String,
+    #[serde(default = "default_name_column")]
+    pub name_column: String,
+    pub iframe_column: Option<String>,
+}
+
+fn default_id_column() -> String {
+    String::from("id")
+}
+
+fn default_name_column() -> String {
+    String::from("name")
+}
+
+/// Add the items of an uploaded file to the list
+///
+/// Uploading a file with the same name replaces the previous upload. Existing items keep their
+/// scores but their names and metadata are updated.
+pub async fn upload_items<C: SessionClient + Sync>(
+    client: &C,
+    registry: &Registry<C>,
+    user_id: &UserId,
+    list: List,
+    upload: Upload,
+    data: &[u8],
+) -> Result<(), Error> {
+    let value = Value::Array(read_upload(&upload, data)?);
+    let items = super::get_custom_items(user_id, &value)?;
+    // Only the ids are saved in the source since the items have the data of the file
+    let ids = items.iter().map(|i| i.id.clone()).collect();
+    super::import_items(
+        client,
+        registry,
+        user_id,
+        list,
+        Source::new(SourceType::Upload(ids), upload.file_name),
+        items,
+    )
+    .await
+}
+
+/// Convert the rows of the file into custom item objects
+fn read_upload(upload: &Upload, data: &[u8]) -> Result<Vec<Value>, Error> {
+    let format = upload
+        .format
+        .or_else(|| Format::from_file_name(&upload.file_name))
+        .ok_or_else(|| Error::client_error("unknown file format"))?;
+    let rows = match format {
+        Format::Csv => read_csv(data)?,
+        Format::Json => read_json(data)?,
+        Format::Ndjson => read_ndjson(data),
+    };
+    let mut ids = HashSet::new();
+    let mut values = Vec::with_capacity(rows.len());
+    let mut errors = Vec::new();
+    for (i, row) in rows.into_iter().enumerate() {
+        match row.and_then(|row| map_row(upload, format, row)) {
+            Ok((id, value)) if ids.insert(id.clone()) => values.push(value),
+            Ok((id, _)) => errors.push(format!("row {}: duplicate id {id}", i + 1)),
+            Err(e) => errors.push(format!("row {}: {e}", i + 1)),
+        }
+    }
+    check_row_errors(errors)?;
+    Ok(values)
+}
+
+/// Report all of the invalid rows at once so they can be fixed together
+pub(super) fn check_row_errors(mut errors: Vec<String>) -> Result<(), Error> {
+    if errors.is_empty() {
+        return Ok(());
+    }
+    let count = errors.len();
+    errors.truncate(MAX_ROW_ERRORS);
+    if count > MAX_ROW_ERRORS {
+        errors.push(format!("and {} more errors", count - MAX_ROW_ERRORS));
+    }
+    Err(Error::client_error(errors.join("\n")))
+}
+
+pub(super) type Row = Result<Map<String, Value>, String>;
+
+/// Rows keyed by the header of the file
+pub(super) fn read_csv(data: &[u8]) -> Result<Vec<Row>, Error> {
+    let mut reader = csv::Reader::from_reader(data);
+    let headers = reader
+        .headers()
+        .map_err(|e| Error::client_error(format!("invalid header: {e}")))?
+        .clone();
+    Ok(reader
+        .records()
+        .map(|record| {
+            let record = record.map_err(|e| e.to_string())?;
+            Ok(headers
+                .iter()
+                .zip(record.iter())
+                .map(|(k, v)| (k.to_owned(), Value::String(v.to_owned())))
+                .collect())
+        })
+        .collect())
+}
+
+pub(super) fn read_json(data: &[u8]) -> Result<Vec<Row>, Error> {
+    let Value::Array(a) = serde_json::from_slice(data)
+        .map_err(|e| Error::client_error(format!("invalid JSON: {e}")))?
+    else {
+        return Err(Error::client_error("expected a JSON array"));
+    };
+    Ok(a.into_iter().map(into_row).collect())
+}
+
+pub(super) fn read_ndjson(data: &[u8]) -> Vec<Row> {
+    String::from_utf8_lossy(data)
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            serde_json::from_str(line)
+                .map_err(|e| e.to_string())
+                .and_then(into_row)
+        })
+        .collect()
+}
+
+fn into_row(value: Value) -> Row {
+    match value {
+        Value::Object(o) => Ok(o),
+        _ => Err(String::from("expected an object")),
+    }
+}
+
+/// Returns the item ID and the custom item object
+fn map_row(
+    upload: &Upload,
+    format: Format,
+    mut row: Map<String, Value>,
+) -> Result<(String, Value), String> {
+    let id = take_string(&mut row, &upload.id_column)?;
+    let name = take_string(&mut row, &upload.name_column)?;
+    let iframe = match &upload.iframe_column {
+        Some(column) => Some(take_string(&mut row, column)?),
+        None => None,
+    };
+    let mut value: Map<_, _> = row
+        .into_iter()
+        .filter_map(|(k, v)| match (format, v) {
+            // CSV cells are always strings so guess the type for queries
+            (Format::Csv, Value::String(s)) => parse_cell(&s).map(|v| (k, v)),
+            (_, v) => Some((k, v)),
+        })
+        .collect();
+    value.insert(String::from("id"), Value::String(id.clone()));
+    value.insert(String::from("name"), Value::String(name));
+    if let Some(iframe) = iframe {
+        value.insert(String::from("iframe"), Value::String(iframe));
+    }
+    Ok((id, Value::Object(value)))
+}
+
+fn take_string(row: &mut Map<String, Value>, column: &str) -> Result<String, String> {
+    match row.remove(column) {
+        Some(Value::String(s)) if !s.trim().is_empty() => Ok(s.trim().to_owned()),
+        Some(Value::Number(n)) => Ok(n.to_string()),
+        Some(Value::String(_) | Value::Null) | None => Err(format!("missing {column}")),
+        Some(_) => Err(format!("invalid {column}")),
+    }
+}
+
+/// Non-empty text of a column
+pub(super) fn cell<'a>(row: &'a Map<String, Value>, column: &str) -> Option<&'a str> {
+    match row.get(column) {
+        Some(Value::String(s)) if !s.trim().is_empty() => Some(s.trim()),
+        _ => None,
+    }
+}
+
+fn parse_cell(s: &str) -> Option<Value> {
+    if s.is_empty() {
+        None
+    } else if let Ok(i) = s.parse::<i64>() {
+        Some(Value::Number(i.into()))
+    } else if let Some(n) = s.parse().ok().and_then(Number::from_f64) {
+        Some(Value::Number(n))
+    } else {
+        Some(Value::String(s.to_owned()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Format, Upload};
+    use serde_json::json;
+    use zeroflops::Error;
+
+    fn upload(file_name: &str) -> Upload {
+        Upload {
+            file_name: file_name.to_owned(),
+            format: None,
+            id_column: String::from("id"),
+            name_column: String::from("name"),
+            iframe_column: None,
+        }
+    }
+
+    #[test]
+    fn test_read_csv() {
+        let upload = Upload {
+            name_column: String::from("Title"),
+            ..upload("books.csv")
+        };
+        let values = super::read_upload(
+            &upload,
+            b"id,Title,Year,Rating,Notes\n1,Dune,1965,4.5,\n2,Emma,1815,3,Classic\n",
+        )
+        .unwrap();
+        assert_eq!(
+            values,
+            vec![
+                json!({"Year": 1965, "Rating": 4.5, "id": "1", "name": "Dune"}),
+                json!({"Year": 1815, "Rating": 3, "Notes": "Classic", "id": "2", "name": "Emma"}),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_read_ndjson() {
+        let upload = Upload {
+            format: Some(Format::Ndjson),
+            iframe_column: Some(String::from("url")),
+            ..upload("items.txt")
+        };
+        let values = super::read_upload(
+            &upload,
+            br#"{"id": 1, "name": "a", "url": "https://example.com", "tags": ["x"]}
+
+{"id": "2", "name": "b", "url": "https://example.org"}
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            values,
+            vec![
+                json!({"tags": ["x"], "id": "1", "name": "a", "iframe": "https://example.com"}),
+                json!({"id": "2", "name": "b", "iframe": "https://example.org"}),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_read_invalid_rows() {
+        let result = super::read_upload(
+            &upload("items.json"),
+            br#"[{"id": "1", "name": "a"}, {"id": "2"}, "c", {"id": "1", "name": "d"}]"#,
+        );
+        let Err(Error::ClientError(error)) = result else {
+            panic!("expected row errors");
+        };
+        assert_eq!(
+            error,
+            "row 2: missing name\nrow 3: expected an object\nrow 4: duplicate id 1"
+        );
+        assert!(matches!(
+            super::read_upload(&upload("items.xml"), b""),
+            Err(Error::ClientError(_))
+        ));
+    }
+}
@@ -1,3 +1,4 @@
+use crate::query::IntoQuery;
 use ::spotify::{AuthClient, SpotifyCredentials};
 use async_trait::async_trait;
 use axum_login::{
@@ -15,7 +16,13 @@ use reqwest::Client;
 use rusqlite::{Connection, OptionalExtension, Params, Row};
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use uuid::Uuid;
-use zeroflops::Error;
+use zeroflops::{
+    storage::{
+        CosmosParam, CosmosQuery, DocumentWriter, QueryDocumentsBuilder, ReplaceDocumentBuilder,
+        SessionClient, View,
+    },
+    upstream, Error, UserId,
+};
 
 #[async_trait]
 pub trait Auth {
@@ -256,17 +263,14 @@ impl AuthClient for GoogleClient {
     async fn get_credentials(&self, code: &str, origin: &str) -> Result<Self::Credentials, Error> {
         let client = Client::new();
         let token: GoogleCredentials = client
-            .post("https://oauth2.googleapis.com/token")
+            .post(format!("{}/token", upstream::google_oauth()))
             .form(&[
                 ("code", code),
                 (
                     "client_id",
                     "1038220726403-n55jha2cvprd8kdb4akdfvo0uiok4p5u.apps.googleusercontent.com",
                 ),
-                (
-                    "client_secret",
-                    &std::env::var("GOOGLE_SECRET").expect("GOOGLE_SECRET is missing"),
-                ),
+                ("client_secret", &upstream::key("GOOGLE_SECRET")),
                 ("redirect_uri", origin),
                 ("grant_type", "authorization_code"),
             ])
@@ -276,7 +280,7 @@ impl AuthClient for GoogleClient {
             .await?;
 
         Ok(client
-            .get("https://openidconnect.googleapis.com/v1/userinfo")
+            .get(format!("{}/v1/userinfo", upstream::google_userinfo()))
             .header("Authorization", format!("Bearer {}", token.access_token))
             .send()
             .await?
@@ -387,6 +391,45 @@ impl AuthnBackend for SqlStore {
     }
 }
 
+/// Look up the account of a user from the user ID of their lists
+pub async fn get_user(
+    client: &impl SessionClient,
+    user_id: &UserId,
+) -> Result<Option<User>, Error> {
+    client
+        .query_documents::<RawUser>(QueryDocumentsBuilder::new(
+            "user",
+            View::User(user_id.clone()),
+            CosmosQuery::with_params(
+                "SELECT * FROM user WHERE user_id = ?".into_query()?,
+                vec![CosmosParam::new(
+                    String::from("@user_id"),
+                    user_id.0.clone(),
+                )],
+            ),
+        ))
+        .await?
+        .into_iter()
+        .next()
+        .map(User::try_from)
+        .transpose()
+}
+
+/// Save the Spotify credentials of the user
+pub async fn update_spotify_credentials(
+    client: &impl SessionClient,
+    user: &User,
+) -> Result<(), Error> {
+    client
+        .write_document(DocumentWriter::Replace(ReplaceDocumentBuilder {
+            collection_name: "user",
+            document_name: user.id.clone(),
+            partition_key: UserId(user.id.clone()),
+            document: RawUser::from(user.clone()),
+        }))
+        .await
+}
+
 pub fn generate_secret() -> String {
     BASE64_STANDARD.encode(rand::thread_rng().gen::<[u8; 64]>())
 }
@@ -457,6 +500,7 @@ mod test {
                 url: String::new(),
                 access_token: code.to_owned(),
                 refresh_token: String::new(),
+                expires_at: None,
             })
         }
     }
@@ -540,6 +584,7 @@ mod test {
                     url: String::new(),
                     access_token: "test".to_owned(),
                     refresh_token: String::new(),
+                    expires_at: None,
                 }),
                 google_email: None,
                 ..auth.expected_user.clone().unwrap()
@@ -593,6 +638,7 @@ mod test {
                     url: String::new(),
                     access_token: "test".to_owned(),
                     refresh_token: String::new(),
+                    expires_at: None,
                 }),
                 google_email: None,
             }),
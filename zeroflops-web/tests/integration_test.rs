@@ -26,6 +26,9 @@ fn test_get_lists() {
             favorite: true,
             query: "SELECT artists, AVG(user_score) FROM item GROUP BY artists".to_owned(),
             public: true,
+            refresh: None,
+            last_refreshed: None,
+            refresh_error: None,
             ..artists.clone()
         }
     );
@@ -41,6 +44,9 @@ fn test_get_lists() {
             favorite: true,
             query: "SELECT name, user_score FROM item WHERE user_score >= 1500".to_owned(),
             public: true,
+            refresh: None,
+            last_refreshed: None,
+            refresh_error: None,
             ..winners.clone()
         }
     );
@@ -59,6 +65,8 @@ pub fn assert_bop_to_the_top(list: &List) {
     assert_eq!(list.user_id, "demo");
     assert_eq!(list.mode, ListMode::External);
     assert_eq!(list.name, "Bop to the Top");
+    // The snapshot changes whenever the playlist is edited
+    assert!(list.sources[0].snapshot_id.is_some());
     assert_eq!(
         list.sources,
         vec![Source {
@@ -66,7 +74,10 @@ pub fn assert_bop_to_the_top(list: &List) {
                 id: "5MztFbRbMpyxbVYuOSfQV9".to_owned(),
                 raw_id: "https://open.spotify.com/embed/playlist/5MztFbRbMpyxbVYuOSfQV9?utm_source=generator".to_owned()
             })),
-            name: "Bop to the Top".to_owned()
+            name: "Bop to the Top".to_owned(),
+            snapshot_id: list.sources[0].snapshot_id.clone(),
+            item_ids: None,
+            setlist_report: None,
         }]
     );
     assert_eq!(
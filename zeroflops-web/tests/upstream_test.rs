@@ -0,0 +1,97 @@
+use spotify::{AuthClient, SpotifyClient};
+use std::sync::OnceLock;
+use zeroflops::{upstream, Id, SourceType, Spotify, UserId};
+use zeroflops_web::{fake_upstream, source::spotify as spotify_source, user::GoogleClient};
+
+/// Serve the fake upstream APIs on a separate runtime since each test has its own runtime
+fn start_fake_upstream() {
+    static STARTED: OnceLock<()> = OnceLock::new();
+    STARTED.get_or_init(|| {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.set_nonblocking(true).unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            tokio::runtime::Runtime::new().unwrap().block_on(async {
+                fake_upstream::serve(tokio::net::TcpListener::from_std(listener).unwrap()).await;
+            });
+        });
+        upstream::set_base_url(&url);
+    });
+}
+
+#[tokio::test]
+async fn test_spotify_login() {
+    start_fake_upstream();
+    let credentials = SpotifyClient
+        .get_credentials("code", "http://localhost")
+        .await
+        .unwrap();
+    assert_eq!(credentials.user_id, fake_upstream::SPOTIFY_USER);
+    assert_eq!(credentials.access_token, fake_upstream::ACCESS_TOKEN);
+    assert_eq!(credentials.refresh_token, fake_upstream::REFRESH_TOKEN);
+}
+
+#[tokio::test]
+async fn test_google_login() {
+    start_fake_upstream();
+    let user = GoogleClient
+        .get_credentials("code", "http://localhost")
+        .await
+        .unwrap();
+    assert_eq!(user.email, fake_upstream::GOOGLE_EMAIL);
+}
+
+#[tokio::test]
+async fn test_get_playlist() {
+    start_fake_upstream();
+    let id = Id {
+        id: "playlist".to_owned(),
+        raw_id: "https://open.spotify.com/playlist/playlist".to_owned(),
+    };
+    let (source, items) = spotify_source::get_playlist(&UserId("user".to_owned()), id.clone())
+        .await
+        .unwrap();
+    assert_eq!(
+        source.source_type,
+        SourceType::Spotify(Spotify::Playlist(id))
+    );
+    assert_eq!(source.snapshot_id.as_deref(), Some("fake-snapshot"));
+    assert_eq!(
+        items.iter().map(|i| i.id.as_str()).collect::<Vec<_>>(),
+        vec!["spotify:track:fake-track-1", "spotify:track:fake-track-2"]
+    );
+}
+
+#[tokio::test]
+async fn test_get_album() {
+    start_fake_upstream();
+    let id = Id {
+        id: "album".to_owned(),
+        raw_id: "https://open.spotify.com/album/album".to_owned(),
+    };
+    let (source, items) = spotify_source::get_album(&UserId("user".to_owned()), id)
+        .await
+        .unwrap();
+    assert_eq!(source.name, "Fake Album");
+    assert_eq!(items.len(), fake_upstream::TRACKS.len());
+}
+
+#[tokio::test]
+async fn test_push_playlist() {
+    start_fake_upstream();
+    let playlist = spotify_source::create_playlist(
+        fake_upstream::ACCESS_TOKEN,
+        &UserId(fake_upstream::SPOTIFY_USER.to_owned()),
+        "New List",
+    )
+    .await
+    .unwrap();
+    assert_eq!(playlist.name, "New List");
+    spotify_source::update_list(
+        fake_upstream::ACCESS_TOKEN,
+        &playlist.id,
+        &["spotify:track:fake-track-1".to_owned()],
+    )
+    .await
+    .unwrap();
+}
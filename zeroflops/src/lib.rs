@@ -14,6 +14,8 @@ use serde_json::Value;
 pub mod spotify;
 #[cfg(feature = "full")]
 pub mod storage;
+#[cfg(feature = "full")]
+pub mod upstream;
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct UserId(pub String);
@@ -40,6 +42,15 @@ pub struct List {
     // For external lists, query is only used to select fields (not filter)
     pub query: String,
     pub public: bool,
+    /// How often the sources are refreshed in the background, never if missing
+    #[serde(default)]
+    pub refresh: Option<RefreshInterval>,
+    /// Unix timestamp of the last background refresh
+    #[serde(default)]
+    pub last_refreshed: Option<i64>,
+    /// Error of the last background refresh if it failed
+    #[serde(default)]
+    pub refresh_error: Option<String>,
 }
 
 impl List {
@@ -63,6 +74,25 @@ impl List {
             favorite: false,
             query: String::from("SELECT name, user_score FROM item"),
             public: false,
+            refresh: None,
+            last_refreshed: None,
+            refresh_error: None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RefreshInterval {
+    Daily,
+    Weekly,
+}
+
+impl RefreshInterval {
+    pub fn seconds(&self) -> i64 {
+        match self {
+            RefreshInterval::Daily => 24 * 60 * 60,
+            RefreshInterval::Weekly => 7 * 24 * 60 * 60,
         }
     }
 }
@@ -83,6 +113,9 @@ pub struct RawList {
     // For external lists, query is only used to select fields (not filter)
     pub query: String,
     pub public: Option<bool>,
+    pub refresh: Option<String>,
+    pub last_refreshed: Option<i64>,
+    pub refresh_error: Option<String>,
 }
 
 impl From<List> for RawList {
@@ -98,6 +131,11 @@ impl From<List> for RawList {
             favorite: l.favorite,
             query: l.query,
             public: Some(l.public),
+            refresh: l
+                .refresh
+                .map(|r| serde_json::to_string(&r).expect("refresh should serialize")),
+            last_refreshed: l.last_refreshed,
+            refresh_error: l.refresh_error,
         }
     }
 }
@@ -116,6 +154,9 @@ impl TryFrom<RawList> for List {
             favorite: l.favorite,
             query: l.query,
             public: l.public.unwrap_or_default(),
+            refresh: l.refresh.map(|r| serde_json::from_str(&r)).transpose()?,
+            last_refreshed: l.last_refreshed,
+            refresh_error: l.refresh_error,
         })
     }
 }
@@ -160,6 +201,27 @@ pub enum ListMode {
 pub struct Source {
     pub source_type: SourceType,
     pub name: String,
+    /// Spotify playlist snapshot when the items were resolved
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub snapshot_id: Option<String>,
+    /// Missing if the source hasn't been resolved
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub item_ids: Option<Vec<String>>,
+    /// How the songs of a setlist source were matched when the items were resolved
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub setlist_report: Option<SetlistReport>,
+}
+
+impl Source {
+    pub fn new(source_type: SourceType, name: String) -> Source {
+        Source {
+            source_type,
+            name,
+            snapshot_id: None,
+            item_ids: None,
+            setlist_report: None,
+        }
+    }
 }
 
 #[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
@@ -167,7 +229,23 @@ pub enum SourceType {
     Custom(Value),
     Spotify(Spotify),
     Setlist(Id),
+    /// Songs from every setlist of an artist on a tour or in a range of years
+    SetlistSearch(SetlistQuery),
+    /// Items of another list, resolved again when the list is refreshed
     ListItems(String),
+    /// Items combined from several lists
+    ListSet(ListSet),
+    Imdb(Imdb),
+    /// Spotify items matched from a Last.fm scrobble export
+    Lastfm(Vec<String>),
+    /// Movies from a Letterboxd diary, ratings or watched export
+    Letterboxd(Vec<String>),
+    /// Books from a Goodreads library export
+    Goodreads(Vec<String>),
+    /// College football teams from CollegeFootballData files
+    Cfb(Vec<String>),
+    /// Custom items from an uploaded CSV, JSON or NDJSON file
+    Upload(Vec<String>),
 }
 
 impl List {
@@ -228,7 +306,10 @@ fn get_source_id(source: &Source) -> Option<&str> {
     match source.source_type {
         SourceType::Spotify(_) => Some("spotify"),
         SourceType::Setlist(_) => Some("spotify"),
+        SourceType::SetlistSearch(_) => Some("spotify"),
         SourceType::ListItems(_) => Some("list"),
+        SourceType::ListSet(_) => Some("list"),
+        SourceType::Lastfm(_) => Some("spotify"),
         _ => None,
     }
 }
@@ -238,6 +319,34 @@ pub enum Spotify {
     Playlist(Id),
     Album(Id),
     Track(Id),
+    /// Album groups can be picked with an include_groups parameter in the URL
+    Artist(Id),
+    /// The user's saved tracks
+    LikedSongs,
+    /// The user's most played tracks
+    TopTracks(TimeRange),
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TimeRange {
+    /// About 4 weeks
+    ShortTerm,
+    /// About 6 months
+    MediumTerm,
+    /// About a year
+    LongTerm,
+}
+
+impl TimeRange {
+    /// The time_range parameter of the Spotify API
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TimeRange::ShortTerm => "short_term",
+            TimeRange::MediumTerm => "medium_term",
+            TimeRange::LongTerm => "long_term",
+        }
+    }
 }
 
 #[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
@@ -246,6 +355,102 @@ pub struct Id {
     pub raw_id: String,
 }
 
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub enum Imdb {
+    /// Title IDs like tt0111161
+    Titles(Vec<String>),
+    Query(ImdbQuery),
+}
+
+/// Titles matching all of the filters, ordered by number of votes
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct ImdbQuery {
+    // e.g. movie or tvSeries
+    pub title_type: Option<String>,
+    pub year: Option<i32>,
+    pub genre: Option<String>,
+    pub min_votes: Option<u32>,
+}
+
+/// Setlists of an artist matching either the tour or the years
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct SetlistQuery {
+    /// MusicBrainz ID of the artist
+    pub artist_mbid: String,
+    pub tour: Option<String>,
+    pub start_year: Option<i32>,
+    /// Defaults to the start year
+    pub end_year: Option<i32>,
+}
+
+/// Set operation on the items of lists, in the order of the first list
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct ListSet {
+    pub operation: SetOperation,
+    /// IDs of the lists
+    pub ids: Vec<String>,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SetOperation {
+    /// Items in any of the lists
+    Union,
+    /// Items in every list
+    Intersection,
+    /// Items in the first list that aren't in the others
+    Difference,
+}
+
+/// Result of a Last.fm scrobble import
+#[derive(Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct ScrobbleImport {
+    pub scrobbles: usize,
+    pub items: usize,
+    /// Ordered by play count
+    pub unmatched: Vec<UnmatchedTrack>,
+}
+
+/// A scrobbled track that couldn't be found on Spotify
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+pub struct UnmatchedTrack {
+    pub artist: String,
+    pub track: String,
+    pub play_count: usize,
+}
+
+/// How the songs of a setlist source were matched to Spotify tracks
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct SetlistReport {
+    /// Name of the source
+    pub source: String,
+    pub artist: String,
+    /// MusicBrainz ID of the artist, overrides are per artist
+    pub artist_mbid: String,
+    pub matched: Vec<SongMatch>,
+    /// Songs where the track found has a different name or artist
+    pub ambiguous: Vec<SongMatch>,
+    pub unmatched: Vec<String>,
+}
+
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct SongMatch {
+    pub song: String,
+    pub item_id: String,
+    pub item_name: String,
+    /// Chosen with a [SetlistOverride] instead of searching
+    pub pinned: bool,
+}
+
+/// Spotify track to use for a setlist song of an artist
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+pub struct SetlistOverride {
+    pub artist_mbid: String,
+    pub song: String,
+    /// Spotify track URL, URI or ID
+    pub track: String,
+}
+
 #[cfg(feature = "azure")]
 impl CosmosEntity for RawList {
     type Entity = String;
@@ -255,6 +460,84 @@ impl CosmosEntity for RawList {
     }
 }
 
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Dashboards {
+    pub dashboards: Vec<Dashboard>,
+}
+
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Dashboard {
+    pub id: String,
+    pub user_id: String,
+    pub name: String,
+    pub panels: Vec<Panel>,
+    pub public: bool,
+}
+
+impl Dashboard {
+    pub fn new(id: String, user_id: &UserId, name: String) -> Dashboard {
+        Dashboard {
+            id,
+            user_id: user_id.0.clone(),
+            name,
+            panels: Vec::new(),
+            public: false,
+        }
+    }
+}
+
+/// A query over the owner's items and how to display the result
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Panel {
+    pub title: String,
+    pub query: String,
+    // Name of the data view, e.g. Table or Line Graph
+    pub view: String,
+}
+
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct RawDashboard {
+    pub id: String,
+    pub user_id: String,
+    pub name: String,
+    pub panels: String,
+    pub public: bool,
+}
+
+impl From<Dashboard> for RawDashboard {
+    fn from(d: Dashboard) -> RawDashboard {
+        RawDashboard {
+            id: d.id,
+            user_id: d.user_id,
+            name: d.name,
+            panels: serde_json::to_string(&d.panels).expect("panels should serialize"),
+            public: d.public,
+        }
+    }
+}
+
+impl TryFrom<RawDashboard> for Dashboard {
+    type Error = Error;
+    fn try_from(d: RawDashboard) -> Result<Dashboard, Error> {
+        Ok(Dashboard {
+            id: d.id,
+            user_id: d.user_id,
+            name: d.name,
+            panels: serde_json::from_str(&d.panels)?,
+            public: d.public,
+        })
+    }
+}
+
+#[cfg(feature = "azure")]
+impl CosmosEntity for RawDashboard {
+    type Entity = String;
+
+    fn partition_key(&self) -> Self::Entity {
+        self.user_id.clone()
+    }
+}
+
 #[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
 pub struct Items {
     pub items: Vec<Option<ItemMetadata>>,
@@ -273,6 +556,8 @@ pub enum Error {
     ClientError(String),
     InternalError(InternalError),
     NotFound,
+    /// An external service failed or kept rate limiting requests
+    UpstreamError(UpstreamError),
 }
 
 impl Error {
@@ -283,6 +568,36 @@ impl Error {
     pub fn internal_error(e: impl Into<String>) -> Self {
         Self::InternalError(InternalError::Error(e.into()))
     }
+
+    pub fn upstream_error(
+        host: impl Into<String>,
+        status: Option<u16>,
+        message: impl Into<String>,
+    ) -> Self {
+        Self::UpstreamError(UpstreamError {
+            host: host.into(),
+            status,
+            message: message.into(),
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct UpstreamError {
+    pub host: String,
+    /// Missing if there was no response, like for timeouts
+    pub status: Option<u16>,
+    pub message: String,
+}
+
+impl std::fmt::Display for UpstreamError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(status) = self.status {
+            write!(f, "{} returned {}: {}", self.host, status, self.message)
+        } else {
+            write!(f, "{} request failed: {}", self.host, self.message)
+        }
+    }
 }
 
 impl std::fmt::Display for Error {
@@ -361,6 +676,10 @@ impl From<Error> for Response<Body> {
                 StatusCode::INTERNAL_SERVER_ERROR.into_response()
             }
             Error::NotFound => StatusCode::NOT_FOUND.into_response(),
+            Error::UpstreamError(e) => {
+                eprintln!("upstream error: {}", e);
+                (StatusCode::BAD_GATEWAY, e.to_string()).into_response()
+            }
         }
     }
 }
@@ -16,6 +16,13 @@ pub struct RecentTrack {
     pub user_score: Option<i32>,
 }
 
+/// Result of a streaming history import
+#[derive(Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct ImportSummary {
+    pub plays: usize,
+    pub new_items: usize,
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 pub struct Playlists {
     pub items: Vec<Playlist>,
@@ -26,4 +33,7 @@ pub struct Playlist {
     pub id: String,
     pub name: String,
     pub external_urls: HashMap<String, String>,
+    /// Changes whenever the tracks of the playlist change
+    #[serde(default)]
+    pub snapshot_id: Option<String>,
 }
@@ -155,6 +155,209 @@ pub struct SqlSessionClient {
     pub path: &'static str,
 }
 
+impl SqlSessionClient {
+    /// Create the tables that were added after the list and item tables
+    pub fn create_tables(&self) -> Result<(), Error> {
+        let conn = Connection::open(self.path)?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS _dashboard (
+                id TEXT NOT NULL,
+                user_id TEXT NOT NULL,
+                name TEXT NOT NULL,
+                panels TEXT NOT NULL,
+                public BOOLEAN NOT NULL,
+                PRIMARY KEY (id, user_id)
+            );
+            CREATE TABLE IF NOT EXISTS _history (
+                user_id TEXT NOT NULL,
+                list_id TEXT NOT NULL,
+                item_id TEXT NOT NULL,
+                score INTEGER NOT NULL,
+                timestamp INTEGER NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS history_item ON _history (user_id, item_id, timestamp);
+            CREATE TABLE IF NOT EXISTS _play (
+                user_id TEXT NOT NULL,
+                item_id TEXT NOT NULL,
+                timestamp INTEGER NOT NULL,
+                ms_played INTEGER NOT NULL,
+                skipped BOOLEAN NOT NULL,
+                PRIMARY KEY (user_id, item_id, timestamp)
+            );
+            CREATE TABLE IF NOT EXISTS _setlist_override (
+                user_id TEXT NOT NULL,
+                artist_mbid TEXT NOT NULL,
+                song TEXT NOT NULL,
+                item_id TEXT NOT NULL,
+                PRIMARY KEY (user_id, artist_mbid, song)
+            );
+            CREATE TABLE IF NOT EXISTS _seeded_game (
+                user_id TEXT NOT NULL,
+                list_id TEXT NOT NULL,
+                game_id TEXT NOT NULL,
+                PRIMARY KEY (user_id, list_id, game_id)
+            );",
+        )?;
+        // Columns that were added to the list table for background refreshes
+        let columns = conn
+            .prepare("SELECT name FROM pragma_table_info('_list')")?
+            .query_map([], |row| row.get::<_, String>(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+        if !columns.is_empty() {
+            for (column, column_type) in [
+                ("refresh", "TEXT"),
+                ("last_refreshed", "INTEGER"),
+                ("refresh_error", "TEXT"),
+            ] {
+                if !columns.iter().any(|c| c == column) {
+                    conn.execute(
+                        &format!("ALTER TABLE _list ADD COLUMN {column} {column_type}"),
+                        [],
+                    )?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Create many documents in a single transaction
+    ///
+    /// Used for bulk imports where writing each document separately would be too slow.
+    pub fn create_documents<T: Serialize>(
+        &self,
+        collection_name: &str,
+        documents: &[T],
+    ) -> Result<(), Error> {
+        let mut conn = Connection::open(self.path)?;
+        let tx = conn.transaction()?;
+        {
+            let mut stmt = tx.prepare(get_insert_stmt(collection_name, false))?;
+            for document in documents {
+                stmt.execute(
+                    serde_rusqlite::to_params_named(document)?
+                        .to_slice()
+                        .as_slice(),
+                )?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// IDs of the games whose results were already played as matches in the list
+    pub fn get_seeded_games(&self, user_id: &UserId, list_id: &str) -> Result<Vec<String>, Error> {
+        let conn = Connection::open(self.path)?;
+        let mut stmt =
+            conn.prepare("SELECT game_id FROM _seeded_game WHERE user_id = ?1 AND list_id = ?2")?;
+        let ids = stmt
+            .query_map([user_id.0.as_str(), list_id], |row| row.get(0))?
+            .collect::<Result<_, _>>()?;
+        Ok(ids)
+    }
+
+    /// Save the list, items, score changes and games of seeded matches in a single transaction
+    pub fn save_seeded_matches<L: Serialize, I: Serialize, H: Serialize, G: Serialize>(
+        &self,
+        list: &L,
+        items: &[I],
+        changes: &[H],
+        games: &[G],
+    ) -> Result<(), Error> {
+        let mut conn = Connection::open(self.path)?;
+        let tx = conn.transaction()?;
+        {
+            let (stmt, fields) = get_update_stmt("list");
+            tx.execute(
+                stmt,
+                serde_rusqlite::to_params_named_with_fields(list, fields)?
+                    .to_slice()
+                    .as_slice(),
+            )?;
+            let (stmt, fields) = get_update_stmt("item");
+            let mut stmt = tx.prepare(stmt)?;
+            for item in items {
+                stmt.execute(
+                    serde_rusqlite::to_params_named_with_fields(item, fields)?
+                        .to_slice()
+                        .as_slice(),
+                )?;
+            }
+            let mut stmt = tx.prepare(get_insert_stmt("history", false))?;
+            for change in changes {
+                stmt.execute(
+                    serde_rusqlite::to_params_named(change)?
+                        .to_slice()
+                        .as_slice(),
+                )?;
+            }
+            let mut stmt = tx.prepare(get_insert_stmt("seeded_game", false))?;
+            for game in games {
+                stmt.execute(serde_rusqlite::to_params_named(game)?.to_slice().as_slice())?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Replace a duplicate with the merged item in a single transaction
+    ///
+    /// The lists are saved, the score history, plays and setlist overrides of the duplicate are
+    /// pointed at the item, the item is saved with its metadata and the duplicate is deleted.
+    pub fn merge_items<L: Serialize, I: Serialize>(
+        &self,
+        user_id: &UserId,
+        lists: &[L],
+        item: &I,
+        item_id: &str,
+        duplicate_id: &str,
+    ) -> Result<(), Error> {
+        let mut conn = Connection::open(self.path)?;
+        let tx = conn.transaction()?;
+        {
+            let (stmt, fields) = get_update_stmt("list");
+            let mut stmt = tx.prepare(stmt)?;
+            for list in lists {
+                stmt.execute(
+                    serde_rusqlite::to_params_named_with_fields(list, fields)?
+                        .to_slice()
+                        .as_slice(),
+                )?;
+            }
+        }
+        let params = [user_id.0.as_str(), duplicate_id, item_id];
+        tx.execute(
+            "UPDATE _history SET item_id = ?3 WHERE user_id = ?1 AND item_id = ?2",
+            params,
+        )?;
+        // Plays at the same time as a play of the other item are the same play
+        tx.execute(
+            "UPDATE OR IGNORE _play SET item_id = ?3 WHERE user_id = ?1 AND item_id = ?2",
+            params,
+        )?;
+        tx.execute(
+            "DELETE FROM _play WHERE user_id = ?1 AND item_id = ?2",
+            [user_id.0.as_str(), duplicate_id],
+        )?;
+        tx.execute(
+            "UPDATE _setlist_override SET item_id = ?3 WHERE user_id = ?1 AND item_id = ?2",
+            params,
+        )?;
+        let (stmt, fields) = get_update_stmt("item_metadata");
+        tx.execute(
+            stmt,
+            serde_rusqlite::to_params_named_with_fields(item, fields)?
+                .to_slice()
+                .as_slice(),
+        )?;
+        tx.execute(
+            "DELETE FROM _item WHERE user_id = ?1 AND id = ?2",
+            [user_id.0.as_str(), duplicate_id],
+        )?;
+        tx.commit()?;
+        Ok(())
+    }
+}
+
 #[async_trait]
 impl SessionClient for SqlSessionClient {
     async fn get_document<T>(&self, builder: GetDocumentBuilder) -> Result<Option<T>, Error>
@@ -166,14 +369,16 @@ impl SessionClient for SqlSessionClient {
             View::User(user_id) => {
                 conn.execute_batch(&format!(
                     "CREATE TEMP VIEW list AS SELECT * FROM _list WHERE user_id = '{user_id}';
-                        CREATE TEMP VIEW item AS SELECT * FROM _item WHERE user_id = '{user_id}';",
+                        CREATE TEMP VIEW item AS SELECT * FROM _item WHERE user_id = '{user_id}';
+                        CREATE TEMP VIEW dashboard AS SELECT * FROM _dashboard WHERE user_id = '{user_id}';",
                     user_id = user_id.0
                 ))?;
             }
             View::Public => {
                 conn.execute_batch(
                     "CREATE TEMP VIEW list AS SELECT * FROM _list WHERE public = true;
-                    CREATE TEMP VIEW item AS SELECT _item.* FROM _list, json_each(_list.items) JOIN _item ON _item.id=json_each.value->>'id' WHERE public = true;",
+                    CREATE TEMP VIEW item AS SELECT _item.* FROM _list, json_each(_list.items) JOIN _item ON _item.id=json_each.value->>'id' WHERE public = true;
+                    CREATE TEMP VIEW dashboard AS SELECT * FROM _dashboard WHERE public = true;",
                 )?;
             }
             _ => return Err(Error::internal_error("unsupported view")),
@@ -207,6 +412,9 @@ impl SessionClient for SqlSessionClient {
         if query.contains("_item") {
             return Err(Error::client_error("no such table: _item"));
         }
+        if let Some(table) = internal_table(&query) {
+            return Err(Error::client_error(format!("no such table: {table}")));
+        }
         let params: Vec<_> = builder
             .query
             .parameters
@@ -237,7 +445,8 @@ impl SessionClient for SqlSessionClient {
             View::User(user_id) => {
                 conn.execute_batch(&format!(
                     "CREATE TEMP VIEW list AS SELECT * FROM _list WHERE user_id = '{user_id}';
-                        CREATE TEMP VIEW item AS SELECT * FROM _item WHERE user_id = '{user_id}';",
+                        CREATE TEMP VIEW item AS SELECT * FROM _item WHERE user_id = '{user_id}';
+                        CREATE TEMP VIEW dashboard AS SELECT * FROM _dashboard WHERE user_id = '{user_id}';",
                     user_id = user_id.0
                 ))?;
             }
@@ -257,7 +466,8 @@ impl SessionClient for SqlSessionClient {
             View::Public => {
                 conn.execute_batch(
                     "CREATE TEMP VIEW list AS SELECT * FROM _list WHERE public = true;
-                    CREATE TEMP VIEW item AS SELECT _item.* FROM _list, json_each(_list.items) JOIN _item ON _item.id=json_each.value->>'id' WHERE public = true;",
+                    CREATE TEMP VIEW item AS SELECT _item.* FROM _list, json_each(_list.items) JOIN _item ON _item.id=json_each.value->>'id' WHERE public = true;
+                    CREATE TEMP VIEW dashboard AS SELECT * FROM _dashboard WHERE public = true;",
                 )?;
             }
             View::PublicList(ids) => {
@@ -273,6 +483,12 @@ impl SessionClient for SqlSessionClient {
                 )?;
             }
         }
+        // Score changes, plays and setlist overrides are visible for the items in the view
+        conn.execute_batch(
+            "CREATE TEMP VIEW history AS SELECT _history.* FROM _history JOIN item ON _history.item_id = item.id AND _history.user_id = item.user_id;
+            CREATE TEMP VIEW play AS SELECT _play.* FROM _play JOIN item ON _play.item_id = item.id AND _play.user_id = item.user_id;
+            CREATE TEMP VIEW setlist_override AS SELECT _setlist_override.* FROM _setlist_override JOIN item ON _setlist_override.item_id = item.id AND _setlist_override.user_id = item.user_id;",
+        )?;
         let mut stmt = conn.prepare(&query)?;
         let query = stmt.query(rusqlite::params_from_iter(params))?;
         serde_rusqlite::from_rows(query)
@@ -318,10 +534,20 @@ impl SessionClient for SqlSessionClient {
 fn get_insert_stmt(collection_name: &str, is_upsert: bool) -> &str {
     match (collection_name, is_upsert) {
         ("item", false) => "INSERT INTO _item (id, user_id, type, name, iframe, rating, user_score, user_wins, user_losses, metadata, hidden) VALUES (:id, :user_id, :type, :name, :iframe, :rating, :user_score, :user_wins, :user_losses, :metadata, :hidden)",
-        ("list", false) => "INSERT INTO _list (id, user_id, mode, name, sources, iframe, items, favorite, query, public) VALUES (:id, :user_id, :mode, :name, :sources, :iframe, :items, :favorite, :query, :public)",
-        // is_upsert is currently only used to reset demo lists and items
+        ("list", false) => "INSERT INTO _list (id, user_id, mode, name, sources, iframe, items, favorite, query, public, refresh, last_refreshed, refresh_error) VALUES (:id, :user_id, :mode, :name, :sources, :iframe, :items, :favorite, :query, :public, :refresh, :last_refreshed, :refresh_error)",
+        ("history", false) => "INSERT INTO _history (user_id, list_id, item_id, score, timestamp) VALUES (:user_id, :list_id, :item_id, :score, :timestamp)",
+        // Plays are unique per timestamp so importing the same file again is a no-op
+        ("play", false) => "INSERT OR IGNORE INTO _play (user_id, item_id, timestamp, ms_played, skipped) VALUES (:user_id, :item_id, :timestamp, :ms_played, :skipped)",
+        // Games are only seeded once per list
+        ("seeded_game", false) => "INSERT OR IGNORE INTO _seeded_game (user_id, list_id, game_id) VALUES (:user_id, :list_id, :game_id)",
+        ("dashboard", false) => "INSERT INTO _dashboard (id, user_id, name, panels, public) VALUES (:id, :user_id, :name, :panels, :public)",
+        // Upserting items and lists resets the demo lists, other upserts keep the scores
         ("item", true) => "INSERT INTO _item (id, user_id, type, name, iframe, rating, user_score, user_wins, user_losses, metadata, hidden) VALUES (:id, :user_id, :type, :name, :iframe, :rating, :user_score, :user_wins, :user_losses, :metadata, :hidden) ON CONFLICT(id, user_id) DO UPDATE SET rating=excluded.rating, user_score=excluded.user_score, user_wins=excluded.user_wins, user_losses=excluded.user_losses",
-        ("list", true) => "INSERT INTO _list (id, user_id, mode, name, sources, iframe, items, favorite, query, public) VALUES (:id, :user_id, :mode, :name, :sources, :iframe, :items, :favorite, :query, :public) ON CONFLICT(id, user_id) DO UPDATE SET items=excluded.items, query=excluded.query, public=excluded.public",
+        // Refresh the source data of items without resetting their scores
+        ("item_metadata", true) => "INSERT INTO _item (id, user_id, type, name, iframe, rating, user_score, user_wins, user_losses, metadata, hidden) VALUES (:id, :user_id, :type, :name, :iframe, :rating, :user_score, :user_wins, :user_losses, :metadata, :hidden) ON CONFLICT(id, user_id) DO UPDATE SET name=excluded.name, iframe=excluded.iframe, rating=COALESCE(excluded.rating, rating), metadata=excluded.metadata",
+        // Pinning another track for the same song replaces the override
+        ("setlist_override", true) => "INSERT INTO _setlist_override (user_id, artist_mbid, song, item_id) VALUES (:user_id, :artist_mbid, :song, :item_id) ON CONFLICT(user_id, artist_mbid, song) DO UPDATE SET item_id=excluded.item_id",
+        ("list", true) => "INSERT INTO _list (id, user_id, mode, name, sources, iframe, items, favorite, query, public, refresh, last_refreshed, refresh_error) VALUES (:id, :user_id, :mode, :name, :sources, :iframe, :items, :favorite, :query, :public, :refresh, :last_refreshed, :refresh_error) ON CONFLICT(id, user_id) DO UPDATE SET items=excluded.items, query=excluded.query, public=excluded.public",
         _ => unreachable!()
     }
 }
@@ -329,7 +555,12 @@ fn get_insert_stmt(collection_name: &str, is_upsert: bool) -> &str {
 fn get_update_stmt(collection_name: &str) -> (&str, &[&str]) {
     match collection_name {
         "item" => ("UPDATE _item SET rating = :rating, user_score = :user_score, user_wins = :user_wins, user_losses = :user_losses WHERE id = :id AND user_id = :user_id", &["id", "user_id", "rating", "user_score", "user_wins", "user_losses"]),
-        "list" => ("UPDATE _list SET mode = :mode, name = :name, sources = :sources, iframe = :iframe, items = :items, favorite = :favorite, query = :query, public = :public WHERE id = :id AND user_id = :user_id", &["id", "user_id", "mode", "name", "sources", "iframe", "items", "favorite", "query", "public"]),
+        // Merged items also combine their metadata
+        "item_metadata" => ("UPDATE _item SET rating = :rating, user_score = :user_score, user_wins = :user_wins, user_losses = :user_losses, metadata = :metadata WHERE id = :id AND user_id = :user_id", &["id", "user_id", "rating", "user_score", "user_wins", "user_losses", "metadata"]),
+        "dashboard" => ("UPDATE _dashboard SET name = :name, panels = :panels, public = :public WHERE id = :id AND user_id = :user_id", &["id", "user_id", "name", "panels", "public"]),
+        "list" => ("UPDATE _list SET mode = :mode, name = :name, sources = :sources, iframe = :iframe, items = :items, favorite = :favorite, query = :query, public = :public, refresh = :refresh, last_refreshed = :last_refreshed, refresh_error = :refresh_error WHERE id = :id AND user_id = :user_id", &["id", "user_id", "mode", "name", "sources", "iframe", "items", "favorite", "query", "public", "refresh", "last_refreshed", "refresh_error"]),
+        // Only the credentials change after the user is created
+        "user" => ("UPDATE user SET spotify_credentials = :spotify_credentials WHERE id = :id", &["id", "spotify_credentials"]),
         _ => unreachable!()
     }
 }
@@ -362,3 +593,227 @@ pub struct DeleteDocumentBuilder {
     pub document_name: String,
     pub partition_key: UserId,
 }
+
+/// Internal table other than _list and _item that a user query names
+///
+/// Plain substring checks would reject metadata fields like last_played, so the query is split
+/// into identifiers. SQLite table names are case insensitive.
+fn internal_table(query: &str) -> Option<&'static str> {
+    const TABLES: [&str; 5] = [
+        "_dashboard",
+        "_history",
+        "_play",
+        "_setlist_override",
+        "_seeded_game",
+    ];
+    query
+        .split(|c: char| !c.is_alphanumeric() && c != '_')
+        .find_map(|s| TABLES.into_iter().find(|t| s.eq_ignore_ascii_case(t)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::SqlSessionClient;
+    use crate::UserId;
+    use rusqlite::Connection;
+    use serde_json::{json, Value};
+
+    /// Database in a temporary file with the list and item tables
+    fn test_client(name: &str) -> SqlSessionClient {
+        let path = std::env::temp_dir().join(format!("zeroflops-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let path: &'static str = Box::leak(path.to_str().unwrap().to_owned().into_boxed_str());
+        Connection::open(path)
+            .unwrap()
+            .execute_batch(
+                "CREATE TABLE _list (id TEXT NOT NULL, user_id TEXT NOT NULL, mode TEXT NOT NULL, name TEXT NOT NULL, sources TEXT NOT NULL, iframe TEXT, items TEXT NOT NULL, favorite BOOLEAN NOT NULL, query TEXT NOT NULL, public BOOLEAN NOT NULL, refresh TEXT, last_refreshed INTEGER, refresh_error TEXT, PRIMARY KEY (id, user_id));
+                CREATE TABLE _item (id TEXT NOT NULL, user_id TEXT NOT NULL, type TEXT NOT NULL, name TEXT NOT NULL, iframe TEXT, rating INTEGER, user_score INTEGER NOT NULL, user_wins INTEGER NOT NULL, user_losses INTEGER NOT NULL, metadata TEXT NOT NULL, hidden BOOLEAN NOT NULL, PRIMARY KEY (id, user_id));",
+            )
+            .unwrap();
+        let client = SqlSessionClient { path };
+        client.create_tables().unwrap();
+        client
+    }
+
+    fn item(id: &str, score: i32, wins: i32, metadata: Value) -> Value {
+        json!({
+            "id": id,
+            "user_id": "user",
+            "type": "track",
+            "name": id,
+            "iframe": null,
+            "rating": null,
+            "user_score": score,
+            "user_wins": wins,
+            "user_losses": 0,
+            "metadata": metadata.to_string(),
+            "hidden": false,
+        })
+    }
+
+    fn list(items: Value) -> Value {
+        json!({
+            "id": "list",
+            "user_id": "user",
+            "mode": r#"{"User":null}"#,
+            "name": "",
+            "sources": "[]",
+            "iframe": null,
+            "items": items.to_string(),
+            "favorite": false,
+            "query": "SELECT name, user_score FROM item",
+            "public": false,
+            "refresh": null,
+            "last_refreshed": null,
+            "refresh_error": null,
+        })
+    }
+
+    #[test]
+    fn test_merge_items() {
+        let client = test_client("merge");
+        client
+            .create_documents(
+                "item",
+                &[
+                    item("a", 1600, 1, json!({"album": "A"})),
+                    item("b", 1400, 1, json!({"note": "live"})),
+                ],
+            )
+            .unwrap();
+        client
+            .create_documents("list", &[list(json!([{"id": "b"}]))])
+            .unwrap();
+        client
+            .create_documents(
+                "history",
+                &[json!({"user_id": "user", "list_id": "list", "item_id": "b", "score": 1400, "timestamp": 1})],
+            )
+            .unwrap();
+
+        let mut merged = item("a", 1500, 2, json!({"album": "A", "note": "live"}));
+        merged["rating"] = json!(5);
+        client
+            .merge_items(
+                &UserId(String::from("user")),
+                &[list(json!([{"id": "a"}]))],
+                &merged,
+                "a",
+                "b",
+            )
+            .unwrap();
+
+        let conn = Connection::open(client.path).unwrap();
+        let items: Vec<(String, Option<i32>, i32, i32, String)> = conn
+            .prepare("SELECT id, rating, user_score, user_wins, metadata FROM _item")
+            .unwrap()
+            .query_map([], |row| {
+                Ok((
+                    row.get(0)?,
+                    row.get(1)?,
+                    row.get(2)?,
+                    row.get(3)?,
+                    row.get(4)?,
+                ))
+            })
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(
+            items,
+            vec![(
+                String::from("a"),
+                Some(5),
+                1500,
+                2,
+                json!({"album": "A", "note": "live"}).to_string()
+            )]
+        );
+        let list_items: String = conn
+            .query_row("SELECT items FROM _list", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(list_items, json!([{"id": "a"}]).to_string());
+        let history: String = conn
+            .query_row("SELECT item_id FROM _history", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(history, "a");
+        std::fs::remove_file(client.path).unwrap();
+    }
+
+    #[test]
+    fn test_save_seeded_matches() {
+        let client = test_client("seed");
+        let user_id = UserId(String::from("user"));
+        client
+            .create_documents(
+                "item",
+                &[item("a", 1500, 0, json!({})), item("b", 1500, 0, json!({}))],
+            )
+            .unwrap();
+        client
+            .create_documents("list", &[list(json!([{"id": "a"}, {"id": "b"}]))])
+            .unwrap();
+        let game = json!({"user_id": "user", "list_id": "list", "game_id": "1"});
+        client
+            .save_seeded_matches(
+                &list(json!([{"id": "a", "score": 1516}, {"id": "b", "score": 1484}])),
+                &[item("a", 1516, 1, json!({}))],
+                &[json!({"user_id": "user", "list_id": "list", "item_id": "a", "score": 1516, "timestamp": 1})],
+                std::slice::from_ref(&game),
+            )
+            .unwrap();
+        assert_eq!(
+            client.get_seeded_games(&user_id, "list").unwrap(),
+            vec![String::from("1")]
+        );
+        assert!(client
+            .get_seeded_games(&user_id, "other")
+            .unwrap()
+            .is_empty());
+        // Saving a game again doesn't duplicate it
+        client
+            .save_seeded_matches(
+                &list(json!([])),
+                &Vec::<Value>::new(),
+                &Vec::<Value>::new(),
+                &[game],
+            )
+            .unwrap();
+        assert_eq!(client.get_seeded_games(&user_id, "list").unwrap().len(), 1);
+
+        let conn = Connection::open(client.path).unwrap();
+        let score: i32 = conn
+            .query_row("SELECT user_score FROM _item WHERE id = 'a'", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(score, 1516);
+        let changes: i64 = conn
+            .query_row("SELECT COUNT(*) FROM _history", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(changes, 1);
+        std::fs::remove_file(client.path).unwrap();
+    }
+
+    #[test]
+    fn test_internal_table() {
+        assert_eq!(
+            super::internal_table("SELECT * FROM _history"),
+            Some("_history")
+        );
+        assert_eq!(
+            super::internal_table("SELECT * FROM item, \"_Seeded_Game\""),
+            Some("_seeded_game")
+        );
+        assert_eq!(
+            super::internal_table("SELECT * FROM main._play"),
+            Some("_play")
+        );
+        assert_eq!(
+            super::internal_table(
+                "SELECT name, last_played, user_history, my_dashboard, setlist_override FROM item"
+            ),
+            None
+        );
+    }
+}
@@ -0,0 +1,59 @@
+//! Base URLs and keys of the external services
+//!
+//! Each URL can be overridden with an environment variable, or every URL at once with
+//! [set_base_url], e.g. to use the fake upstream server in tests.
+use std::sync::OnceLock;
+
+static BASE_URL: OnceLock<String> = OnceLock::new();
+
+/// Send the requests to every service to one server instead of the environment variables
+///
+/// The base URL can only be set once.
+pub fn set_base_url(url: &str) {
+    let _ = BASE_URL.set(url.trim_end_matches('/').to_owned());
+}
+
+fn base_url(var: &str, default: &str) -> String {
+    if let Some(url) = BASE_URL.get() {
+        return url.clone();
+    }
+    std::env::var(var)
+        .map(|url| url.trim_end_matches('/').to_owned())
+        .unwrap_or_else(|_| default.to_owned())
+}
+
+/// API key or secret from the environment variable
+///
+/// The fake upstream server doesn't check keys so they are optional after [set_base_url].
+pub fn key(var: &str) -> String {
+    std::env::var(var).unwrap_or_else(|_| {
+        if BASE_URL.get().is_some() {
+            String::from("fake")
+        } else {
+            panic!("{var} is missing")
+        }
+    })
+}
+
+pub fn spotify_api() -> String {
+    base_url("SPOTIFY_API_URL", "https://api.spotify.com")
+}
+
+pub fn spotify_accounts() -> String {
+    base_url("SPOTIFY_ACCOUNTS_URL", "https://accounts.spotify.com")
+}
+
+pub fn setlist_api() -> String {
+    base_url("SETLIST_API_URL", "https://api.setlist.fm")
+}
+
+pub fn google_oauth() -> String {
+    base_url("GOOGLE_OAUTH_URL", "https://oauth2.googleapis.com")
+}
+
+pub fn google_userinfo() -> String {
+    base_url(
+        "GOOGLE_USERINFO_URL",
+        "https://openidconnect.googleapis.com",
+    )
+}